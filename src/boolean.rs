@@ -0,0 +1,282 @@
+use i_overlay::core::fill_rule::FillRule;
+use i_overlay::core::overlay_rule::OverlayRule;
+use i_overlay::float::single::SingleFloatOverlay;
+use log::info;
+
+use crate::edge::Edge;
+use crate::error::ConversionError;
+use crate::shapes::{close_ring, signed_area, MIN_AREA};
+use crate::tree::Node;
+use crate::vert::Vert;
+
+/// Ways to combine two sets of polygons
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BooleanOp {
+    /// the area in either set
+    Union,
+    /// the area in both sets
+    Intersection,
+    /// the area in the first set and not the second
+    Difference,
+    /// the area in only one of the sets
+    Xor,
+}
+
+impl BooleanOp {
+    fn rule(self) -> OverlayRule {
+        match self {
+            BooleanOp::Union => OverlayRule::Union,
+            BooleanOp::Intersection => OverlayRule::Intersect,
+            BooleanOp::Difference => OverlayRule::Difference,
+            BooleanOp::Xor => OverlayRule::Xor,
+        }
+    }
+}
+
+/// The points of a closed edge without the last one, counterclockwise for outlines and
+/// clockwise for holes, so the winding number is positive inside the filled area
+fn contour(edge: &Edge) -> Result<Option<Vec<[f64; 2]>>, ConversionError> {
+    if edge.len() < 2 || !edge.is_closed() {
+        return Err(ConversionError::DegenerateContour(format!(
+            "the edge starting at {:?} is not closed, so it has no inside to combine",
+            edge.front()
+        )));
+    }
+    let points: Vec<Vert> = edge.iter().take(edge.len() - 1).cloned().collect();
+    let area = signed_area(&points);
+    if area.abs() <= MIN_AREA {
+        return Ok(None);
+    }
+    let mut out: Vec<[f64; 2]> = points.iter().map(|v| [v.x, v.y]).collect();
+    if (area < 0.) != edge.is_hole() {
+        out.reverse();
+    }
+    Ok(Some(out))
+}
+
+fn contours<'a>(
+    edges: impl IntoIterator<Item = &'a Edge>,
+) -> Result<Vec<Vec<[f64; 2]>>, ConversionError> {
+    let mut out = vec![];
+    for edge in edges {
+        if let Some(c) = contour(edge)? {
+            out.push(c);
+        }
+    }
+    Ok(out)
+}
+
+/// every edge of the nodes and the nodes nested in them
+fn node_edges<'a>(nodes: &'a [Node], out: &mut Vec<&'a Edge>) {
+    for node in nodes {
+        out.push(&node.data);
+        node_edges(&node.children, out);
+    }
+}
+
+fn combine(
+    subject: Vec<&Edge>,
+    clip: Vec<&Edge>,
+    op: BooleanOp,
+) -> Result<Vec<Node>, ConversionError> {
+    // i_overlay winds around points the other way, as if y went down
+    let shapes = contours(subject)?.overlay(&contours(clip)?, op.rule(), FillRule::Negative);
+    let nodes: Vec<Node> = shapes
+        .into_iter()
+        .filter_map(|shape| {
+            // the first ring of a shape goes around it and the rest are its holes
+            let mut rings = shape.into_iter().map(|ring| {
+                let points: Vec<Vert> = ring.into_iter().map(|[x, y]| Vert::new(x, y)).collect();
+                (signed_area(&points).abs() > MIN_AREA).then_some(points)
+            });
+            let outline = close_ring(rings.next()??, false);
+            let children = rings
+                .flatten()
+                .map(|points| Node {
+                    data: close_ring(points, true),
+                    children: vec![],
+                })
+                .collect();
+            Some(Node {
+                data: outline,
+                children,
+            })
+        })
+        .collect();
+    info!("{:?} made {} polygons", op, nodes.len());
+    Ok(nodes)
+}
+
+/// Combine two sets of closed edges. Outlines can overlap other outlines of the same set,
+/// the filled area is everywhere inside more outlines than holes.
+/// The result is like what `sort_edges` gives, polygons with the holes right inside them,
+/// and islands inside the holes as polygons of their own.
+pub fn boolean_edges(
+    subject: &[Edge],
+    clip: &[Edge],
+    op: BooleanOp,
+) -> Result<Vec<Node>, ConversionError> {
+    combine(subject.iter().collect(), clip.iter().collect(), op)
+}
+
+/// Combine two sets of polygons, like the ones from `sort_edges`, see `boolean_edges`.
+/// Nested nodes and islands that are also polygons of their own both work.
+pub fn boolean(
+    subject: &[Node],
+    clip: &[Node],
+    op: BooleanOp,
+) -> Result<Vec<Node>, ConversionError> {
+    let (mut a, mut b) = (vec![], vec![]);
+    node_edges(subject, &mut a);
+    node_edges(clip, &mut b);
+    combine(a, b, op)
+}
+
+pub fn union(a: &[Node], b: &[Node]) -> Result<Vec<Node>, ConversionError> {
+    boolean(a, b, BooleanOp::Union)
+}
+
+pub fn intersection(a: &[Node], b: &[Node]) -> Result<Vec<Node>, ConversionError> {
+    boolean(a, b, BooleanOp::Intersection)
+}
+
+pub fn difference(a: &[Node], b: &[Node]) -> Result<Vec<Node>, ConversionError> {
+    boolean(a, b, BooleanOp::Difference)
+}
+
+pub fn xor(a: &[Node], b: &[Node]) -> Result<Vec<Node>, ConversionError> {
+    boolean(a, b, BooleanOp::Xor)
+}
+
+/// the outlines and holes of polygons, to draw or extrude them like any closed edges
+pub fn into_edges(nodes: Vec<Node>) -> Vec<Edge> {
+    nodes
+        .into_iter()
+        .flat_map(|node| std::iter::once(node.data).chain(into_edges(node.children)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::constants::DEFAULT_THRESHOLD_VALUE;
+    use crate::edge_file;
+    use crate::ribbon::EdgeProfile;
+    use crate::shapes::rectangle;
+    use crate::tree::sort_edges;
+    use crate::triangle::{extrude_nodes_with_profile, nodes_to_triangles};
+    use crate::util::testing::donut;
+    use std::error::Error;
+
+    fn area(edge: &Edge) -> f64 {
+        signed_area(&edge.iter().cloned().collect::<Vec<Vert>>()).abs() / 2.
+    }
+
+    /// the filled area of polygons, with their holes taken out
+    fn filled_area(nodes: &[Node]) -> f64 {
+        nodes
+            .iter()
+            .map(|n| area(&n.data) - n.children.iter().map(|c| area(&c.data)).sum::<f64>())
+            .sum()
+    }
+
+    /// the area covered by the triangles, which earcut only gets right for good polygons
+    fn triangle_area(nodes: Vec<Node>) -> Result<f64, ConversionError> {
+        Ok(nodes_to_triangles(nodes)?
+            .chunks(6)
+            .map(|t| ((t[2] - t[0]) * (t[5] - t[1]) - (t[4] - t[0]) * (t[3] - t[1])).abs() / 2.)
+            .sum())
+    }
+
+    fn squares() -> (Vec<Node>, Vec<Node>) {
+        let a = vec![Node {
+            data: rectangle(0., 0., 10., 10., false),
+            children: vec![],
+        }];
+        let b = vec![Node {
+            data: rectangle(5., 5., 10., 10., false),
+            children: vec![],
+        }];
+        (a, b)
+    }
+
+    #[test]
+    fn test_overlapping_squares() -> Result<(), Box<dyn Error>> {
+        let (a, b) = squares();
+        for (nodes, expected, polygons) in [
+            (union(&a, &b)?, 175., 1),
+            (intersection(&a, &b)?, 25., 1),
+            (difference(&a, &b)?, 75., 1),
+            (xor(&a, &b)?, 150., 2),
+        ] {
+            assert_eq!(nodes.len(), polygons);
+            assert!((filled_area(&nodes) - expected).abs() < 1e-6, "{:?}", nodes);
+            for node in nodes.iter() {
+                assert!(!node.data.is_hole());
+            }
+            assert!((triangle_area(nodes)? - expected).abs() < 1e-6);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_cut_out_a_hole() -> Result<(), Box<dyn Error>> {
+        let (a, _) = squares();
+        let inside = rectangle(2., 2., 3., 3., false);
+        let nodes = boolean(&a, &sort_edges(vec![inside])?, BooleanOp::Difference)?;
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].children.len(), 1);
+        assert!(nodes[0].children[0].data.is_hole());
+        assert!((filled_area(&nodes) - 91.).abs() < 1e-6);
+        // the holes of the donut stay holes, and the square in its hole is filled again
+        let mut edges = donut(10., 2., 0., 0.);
+        edges.push(rectangle(4., 4., 2., 2., false));
+        let nodes = boolean_edges(&edges, &[], BooleanOp::Union)?;
+        assert_eq!(nodes.len(), 2);
+        assert!((filled_area(&nodes) - (100. - 36. + 4.)).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_outlines_in_the_same_set_overlap() -> Result<(), Box<dyn Error>> {
+        let (a, b) = squares();
+        let both = [a, b].concat();
+        let nodes = union(&both, &[])?;
+        assert_eq!(nodes.len(), 1);
+        assert!((filled_area(&nodes) - 175.).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_traced_image_with_a_plate() -> Result<(), Box<dyn Error>> {
+        let traced = sort_edges(
+            edge_file(DEFAULT_THRESHOLD_VALUE, "./images/stick-figure.png")?.closed_edges,
+        )?;
+        let traced_area = filled_area(&traced);
+        // a plate bigger than the image covers all of it
+        let plate = sort_edges(vec![rectangle(-1., -1., 1000., 1000., false)])?;
+        let inside = intersection(&traced, &plate)?;
+        assert!((filled_area(&inside) - traced_area).abs() < 1e-3);
+        assert!(difference(&traced, &plate)?.is_empty());
+        let cut = difference(&plate, &traced)?;
+        assert!((filled_area(&cut) - (1000. * 1000. - traced_area)).abs() < 1e-3);
+        extrude_nodes_with_profile(cut, 1., EdgeProfile::Vertical)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_edges_fail() {
+        let open = Edge::from([Vert::new(0., 0.), Vert::new(1., 0.), Vert::new(1., 1.)]);
+        assert!(boolean_edges(&[open], &[], BooleanOp::Union).is_err());
+    }
+
+    #[test]
+    fn test_into_edges() -> Result<(), Box<dyn Error>> {
+        let (a, _) = squares();
+        let nodes = difference(&a, &sort_edges(vec![rectangle(2., 2., 3., 3., false)])?)?;
+        let edges = into_edges(nodes);
+        assert_eq!(edges.len(), 2);
+        assert_eq!(edges.iter().filter(|e| e.is_hole()).count(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+
+use crate::edge::Edge;
+use crate::error::ConversionError;
+use crate::ribbon::EdgeProfile;
+use crate::shapes::circle;
+use crate::triangle::{extrude_edges_with_profile, translate_triangles};
+
+/// number of sides used for the round dots
+static DOT_SEGMENTS: usize = 16;
+
+static NUMBER_SIGN: u8 = 0b111100;
+static CAPITAL_SIGN: u8 = 0b100000;
+/// ends a number when it is followed by one of the letters a to j
+static LETTER_SIGN: u8 = 0b110000;
+
+/// Sizes of braille cells, in millimeters.
+/// The defaults are the usual sizes for English braille signs.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BrailleDimensions {
+    pub dot_diameter: f64,
+    pub dot_height: f64,
+    /// distance between the centers of dots in a cell
+    pub dot_spacing: f64,
+    /// distance between the centers of the same dot in neighboring cells
+    pub cell_spacing: f64,
+    /// distance between the centers of the same dot in cells on neighboring lines
+    pub line_spacing: f64,
+}
+
+impl Default for BrailleDimensions {
+    fn default() -> Self {
+        Self {
+            dot_diameter: 1.5,
+            dot_height: 0.6,
+            dot_spacing: 2.5,
+            cell_spacing: 6.0,
+            line_spacing: 10.0,
+        }
+    }
+}
+
+impl BrailleDimensions {
+    /// width of a line of `n` cells, from the edge of the first dot to the edge of the last
+    pub fn width(&self, n: usize) -> f64 {
+        if n == 0 {
+            return 0.;
+        }
+        (n - 1) as f64 * self.cell_spacing + self.dot_spacing + self.dot_diameter
+    }
+
+    /// height of a single line of cells
+    pub fn height(&self) -> f64 {
+        2. * self.dot_spacing + self.dot_diameter
+    }
+}
+
+/// dots 1 to 6 of a letter, bit 0 is dot 1
+fn letter_dots(c: char) -> Option<u8> {
+    let dots: &[u8] = match c {
+        'a' => &[1],
+        'b' => &[1, 2],
+        'c' => &[1, 4],
+        'd' => &[1, 4, 5],
+        'e' => &[1, 5],
+        'f' => &[1, 2, 4],
+        'g' => &[1, 2, 4, 5],
+        'h' => &[1, 2, 5],
+        'i' => &[2, 4],
+        'j' => &[2, 4, 5],
+        'k' => &[1, 3],
+        'l' => &[1, 2, 3],
+        'm' => &[1, 3, 4],
+        'n' => &[1, 3, 4, 5],
+        'o' => &[1, 3, 5],
+        'p' => &[1, 2, 3, 4],
+        'q' => &[1, 2, 3, 4, 5],
+        'r' => &[1, 2, 3, 5],
+        's' => &[2, 3, 4],
+        't' => &[2, 3, 4, 5],
+        'u' => &[1, 3, 6],
+        'v' => &[1, 2, 3, 6],
+        'w' => &[2, 4, 5, 6],
+        'x' => &[1, 3, 4, 6],
+        'y' => &[1, 3, 4, 5, 6],
+        'z' => &[1, 3, 5, 6],
+        ',' => &[2],
+        ';' => &[2, 3],
+        ':' => &[2, 5],
+        '.' => &[2, 5, 6],
+        '!' => &[2, 3, 5],
+        '?' => &[2, 3, 6],
+        '\'' => &[3],
+        '-' => &[3, 6],
+        _ => return None,
+    };
+    Some(dots.iter().fold(0, |acc, d| acc | 1 << (d - 1)))
+}
+
+/// digits are written with the letters a to j after a number sign
+fn digit_dots(c: char) -> Option<u8> {
+    let letter = match c {
+        '0' => 'j',
+        '1'..='9' => (b'a' + (c as u8 - b'1')) as char,
+        _ => return None,
+    };
+    letter_dots(letter)
+}
+
+/// Translate text into uncontracted (grade 1) braille cells.
+/// Each cell is a bit mask of dots 1 to 6, with dot 1 in the lowest bit.
+/// Characters without a braille sign are left out.
+pub fn braille_cells(text: &str) -> Vec<u8> {
+    let mut out = vec![];
+    let mut in_number = false;
+    for c in text.chars() {
+        if let Some(dots) = digit_dots(c) {
+            if !in_number {
+                out.push(NUMBER_SIGN);
+                in_number = true;
+            }
+            out.push(dots);
+            continue;
+        }
+        // a decimal point or comma inside a number keeps the number going
+        if in_number && (c == '.' || c == ',') {
+            out.extend(letter_dots(c));
+            continue;
+        }
+        if c == ' ' {
+            in_number = false;
+            out.push(0);
+            continue;
+        }
+        let lower = c.to_ascii_lowercase();
+        if let Some(dots) = letter_dots(lower) {
+            if in_number && ('a'..='j').contains(&lower) {
+                out.push(LETTER_SIGN);
+            }
+            in_number = false;
+            if c.is_ascii_uppercase() {
+                out.push(CAPITAL_SIGN);
+            }
+            out.push(dots);
+        }
+    }
+    out
+}
+
+/// Create a closed edge for every dot of the text.
+/// (x, y) is the top left corner of the first cell, with y going up.
+pub fn braille_edges(text: &str, x: f64, y: f64, dims: &BrailleDimensions) -> Vec<Edge> {
+    let radius = dims.dot_diameter / 2.;
+    braille_cells(text)
+        .into_iter()
+        .enumerate()
+        .flat_map(|(i, cell)| {
+            let cell_x = x + radius + i as f64 * dims.cell_spacing;
+            let cell_y = y - radius;
+            (0..6)
+                .filter(move |dot| cell & (1 << dot) != 0)
+                .map(move |dot| {
+                    let column = (dot / 3) as f64;
+                    let row = (dot % 3) as f64;
+                    circle(
+                        cell_x + column * dims.dot_spacing,
+                        cell_y - row * dims.dot_spacing,
+                        radius,
+                        DOT_SEGMENTS,
+                        false,
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Domed braille dots for the text, sitting on a surface at height z
+pub fn braille_triangles(
+    text: &str,
+    x: f64,
+    y: f64,
+    z: f64,
+    dims: &BrailleDimensions,
+) -> Result<Vec<f64>, ConversionError> {
+    let mut tris = extrude_edges_with_profile(
+        braille_edges(text, x, y, dims),
+        dims.dot_height,
+        EdgeProfile::Dome,
+    )?;
+    translate_triangles(&mut tris, 0., 0., z);
+    Ok(tris)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn test_letters() {
+        assert_eq!(braille_cells("abc"), vec![0b1, 0b11, 0b1001]);
+        assert_eq!(braille_cells("z"), vec![0b110101]);
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(braille_cells("10"), vec![NUMBER_SIGN, 0b1, 0b11010]);
+        assert_eq!(
+            braille_cells("2.5"),
+            vec![NUMBER_SIGN, 0b11, 0b110010, 0b10001]
+        );
+        // a letter from a to j after a number needs a letter sign
+        assert_eq!(
+            braille_cells("1a"),
+            vec![NUMBER_SIGN, 0b1, LETTER_SIGN, 0b1]
+        );
+        assert_eq!(braille_cells("1 a"), vec![NUMBER_SIGN, 0b1, 0, 0b1]);
+    }
+
+    #[test]
+    fn test_capitals_and_unknown() {
+        assert_eq!(braille_cells("B~"), vec![CAPITAL_SIGN, 0b11]);
+    }
+
+    #[test]
+    fn test_braille_edges() {
+        let dims = BrailleDimensions::default();
+        // dots 1, 2, 4 and 5
+        let edges = braille_edges("g", 0., 0., &dims);
+        assert_eq!(edges.len(), 4);
+        // every dot is inside the cell
+        for e in edges.iter() {
+            for v in e.iter() {
+                assert!(v.x >= -1e-9 && v.x <= dims.width(1) + 1e-9);
+                assert!(v.y <= 1e-9 && v.y >= -dims.height() - 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_braille_triangles_height() -> Result<(), Box<dyn Error>> {
+        let dims = BrailleDimensions::default();
+        let tris = braille_triangles("a", 0., 0., 2., &dims)?;
+        let zs: Vec<f64> = tris.chunks(3).map(|v| v[2]).collect();
+        assert_eq!(zs.iter().cloned().fold(f64::MAX, f64::min), 2.);
+        assert_eq!(
+            zs.iter().cloned().fold(f64::MIN, f64::max),
+            2. + dims.dot_height
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,645 @@
+use image::{GrayImage, Luma};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::braille::{braille_cells, braille_edges, braille_triangles, BrailleDimensions};
+use crate::edge::Edge;
+use crate::error::ConversionError;
+use crate::legend::{Legend, LegendPlacement};
+use crate::line_style::LineStyle;
+use crate::raster::fill_edges;
+use crate::shapes::{circle, close_ring, rectangle, stroke};
+use crate::stl::write_stl_3d;
+use crate::texture::Texture;
+use crate::triangle::{extrude_edges, translate_triangles};
+use crate::vert::Vert;
+
+/// space around the chart and between the labels and the axes, in millimeters
+pub(crate) static MARGIN: f64 = 5.;
+pub(crate) static LABEL_GAP: f64 = 3.;
+/// length of the tick marks on the axes
+pub(crate) static TICK_LENGTH: f64 = 2.;
+/// part of each category's space taken up by its bars
+static BAR_GROUP_WIDTH: f64 = 0.7;
+
+/// The kind of chart to draw
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ChartKind {
+    #[default]
+    Bar,
+    Line,
+    Scatter,
+}
+
+/// One column of values
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub name: String,
+    pub values: Vec<f64>,
+}
+
+/// Data for a chart. The first column holds the categories or x values,
+/// every other column is a series.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartData {
+    pub x_name: String,
+    pub labels: Vec<String>,
+    pub series: Vec<Series>,
+}
+
+impl ChartData {
+    /// Read comma separated values with a header row. Quoted cells can not contain commas.
+    pub fn from_csv(text: &str) -> Result<Self, ConversionError> {
+        let mut rows = text
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                line.split(',')
+                    .map(|cell| cell.trim().trim_matches('"').to_string())
+                    .collect::<Vec<String>>()
+            });
+        let header = rows
+            .next()
+            .ok_or_else(|| ConversionError::Parse("the csv has no header row".to_string()))?;
+        if header.len() < 2 {
+            return Err(ConversionError::Parse(
+                "the csv needs a column of labels and at least one column of values".to_string(),
+            ));
+        }
+        let mut data = ChartData {
+            x_name: header[0].clone(),
+            labels: vec![],
+            series: header[1..]
+                .iter()
+                .map(|name| Series {
+                    name: name.clone(),
+                    values: vec![],
+                })
+                .collect(),
+        };
+        for (i, row) in rows.enumerate() {
+            if row.len() != header.len() {
+                return Err(ConversionError::Parse(format!(
+                    "row {} has {} cells, expected {}",
+                    i + 2,
+                    row.len(),
+                    header.len()
+                )));
+            }
+            data.labels.push(row[0].clone());
+            for (series, cell) in data.series.iter_mut().zip(row[1..].iter()) {
+                let value = cell.parse::<f64>().map_err(|_| {
+                    ConversionError::Parse(format!("row {}: '{}' is not a number", i + 2, cell))
+                })?;
+                series.values.push(value);
+            }
+        }
+        if data.labels.is_empty() {
+            return Err(ConversionError::Parse(
+                "the csv has no data rows".to_string(),
+            ));
+        }
+        Ok(data)
+    }
+
+    /// the labels as numbers, when every one of them is a number
+    fn numeric_x(&self) -> Option<Vec<f64>> {
+        self.labels.iter().map(|l| l.parse::<f64>().ok()).collect()
+    }
+}
+
+/// How to draw a chart. Sizes are in millimeters.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ChartOptions {
+    pub kind: ChartKind,
+    /// size of the area inside the axes
+    pub width: f64,
+    pub height: f64,
+    /// thickness of the plate under the chart
+    pub base_thickness: f64,
+    /// height of the axes and data above the plate, gridlines are half as high
+    pub relief_height: f64,
+    pub axis_width: f64,
+    /// width of the data lines, bar outlines and gridlines
+    pub line_width: f64,
+    pub grid: bool,
+    /// about how many steps to split the value axis into
+    pub ticks: usize,
+    pub braille: BrailleDimensions,
+    /// where the key to the bar textures or line styles goes
+    #[serde(default)]
+    pub legend: LegendPlacement,
+}
+
+impl Default for ChartOptions {
+    fn default() -> Self {
+        Self {
+            kind: ChartKind::Bar,
+            width: 150.,
+            height: 100.,
+            base_thickness: 2.,
+            relief_height: 1.,
+            axis_width: 1.5,
+            line_width: 1.,
+            grid: true,
+            ticks: 5,
+            braille: BrailleDimensions::default(),
+            legend: LegendPlacement::None,
+        }
+    }
+}
+
+/// patterns that tell the bars of each series apart, repeated when there are more series
+pub(crate) static BAR_TEXTURES: [Texture; 4] = [
+    Texture::Solid,
+    Texture::Stripes {
+        spacing: 3,
+        width: 1,
+    },
+    Texture::Dots {
+        spacing: 3,
+        radius: 1,
+    },
+    Texture::Grid {
+        spacing: 4,
+        width: 1,
+    },
+];
+
+/// shapes of the points of each series in a scatter chart, repeated when there are more series
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Marker {
+    Circle,
+    Square,
+    Triangle,
+    Diamond,
+}
+
+static MARKERS: [Marker; 4] = [
+    Marker::Circle,
+    Marker::Square,
+    Marker::Triangle,
+    Marker::Diamond,
+];
+
+/// A step between ticks of 1, 2 or 5 times a power of ten, giving about `ticks` steps
+fn nice_step(range: f64, ticks: usize) -> f64 {
+    let raw = range / ticks.max(1) as f64;
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let nice = match raw / magnitude {
+        f if f <= 1. => 1.,
+        f if f <= 2. => 2.,
+        f if f <= 5. => 5.,
+        _ => 10.,
+    };
+    nice * magnitude
+}
+
+/// the range covering the values, widened out to whole steps
+pub(crate) fn nice_range(
+    values: impl Iterator<Item = f64>,
+    ticks: usize,
+    include_zero: bool,
+) -> (f64, f64, f64) {
+    let (mut lo, mut hi) = values.fold((f64::MAX, f64::MIN), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    if include_zero {
+        lo = lo.min(0.);
+        hi = hi.max(0.);
+    }
+    if hi <= lo {
+        hi = lo + 1.;
+    }
+    let step = nice_step(hi - lo, ticks);
+    ((lo / step).floor() * step, (hi / step).ceil() * step, step)
+}
+
+pub(crate) fn tick_values(lo: f64, hi: f64, step: f64) -> Vec<f64> {
+    let n = ((hi - lo) / step).round() as usize;
+    (0..=n).map(|i| lo + i as f64 * step).collect()
+}
+
+/// numbers without trailing zeros or floating point noise
+pub(crate) fn tick_label(v: f64) -> String {
+    let rounded = (v * 1e6).round() / 1e6;
+    format!("{}", if rounded == 0. { 0. } else { rounded })
+}
+
+fn marker(kind: Marker, center: &Vert, size: f64) -> Edge {
+    let r = size / 2.;
+    let (x, y) = (center.x, center.y);
+    match kind {
+        Marker::Circle => circle(x, y, r, 16, false),
+        Marker::Square => rectangle(x - r, y - r, size, size, false),
+        Marker::Triangle => close_ring(
+            vec![
+                Vert::new(x - r, y - r),
+                Vert::new(x + r, y - r),
+                Vert::new(x, y + r),
+            ],
+            false,
+        ),
+        Marker::Diamond => close_ring(
+            vec![
+                Vert::new(x, y - r),
+                Vert::new(x + r, y),
+                Vert::new(x, y + r),
+                Vert::new(x - r, y),
+            ],
+            false,
+        ),
+    }
+}
+
+/// A chart laid out on a plate, in millimeters with the lower left corner of the plate at
+/// the origin. The shapes overlap where lines cross, so each one is extruded on its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chart {
+    pub width: f64,
+    pub height: f64,
+    /// groups of closed edges to extrude together, with their height above the plate
+    pub shapes: Vec<(Vec<Edge>, f64)>,
+    /// braille text with the top left corner of its first cell
+    pub labels: Vec<(String, Vert)>,
+}
+
+impl Chart {
+    pub fn new(data: &ChartData, options: &ChartOptions) -> Result<Self, ConversionError> {
+        let dims = &options.braille;
+        let values = data.series.iter().flat_map(|s| s.values.iter().cloned());
+        let (lo, hi, step) = nice_range(values, options.ticks, options.kind == ChartKind::Bar);
+        let y_ticks = tick_values(lo, hi, step);
+
+        // leave room for the value labels on the left and the category labels below
+        let label_width = |text: &str| dims.width(braille_cells(text).len());
+        let y_label_width = y_ticks
+            .iter()
+            .map(|v| label_width(&tick_label(*v)))
+            .fold(0., f64::max);
+        let ox = MARGIN + y_label_width + LABEL_GAP + TICK_LENGTH;
+        let oy = MARGIN + dims.height() + LABEL_GAP + TICK_LENGTH;
+        let (w, h) = (options.width, options.height);
+        let y_at = |v: f64| oy + (v - lo) / (hi - lo) * h;
+
+        let mut chart = Chart {
+            width: ox + w + MARGIN,
+            height: oy + h + MARGIN,
+            shapes: vec![],
+            labels: vec![],
+        };
+        let relief = options.relief_height;
+
+        // value axis with its ticks, labels and gridlines
+        let x_axis_y = y_at(lo.max(0.).min(hi));
+        chart.line(
+            &[Vert::new(ox, oy), Vert::new(ox, oy + h)],
+            options.axis_width,
+            relief,
+        );
+        chart.line(
+            &[Vert::new(ox, x_axis_y), Vert::new(ox + w, x_axis_y)],
+            options.axis_width,
+            relief,
+        );
+        for v in y_ticks.iter() {
+            let y = y_at(*v);
+            chart.line(
+                &[Vert::new(ox - TICK_LENGTH, y), Vert::new(ox, y)],
+                options.line_width,
+                relief,
+            );
+            if options.grid && (y - x_axis_y).abs() > options.axis_width {
+                chart.line(
+                    &[Vert::new(ox, y), Vert::new(ox + w, y)],
+                    options.line_width / 2.,
+                    relief / 2.,
+                );
+            }
+            chart.label_left(
+                tick_label(*v),
+                Vert::new(ox - TICK_LENGTH - LABEL_GAP, y),
+                dims,
+            );
+        }
+
+        // where each category or x value goes along the bottom
+        let n = data.labels.len();
+        let slot = w / n as f64;
+        let numeric_x = data.numeric_x().filter(|_| options.kind != ChartKind::Bar);
+        let x_positions: Vec<f64> = if let Some(xs) = &numeric_x {
+            let (x_lo, x_hi, x_step) = nice_range(xs.iter().cloned(), options.ticks, false);
+            let x_at = |v: f64| ox + (v - x_lo) / (x_hi - x_lo) * w;
+            for v in tick_values(x_lo, x_hi, x_step) {
+                let x = x_at(v);
+                chart.line(
+                    &[Vert::new(x, oy - TICK_LENGTH), Vert::new(x, oy)],
+                    options.line_width,
+                    relief,
+                );
+                chart.label_below(
+                    tick_label(v),
+                    Vert::new(x, oy - TICK_LENGTH - LABEL_GAP),
+                    dims,
+                );
+            }
+            xs.iter().map(|v| x_at(*v)).collect()
+        } else {
+            (0..n)
+                .map(|i| {
+                    let x = ox + (i as f64 + 0.5) * slot;
+                    let below = Vert::new(x, oy - TICK_LENGTH - LABEL_GAP);
+                    chart.label_below(data.labels[i].clone(), below, dims);
+                    x
+                })
+                .collect()
+        };
+
+        match options.kind {
+            ChartKind::Bar => {
+                let bar_width = slot * BAR_GROUP_WIDTH / data.series.len() as f64;
+                // a gap between neighboring bars, so they can be told apart
+                let gap = options.line_width.min(bar_width / 2.);
+                for (i, series) in data.series.iter().enumerate() {
+                    let texture = BAR_TEXTURES[i % BAR_TEXTURES.len()];
+                    for (x, v) in x_positions.iter().zip(series.values.iter()) {
+                        let x0 = x - slot * BAR_GROUP_WIDTH / 2. + i as f64 * bar_width + gap / 2.;
+                        let x1 = x0 + bar_width - gap;
+                        let (y0, y1) = (x_axis_y.min(y_at(*v)), x_axis_y.max(y_at(*v)));
+                        chart.bar(Vert::new(x0, y0), Vert::new(x1, y1), texture, 1., options);
+                    }
+                }
+            }
+            ChartKind::Line => {
+                for (i, series) in data.series.iter().enumerate() {
+                    let points: Vec<Vert> = x_positions
+                        .iter()
+                        .zip(series.values.iter())
+                        .map(|(x, v)| Vert::new(*x, y_at(*v)))
+                        .collect();
+                    let name = LineStyle::NAMES[i % LineStyle::NAMES.len()];
+                    let style = LineStyle::from_name(name, options.line_width).unwrap();
+                    for e in style.line(&points)? {
+                        chart.shapes.push((vec![e], relief));
+                    }
+                }
+            }
+            ChartKind::Scatter => {
+                for (i, series) in data.series.iter().enumerate() {
+                    let kind = MARKERS[i % MARKERS.len()];
+                    for (x, v) in x_positions.iter().zip(series.values.iter()) {
+                        let m = marker(kind, &Vert::new(*x, y_at(*v)), 3. * options.line_width);
+                        chart.shapes.push((vec![m], relief));
+                    }
+                }
+            }
+        }
+        if options.legend == LegendPlacement::Plate {
+            chart.add_legend(&Legend::from_chart(data, options), options)?;
+        }
+        Ok(chart)
+    }
+
+    /// make the plate taller to fit a legend above what is already on it
+    pub fn add_legend(
+        &mut self,
+        legend: &Legend,
+        options: &ChartOptions,
+    ) -> Result<(), ConversionError> {
+        if legend.is_empty() {
+            return Ok(());
+        }
+        let (width, height) = legend.size(options);
+        let top = self.height - MARGIN + LABEL_GAP + height;
+        legend.draw(self, MARGIN, top, options)?;
+        self.width = self.width.max(width + 2. * MARGIN);
+        self.height = top + MARGIN;
+        Ok(())
+    }
+
+    /// a label centered below the point
+    pub(crate) fn label_below(&mut self, text: String, at: Vert, dims: &BrailleDimensions) {
+        let x = at.x - dims.width(braille_cells(&text).len()) / 2.;
+        self.labels.push((text, Vert::new(x, at.y)));
+    }
+
+    /// a label ending at the point, centered on it up and down
+    pub(crate) fn label_left(&mut self, text: String, at: Vert, dims: &BrailleDimensions) {
+        let x = at.x - dims.width(braille_cells(&text).len());
+        self.labels
+            .push((text, Vert::new(x, at.y + dims.height() / 2.)));
+    }
+
+    pub(crate) fn line(&mut self, points: &[Vert], width: f64, height: f64) {
+        if let Some(e) = stroke(points, width) {
+            self.shapes.push((vec![e], height));
+        }
+    }
+
+    /// A bar filled with a texture, with a solid outline so its size can still be felt.
+    /// The texture has `scale` millimeters for each of its units.
+    pub(crate) fn bar(
+        &mut self,
+        lower: Vert,
+        upper: Vert,
+        texture: Texture,
+        scale: f64,
+        options: &ChartOptions,
+    ) {
+        let (x0, y0, x1, y1) = (lower.x, lower.y, upper.x, upper.y);
+        let relief = options.relief_height;
+        let lw = options.line_width;
+        if x1 <= x0 || y1 <= y0 {
+            return;
+        }
+        if texture == Texture::Solid || x1 - x0 <= 3. * lw || y1 - y0 <= 3. * lw {
+            self.shapes
+                .push((vec![rectangle(x0, y0, x1 - x0, y1 - y0, false)], relief));
+            return;
+        }
+        let (ix0, iy0, ix1, iy1) = (x0 + lw, y0 + lw, x1 - lw, y1 - lw);
+        self.shapes.push((
+            vec![
+                rectangle(ix0, iy0, ix1 - ix0, iy1 - iy0, true),
+                rectangle(x0, y0, x1 - x0, y1 - y0, false),
+            ],
+            relief,
+        ));
+        for e in texture.scaled_pattern_edges(ix0, iy0, ix1, iy1, scale) {
+            self.shapes.push((vec![e], relief));
+        }
+    }
+
+    /// the plate with the chart and its braille labels on top
+    pub fn triangles(&self, options: &ChartOptions) -> Result<Vec<f64>, ConversionError> {
+        let base = options.base_thickness;
+        let mut tris = extrude_edges(
+            vec![rectangle(0., 0., self.width, self.height, false)],
+            base,
+        )?;
+        for (edges, height) in self.shapes.iter() {
+            let mut shape = extrude_edges(edges.clone(), *height)?;
+            translate_triangles(&mut shape, 0., 0., base);
+            tris.append(&mut shape);
+        }
+        for (text, at) in self.labels.iter() {
+            tris.extend(braille_triangles(
+                text,
+                at.x,
+                at.y,
+                base,
+                &options.braille,
+            )?);
+        }
+        info!(
+            "made chart with {} shapes and {} labels",
+            self.shapes.len(),
+            self.labels.len()
+        );
+        Ok(tris)
+    }
+
+    /// Draw the chart for swell paper, where everything printed black rises when heated.
+    /// Heights are lost, so gridlines come out as high as the rest.
+    pub fn to_image(&self, pixels_per_mm: f64, dims: &BrailleDimensions) -> GrayImage {
+        let width = (self.width * pixels_per_mm).ceil() as u32 + 1;
+        let height = (self.height * pixels_per_mm).ceil() as u32 + 1;
+        let mut img = GrayImage::from_pixel(width, height, Luma([255]));
+        // images count rows down from the top
+        let to_pixels = |e: &Edge| {
+            Edge::from(
+                e.iter()
+                    .map(|v| Vert::new(v.x * pixels_per_mm, (self.height - v.y) * pixels_per_mm))
+                    .collect::<Vec<Vert>>(),
+            )
+        };
+        for (edges, _) in self.shapes.iter() {
+            fill_edges(
+                &mut img,
+                &edges.iter().map(to_pixels).collect::<Vec<Edge>>(),
+            );
+        }
+        for (text, at) in self.labels.iter() {
+            for dot in braille_edges(text, at.x, at.y, dims) {
+                fill_edges(&mut img, &[to_pixels(&dot)]);
+            }
+        }
+        img
+    }
+}
+
+/// Read a csv file and write a tactile chart of it as an stl.
+/// A legend placed on its own page is written next to it, see `key_filename`.
+pub fn csv_file_to_chart_stl(
+    input_filename: &str,
+    options: &ChartOptions,
+    output_filename: &str,
+) -> Result<(), ConversionError> {
+    let data = ChartData::from_csv(&fs::read_to_string(input_filename)?)?;
+    write_stl_3d(
+        output_filename,
+        Chart::new(&data, options)?.triangles(options)?,
+    )?;
+    let legend = Legend::from_chart(&data, options);
+    if options.legend == LegendPlacement::Page && !legend.is_empty() {
+        write_stl_3d(
+            &key_filename(output_filename),
+            legend.to_chart(options)?.triangles(options)?,
+        )?;
+    }
+    Ok(())
+}
+
+/// the name of the key plate that goes with an output file, "chart.stl" has "chart-key.stl"
+pub fn key_filename(filename: &str) -> String {
+    match filename.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{}-key.{}", stem, extension),
+        _ => format!("{}-key", filename),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    static CSV: &str = "month, rain, sun\nJan, 10, 2\nFeb, 25.5, 4\nMar, 5, 7\n";
+
+    #[test]
+    fn test_from_csv() -> Result<(), Box<dyn Error>> {
+        let data = ChartData::from_csv(CSV)?;
+        assert_eq!(data.x_name, "month");
+        assert_eq!(data.labels, vec!["Jan", "Feb", "Mar"]);
+        assert_eq!(data.series.len(), 2);
+        assert_eq!(data.series[0].values, vec![10., 25.5, 5.]);
+        assert!(data.numeric_x().is_none());
+        assert!(matches!(
+            ChartData::from_csv("a, b\n1, x\n"),
+            Err(ConversionError::Parse(_))
+        ));
+        assert!(ChartData::from_csv("a, b\n1, 2, 3\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_nice_range() {
+        assert_eq!(nice_range([3., 27.].into_iter(), 5, true), (0., 30., 10.));
+        assert_eq!(nice_range([0.12, 0.9].into_iter(), 4, false), (0., 1., 0.2));
+        assert_eq!(tick_label(0.1 + 0.2), "0.3");
+    }
+
+    #[test]
+    fn test_bar_chart() -> Result<(), Box<dyn Error>> {
+        let data = ChartData::from_csv(CSV)?;
+        let options = ChartOptions::default();
+        let chart = Chart::new(&data, &options)?;
+        // value ticks from 0 to 30 and the three months
+        assert_eq!(chart.labels.len(), 4 + 3);
+        assert!(chart.labels.iter().any(|(text, _)| text == "Feb"));
+        let tris = chart.triangles(&options)?;
+        assert_eq!(tris.len() % 9, 0);
+        let top = tris.chunks(3).map(|v| v[2]).fold(f64::MIN, f64::max);
+        assert_eq!(top, options.base_thickness + options.relief_height);
+        Ok(())
+    }
+
+    #[test]
+    fn test_chart_to_image() -> Result<(), Box<dyn Error>> {
+        let data = ChartData::from_csv(CSV)?;
+        let options = ChartOptions::default();
+        let chart = Chart::new(&data, &options)?;
+        let img = chart.to_image(4., &options.braille);
+        assert_eq!(img.width(), (chart.width * 4.).ceil() as u32 + 1);
+        let black = img.pixels().filter(|p| p.0[0] == 0).count();
+        assert!(black > 0 && black < (img.width() * img.height()) as usize / 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_filename() {
+        assert_eq!(key_filename("out/chart.stl"), "out/chart-key.stl");
+        assert_eq!(key_filename("chart"), "chart-key");
+    }
+
+    #[test]
+    fn test_line_and_scatter_charts() -> Result<(), Box<dyn Error>> {
+        let data = ChartData::from_csv("x, a, b, c\n0, 1, 2, 3\n1, 2, 3, 1\n2, 4, 1, 2\n")?;
+        for kind in [ChartKind::Line, ChartKind::Scatter] {
+            let options = ChartOptions {
+                kind,
+                ..Default::default()
+            };
+            let chart = Chart::new(&data, &options)?;
+            assert!(chart.shapes.len() > 3);
+            // everything is on the plate
+            for v in chart
+                .shapes
+                .iter()
+                .flat_map(|(edges, _)| edges.iter().flat_map(|e| e.iter()))
+            {
+                assert!(v.x >= 0. && v.x <= chart.width && v.y >= 0. && v.y <= chart.height);
+            }
+        }
+        Ok(())
+    }
+}
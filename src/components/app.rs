@@ -1,3 +1,4 @@
+use crate::components::regions::RegionEditor;
 use crate::components::stl::StlViewer;
 
 use std::rc::Rc;
@@ -11,7 +12,7 @@ use gloo_file::callbacks::FileReader;
 use gloo_file::File;
 use log::info;
 
-use crate::components::image::img_html_from_bytes;
+use crate::components::image::{data_url_from_img_bytes, img_html_from_bytes};
 use crate::components::threshold::ThresholdImage;
 use super::store::GlobalState;
 
@@ -20,6 +21,7 @@ use yewdux::prelude::*;
 pub enum Msg {
     FileLoading,
     LoadedBytes(String, Vec<u8>),
+    LoadFailed(String),
     Files(Vec<File>),
     State(Rc<GlobalState>),
 }
@@ -29,6 +31,8 @@ pub struct App {
     reader: Option<FileReader>,
     file_bytes: Option<Rc<Vec<u8>>>,
     file_loading: bool,
+    /// why the last file could not be used
+    error: Option<String>,
     dispatch: Dispatch<BasicStore<GlobalState>>,
     state: Option<Rc<GlobalState>>,
 }
@@ -43,6 +47,7 @@ impl Component for App {
             reader: None,
             file_bytes: None,
             file_loading: false,
+            error: None,
             dispatch,
             state: Default::default()
         }
@@ -59,12 +64,29 @@ impl Component for App {
                 true
             }
             Msg::LoadedBytes(file_name, data) => {
-                self.dispatch.reduce(move |state| state.file_name = Some(file_name));
+                // only images that can be shown and converted are kept
+                if let Err(e) = data_url_from_img_bytes(&data) {
+                    ctx.link().send_message(Msg::LoadFailed(e.to_string()));
+                    return false;
+                }
+                self.error = None;
+                self.dispatch.reduce(move |state| {
+                    state.file_name = Some(file_name);
+                    // region ids belong to the old image
+                    state.region_settings.properties.clear();
+                });
                 self.file_bytes = Some(Rc::from(data));
                 self.file_loading = false;
                 self.reader = None;
                 true
             }
+            Msg::LoadFailed(error) => {
+                self.error = Some(error);
+                self.file_bytes = None;
+                self.file_loading = false;
+                self.reader = None;
+                true
+            }
             Msg::Files(files) => {
                 for file in files.into_iter() {
                     let file_name = file.name();
@@ -74,10 +96,13 @@ impl Component for App {
 
                         info!("Got filename = {}", file_name);
                         gloo_file::callbacks::read_as_bytes(&file, move |res| {
-                            link.send_message(Msg::LoadedBytes(
-                                file_name,
-                                res.expect("failed to read file"),
-                            ))
+                            link.send_message(match res {
+                                Ok(data) => Msg::LoadedBytes(file_name, data),
+                                Err(e) => Msg::LoadFailed(format!(
+                                    "could not read {}: {}",
+                                    file_name, e
+                                )),
+                            })
                         })
                     };
                     self.reader = Some(task);
@@ -120,6 +145,9 @@ impl Component for App {
                     }
                     </h1>
                 </div>
+                if let Some(error) = &self.error {
+                    <p class="error">{ error }</p>
+                }
                 </div>
                 if let Some(data) = &self.file_bytes {
                     <div class="image-container">
@@ -135,6 +163,9 @@ impl Component for App {
                                 <StlViewer
                                     bytes={ Rc::clone(data) }
                                 />
+                                <RegionEditor
+                                    bytes={ Rc::clone(data) }
+                                />
                             }
                             </div>
                         </div>
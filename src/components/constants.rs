@@ -3,3 +3,5 @@ pub static IMAGE_WIDTH_PX: u32 = VIEW_WIDTH_PX / 2;
 pub static DEFAULT_STL_HEIGHT: f64 = 10.0;
 pub static DEFAULT_SCALE_FACTOR: f64 = 0.05;
 pub static DEFAULT_THRESHOLD_VALUE: u8 = 128;
+pub static DEFAULT_EDGE_RADIUS: f64 = 2.0;
+pub static DEFAULT_LINE_WIDTH: f64 = 20.0;
@@ -4,36 +4,48 @@ use yew::{html, Html};
 use base64::encode;
 use image::{
     guess_format, load_from_memory_with_format, DynamicImage,
-    ImageError, ImageFormat,
+    ImageFormat,
 };
 use std::io::Cursor;
 
-fn format_to_dataurl_media_type(fmt: ImageFormat) -> String {
+use crate::error::ConversionError;
+
+fn format_to_dataurl_media_type(fmt: ImageFormat) -> Result<String, ConversionError> {
     match fmt {
-        ImageFormat::Png => "image/png".to_string(),
-        ImageFormat::Jpeg => "image/jpeg".to_string(),
-        _ => panic!("no data url media type available for ImageFormat {:?}", fmt),
+        ImageFormat::Png => Ok("image/png".to_string()),
+        ImageFormat::Jpeg => Ok("image/jpeg".to_string()),
+        _ => Err(ConversionError::UnsupportedFormat(fmt)),
     }
 }
 
-pub fn data_url_from_img_bytes(bytes: &Vec<u8>) -> Result<String, ImageError> {
-    let format = guess_format(bytes).expect("could not guess format");
-    let mediatype = format_to_dataurl_media_type(format);
+pub fn data_url_from_img_bytes(bytes: &Vec<u8>) -> Result<String, ConversionError> {
+    let format = guess_format(bytes)?;
+    let mediatype = format_to_dataurl_media_type(format)?;
     let b64 = encode(bytes);
     Ok(format!("data:{};base64,{}", mediatype, b64))
 }
 
-pub fn img_from_bytes(bytes: &Vec<u8>) -> Result<DynamicImage, ImageError> {
+pub fn img_from_bytes(bytes: &Vec<u8>) -> Result<DynamicImage, ConversionError> {
     let format = guess_format(bytes)?;
     info!("image format is {:?}", format);
-    load_from_memory_with_format(bytes, format)
+    format_to_dataurl_media_type(format)?;
+    Ok(load_from_memory_with_format(bytes, format)?)
+}
+
+/// a message to show in place of something that could not be made
+pub fn error_html(e: &ConversionError) -> Html {
+    html! {
+        <p class="error">{ e.to_string() }</p>
+    }
 }
 
 pub fn img_html_from_bytes(bytes: &Vec<u8>) -> Html {
     log::info!("from img_html_from_bytes!!");
-    let durl = data_url_from_img_bytes(bytes).unwrap();
-    html! {
-        <img src={ durl } alt={ "image from bytes" }/>
+    match data_url_from_img_bytes(bytes) {
+        Ok(durl) => html! {
+            <img src={ durl } alt={ "image from bytes" }/>
+        },
+        Err(e) => error_html(&e),
     }
 }
 
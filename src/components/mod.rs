@@ -2,6 +2,7 @@ pub mod app;
 pub mod constants;
 mod external;
 pub mod image;
+mod regions;
 mod slider;
 mod stl;
 #[macro_use]
@@ -0,0 +1,286 @@
+use crate::chart::ChartOptions;
+use crate::error::ConversionError;
+use crate::legend::Legend;
+use crate::region::{image_to_region_map, RegionMap, RegionSettings};
+use crate::stl::triangles_to_stl_writer;
+use crate::texture::Texture;
+use crate::triangle::StlOptions;
+use base64::encode;
+use gloo_file::{callbacks::read_as_text, File};
+use std::io::BufWriter;
+use std::rc::Rc;
+use web_sys::{Event, HtmlInputElement, HtmlSelectElement};
+use yew::{function_component, html, use_mut_ref, use_state, Callback, Properties, TargetCast};
+
+use yewdux::prelude::*;
+use yewdux_functional::use_store;
+
+use super::{image::{error_html, img_from_bytes}, number_input::NumberInput, store::GlobalState, utils::cached};
+
+type MapKey = (Rc<Vec<u8>>, u8, f64, StlOptions);
+type KeyKey = (Rc<RegionMap>, RegionSettings, f64, f64);
+
+#[derive(Properties, PartialEq)]
+pub struct RegionEditorProps {
+    pub bytes: Rc<Vec<u8>>,
+}
+
+fn json_data_url(json: &str) -> String {
+    format!("data:application/json;base64,{}", encode(json))
+}
+
+fn region_map(key: &MapKey) -> Result<Rc<RegionMap>, Rc<ConversionError>> {
+    let (bytes, threshold, scale, options) = key;
+    img_from_bytes(bytes)
+        .and_then(|image| image_to_region_map(image, *threshold, *scale, options))
+        .map(Rc::new)
+        .map_err(Rc::new)
+}
+
+/// a data url to the stl of the key plate, or None when no region has a texture to show
+fn key_href(key: &KeyKey) -> Result<Option<Rc<String>>, Rc<ConversionError>> {
+    let (map, settings, height, scale) = key;
+    let legend = Legend::from_regions(map, settings, *scale);
+    if legend.is_empty() {
+        return Ok(None);
+    }
+    // raised as high as the regions
+    let key_options = ChartOptions {
+        relief_height: *height,
+        ..Default::default()
+    };
+    let mut writer = BufWriter::new(vec![]);
+    legend
+        .to_chart(&key_options)
+        .and_then(|chart| chart.triangles(&key_options))
+        .and_then(|tris| triangles_to_stl_writer(&mut writer, tris))
+        .and_then(|_| writer.into_inner().map_err(|e| e.into_error().into()))
+        .map(|stl| Some(Rc::new(format!("data:model/stl;base64,{}", encode(stl)))))
+        .map_err(Rc::new)
+}
+
+/// A table of the regions on the plate where each one can be given its own height, texture
+/// and label or be left off, with the region map, the settings and a key to the textures
+/// to download
+#[function_component(RegionEditor)]
+pub fn region_editor(props: &RegionEditorProps) -> Html {
+    let store = use_store::<BasicStore<GlobalState>>();
+    // tracing the image and making the key are slow, only redo them when what they use changes
+    let map_cache = use_mut_ref(|| None);
+    let key_cache = use_mut_ref(|| None);
+    // the reader has to be kept until the settings file is read
+    let settings_reader = use_mut_ref(|| None);
+    let settings_error = use_state(|| None::<String>);
+
+    let state = if let Some(s) = store.state() {
+        s
+    } else {
+        return html! { <div> { "Could not get state" } </div> };
+    };
+
+    let options = state.stl_options();
+    let map_key = (props.bytes.clone(), state.threshold_value, state.stl_scale_factor, options);
+    let traced = match cached(&map_cache, map_key, region_map) {
+        Ok(map) => map,
+        Err(e) => return error_html(&e),
+    };
+    let key_key = (traced.clone(), state.region_settings.clone(), state.stl_height, state.stl_scale_factor);
+    let key_href = match cached(&key_cache, key_key, key_href) {
+        Ok(href) => href,
+        Err(e) => return error_html(&e),
+    };
+    let mut map = (*traced).clone();
+    map.apply_properties(&state.region_settings);
+
+    let name = state.file_name.clone().unwrap_or("no-name".to_string());
+    let json_href = match map.to_json() {
+        Ok(json) => json_data_url(&json),
+        Err(e) => return error_html(&e),
+    };
+    let geojson_href = json_data_url(&map.to_geojson().to_string());
+    let settings_href = match state.region_settings.to_json() {
+        Ok(json) => json_data_url(&json),
+        Err(e) => return error_html(&e),
+    };
+
+    let settings_onchange = {
+        let dispatch = store.dispatch().clone();
+        let settings_error = settings_error.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let file = match input.files().and_then(|files| files.get(0)) {
+                Some(file) => File::from(file),
+                None => return,
+            };
+            let file_name = file.name();
+            let dispatch = dispatch.clone();
+            let settings_error = settings_error.clone();
+            *settings_reader.borrow_mut() = Some(read_as_text(&file, move |res| {
+                let settings = res
+                    .map_err(|e| format!("could not read {}: {}", file_name, e))
+                    .and_then(|text| RegionSettings::from_json(&text).map_err(|e| e.to_string()));
+                match settings {
+                    Ok(settings) => {
+                        settings_error.set(None);
+                        dispatch.reduce(move |state| state.region_settings = settings);
+                    }
+                    Err(e) => settings_error.set(Some(e)),
+                }
+            }));
+        })
+    };
+    let default_texture_onchange = store.dispatch().reduce_callback_with(|state: &mut GlobalState, e: Event| {
+        let select: HtmlSelectElement = e.target_unchecked_into();
+        if let Some(texture) = Texture::from_name(&select.value()) {
+            state.region_settings.defaults.texture = texture;
+        }
+    });
+    let min_area_onchange = store.dispatch().reduce_callback_with(|state: &mut GlobalState, v: f64| state.region_settings.defaults.min_area = v);
+    let by_gray_onclick = store.dispatch().reduce_callback_with(|state: &mut GlobalState, _v| {
+        let defaults = &mut state.region_settings.defaults;
+        defaults.gray_heights = match defaults.gray_heights {
+            Some(_) => None,
+            None => Some((0., state.stl_height)),
+        };
+    });
+
+    html! {
+        <div>
+            <b>{ "Regions" }</b>
+            <NumberInput label="Smallest region area"
+                min=0.0
+                max=10000.0
+                onchange={min_area_onchange}
+                value={ state.region_settings.defaults.min_area }
+            />
+            <label for="height-by-gray">{ "Darker regions stand higher" }</label>
+            <input type="checkbox"
+                id="height-by-gray"
+                checked={ state.region_settings.defaults.gray_heights.is_some() }
+                onclick={by_gray_onclick}
+            />
+            <label for="default-texture">{ "Texture of other regions" }</label>
+            <select id="default-texture" onchange={default_texture_onchange}>
+                { for Texture::NAMES.iter().map(|name| html! {
+                    <option value={ *name } selected={ state.region_settings.defaults.texture.name() == *name }>{ name }</option>
+                }) }
+            </select>
+            <table>
+                <tr>
+                    <th>{ "Id" }</th>
+                    <th>{ "Center" }</th>
+                    <th>{ "Area" }</th>
+                    <th>{ "Height" }</th>
+                    <th>{ "Texture" }</th>
+                    <th>{ "Hollow" }</th>
+                    <th>{ "Include" }</th>
+                    <th>{ "Label" }</th>
+                    <th>{ "Description" }</th>
+                </tr>
+                { for map.regions.iter().map(|region| {
+                    let id = region.id;
+                    let resolved = state.region_settings.resolve(region, state.stl_height);
+                    // the text and height are only kept when the input is left, not on every key
+                    let label_onchange = store.dispatch().reduce_callback_with(move |state: &mut GlobalState, e: Event| {
+                        let input: HtmlInputElement = e.target_unchecked_into();
+                        state.region_settings.properties.entry(id).or_default().label = input.value();
+                    });
+                    let description_onchange = store.dispatch().reduce_callback_with(move |state: &mut GlobalState, e: Event| {
+                        let input: HtmlInputElement = e.target_unchecked_into();
+                        state.region_settings.properties.entry(id).or_default().description = input.value();
+                    });
+                    // an empty height goes back to the default
+                    let height_onchange = store.dispatch().reduce_callback_with(move |state: &mut GlobalState, e: Event| {
+                        let input: HtmlInputElement = e.target_unchecked_into();
+                        let v = input.value_as_number();
+                        state.region_settings.properties.entry(id).or_default().height = if v.is_nan() { None } else { Some(v) };
+                    });
+                    // the empty choice goes back to the default texture
+                    let texture_onchange = store.dispatch().reduce_callback_with(move |state: &mut GlobalState, e: Event| {
+                        let select: HtmlSelectElement = e.target_unchecked_into();
+                        state.region_settings.properties.entry(id).or_default().texture = Texture::from_name(&select.value());
+                    });
+                    let hollow = state.region_settings.hollow(region, &options).is_some();
+                    let hollow_onclick = store.dispatch().reduce_callback_with(move |state: &mut GlobalState, _v| {
+                        state.region_settings.properties.entry(id).or_default().hollow = Some(!hollow);
+                    });
+                    let included = resolved.is_some();
+                    let include_onclick = store.dispatch().reduce_callback_with(move |state: &mut GlobalState, _v| {
+                        state.region_settings.properties.entry(id).or_default().excluded = Some(included);
+                    });
+                    html! {
+                        <tr>
+                            <td>{ id }</td>
+                            <td>{ format!("{:.1}, {:.1}", region.centroid[0], region.centroid[1]) }</td>
+                            <td>{ format!("{:.1}", region.area) }</td>
+                            <td>
+                                <input type="number"
+                                    min="0"
+                                    placeholder={ resolved.map_or(String::new(), |(h, _)| format!("{:.1}", h)) }
+                                    value={ region.properties.height.map_or(String::new(), |h| h.to_string()) }
+                                    onchange={height_onchange}
+                                />
+                            </td>
+                            <td>
+                                <select onchange={texture_onchange}>
+                                    <option value="" selected={ region.properties.texture.is_none() }>{ "default" }</option>
+                                    { for Texture::NAMES.iter().map(|name| html! {
+                                        <option value={ *name } selected={ region.properties.texture.map(|t| t.name()) == Some(*name) }>{ name }</option>
+                                    }) }
+                                </select>
+                            </td>
+                            <td>
+                                <input type="checkbox"
+                                    checked={ hollow }
+                                    onclick={hollow_onclick}
+                                />
+                            </td>
+                            <td>
+                                <input type="checkbox"
+                                    checked={ included }
+                                    onclick={include_onclick}
+                                />
+                            </td>
+                            <td>
+                                <input type="text"
+                                    value={ region.properties.label.clone() }
+                                    onchange={label_onchange}
+                                />
+                            </td>
+                            <td>
+                                <input type="text"
+                                    value={ region.properties.description.clone() }
+                                    onchange={description_onchange}
+                                />
+                            </td>
+                        </tr>
+                    }
+                }) }
+            </table>
+            <a href={ json_href } download={ format!("{}-regions.json", name) }>
+                <button>{ "Download region map" }</button>
+            </a>
+            <a href={ geojson_href } download={ format!("{}-regions.geojson", name) }>
+                <button>{ "Download GeoJSON" }</button>
+            </a>
+            <a href={ settings_href } download={ format!("{}-region-settings.json", name) }>
+                <button>{ "Download region settings" }</button>
+            </a>
+            <label for="region-settings">{ "Load region settings" }</label>
+            <input type="file"
+                id="region-settings"
+                accept="application/json"
+                multiple=false
+                onchange={settings_onchange}
+            />
+            if let Some(e) = &*settings_error {
+                <p class="error">{ e }</p>
+            }
+            if let Some(href) = key_href {
+                <a href={ (*href).clone() } download={ format!("{}-key.stl", name) }>
+                    <button>{ "Download key" }</button>
+                </a>
+            }
+        </div>
+    }
+}
@@ -1,8 +1,13 @@
-use crate::{stl::triangles_to_stl_writer, triangle::image_to_stl};
+use crate::{
+    error::ConversionError,
+    region::{image_to_stl_with_regions, RegionSettings},
+    stl::triangles_to_stl_writer,
+    triangle::StlOptions,
+};
 use log::info;
 use std::io::BufWriter;
 use std::rc::Rc;
-use yew::{function_component, html, use_effect, Properties};
+use yew::{function_component, html, use_effect, use_mut_ref, Properties};
 
 use yewdux::prelude::*;
 use yewdux_functional::use_store;
@@ -10,7 +15,7 @@ use yewdux_functional::use_store;
 
 use super::{
     external::STLViewer,
-    image::img_from_bytes, store::GlobalState,
+    image::{error_html, img_from_bytes}, store::GlobalState, utils::cached,
 };
 
 #[derive(Properties, PartialEq)]
@@ -18,10 +23,37 @@ pub struct StlViewerProps {
     pub bytes: Rc<Vec<u8>>,
 }
 
+type StlKey = (Rc<Vec<u8>>, u8, f64, f64, StlOptions, RegionSettings);
+
+/// the region settings without the labels and descriptions, which do not change the stl
+fn shape_settings(settings: &RegionSettings) -> RegionSettings {
+    let mut settings = settings.clone();
+    for properties in settings.properties.values_mut() {
+        properties.label.clear();
+        properties.description.clear();
+    }
+    settings
+}
+
+fn stl_bytes(key: &StlKey) -> Result<Rc<Vec<u8>>, Rc<ConversionError>> {
+    let (bytes, threshold, height, scale, options, settings) = key;
+    let image = img_from_bytes(bytes)?;
+
+    let triangles = image_to_stl_with_regions(image, *threshold, *height, *scale, options, settings)?;
+
+    let b: Vec<u8> = vec![];
+    let mut writer = BufWriter::new(b);
+    triangles_to_stl_writer(&mut writer, triangles)?;
+
+    let stl = writer.into_inner().map_err(|e| ConversionError::from(e.into_error()))?;
+    Ok(Rc::new(stl))
+}
+
 #[function_component(StlViewer)]
 pub fn stl_viewer(props: &StlViewerProps) -> Html {
 
     let store = use_store::<BasicStore<GlobalState>>();
+    let stl_cache = use_mut_ref(|| None);
 
     let state = if let Some(s) = store.state() {
         s
@@ -29,22 +61,18 @@ pub fn stl_viewer(props: &StlViewerProps) -> Html {
         return html! { <div> { "Could not get state" } </div> };
     };
 
-    let image = img_from_bytes(&props.bytes).unwrap();
-
-    let triangles = image_to_stl(
-        image,
+    let key = (
+        props.bytes.clone(),
         state.threshold_value,
         state.stl_height,
         state.stl_scale_factor,
-    )
-    .unwrap()
-    .collect();
-
-    let b: Vec<u8> = vec![];
-    let mut writer = BufWriter::new(b);
-    triangles_to_stl_writer(&mut writer, triangles).unwrap();
-
-    let inner = writer.into_inner().unwrap();
+        state.stl_options(),
+        shape_settings(&state.region_settings),
+    );
+    let inner = match cached(&stl_cache, key, stl_bytes) {
+        Ok(inner) => inner,
+        Err(e) => return error_html(&e),
+    };
 
     let file_name = format!("{}.stl", state.file_name.clone().unwrap_or("no-name".to_string()));
     let g_file = gloo_file::File::new(&file_name, inner.as_slice());
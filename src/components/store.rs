@@ -1,4 +1,11 @@
-use super::constants::{DEFAULT_STL_HEIGHT, DEFAULT_THRESHOLD_VALUE, DEFAULT_SCALE_FACTOR};
+use super::constants::{DEFAULT_STL_HEIGHT, DEFAULT_THRESHOLD_VALUE, DEFAULT_SCALE_FACTOR, DEFAULT_EDGE_RADIUS, DEFAULT_LINE_WIDTH};
+use crate::engrave::EngraveOptions;
+use crate::frame::BorderOptions;
+use crate::hollow::HollowOptions;
+use crate::mold::MoldOptions;
+use crate::region::RegionSettings;
+use crate::ribbon::EdgeProfile;
+use crate::triangle::StlOptions;
 
 #[derive(Clone)]
 pub struct GlobalState {
@@ -7,6 +14,24 @@ pub struct GlobalState {
     pub stl_scale_factor: f64,
     pub display_stl: bool,
     pub file_name: Option<String>,
+    pub edge_profile: EdgeProfile,
+    /// size used by the fillet and chamfer profiles, or the angle of the draft profile
+    pub edge_radius: f64,
+    /// trace the centerlines of a line drawing instead of the outlines
+    pub line_art: bool,
+    pub line_width: f64,
+    pub border: BorderOptions,
+    /// make a thermoform master instead of the plain relief
+    pub mold_enabled: bool,
+    pub mold: MoldOptions,
+    /// cut the shapes into a plate instead of raising them
+    pub engrave_enabled: bool,
+    pub engrave: EngraveOptions,
+    /// raise only walls along the edges of the shapes
+    pub hollow_enabled: bool,
+    pub hollow: HollowOptions,
+    /// the properties chosen for the regions of the image, by region id
+    pub region_settings: RegionSettings,
     // TODO
     // unit is mm
     // pixels per unit length?
@@ -23,6 +48,32 @@ impl Default for GlobalState {
             stl_scale_factor: DEFAULT_SCALE_FACTOR,
             display_stl: false,
             file_name: None,
+            edge_profile: EdgeProfile::default(),
+            edge_radius: DEFAULT_EDGE_RADIUS,
+            line_art: false,
+            line_width: DEFAULT_LINE_WIDTH,
+            border: BorderOptions::default(),
+            mold_enabled: false,
+            mold: MoldOptions::default(),
+            engrave_enabled: false,
+            engrave: EngraveOptions::default(),
+            hollow_enabled: false,
+            hollow: HollowOptions::default(),
+            region_settings: RegionSettings::default(),
+        }
+    }
+}
+
+impl GlobalState {
+    pub fn stl_options(&self) -> StlOptions {
+        let has_border = self.border.frame_width > 0. || self.border.marker_size > 0.;
+        StlOptions {
+            profile: self.edge_profile,
+            line_width: if self.line_art { Some(self.line_width) } else { None },
+            border: if has_border { Some(self.border) } else { None },
+            mold: if self.mold_enabled { Some(self.mold) } else { None },
+            engrave: if self.engrave_enabled { Some(self.engrave) } else { None },
+            hollow: if self.hollow_enabled { Some(self.hollow) } else { None },
         }
     }
 }
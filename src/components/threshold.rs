@@ -2,10 +2,14 @@ use crate::components::{
         number_input::NumberInput,
         slider::Slider, utils::maybe_resize_photon_image,
     };
+use crate::mold::MoldKind;
+use crate::offset::OffsetJoin;
+use crate::ribbon::EdgeProfile;
+use crate::texture::Texture;
 use log::info;
-use web_sys::HtmlCanvasElement;
+use web_sys::{Event, HtmlCanvasElement, HtmlSelectElement};
 use std::rc::Rc;
-use yew::{html, Component, Context, Html, Properties, NodeRef};
+use yew::{html, Component, Context, Html, Properties, NodeRef, TargetCast};
 use yewdux::prelude::*;
 use wasm_bindgen_futures::spawn_local;
 use photon_rs::{PhotonImage, monochrome::threshold};
@@ -22,6 +26,7 @@ pub enum Msg {
     State(Rc<GlobalState>),
     PhotonImageReady(PhotonImage),
     UpdateImage,
+    Failed(String),
 }
 
 pub struct ThresholdImage {
@@ -31,6 +36,7 @@ pub struct ThresholdImage {
     state: Option<Rc<GlobalState>>,
     last_threshold_value: Option<u8>,
     photon_image: Option<PhotonImage>,
+    error: Option<String>,
 }
 
 macro_rules! timeit {
@@ -57,11 +63,14 @@ impl Component for ThresholdImage {
         let bytes = ctx.props().bytes.clone();
         let link = ctx.link().clone();
         spawn_local(async move {
-            let photon_image = photon_image_from(&bytes).await;
-
-            // TODO should i remove this?
-            let photon_image = maybe_resize_photon_image(photon_image);
-            link.send_message(Msg::PhotonImageReady(photon_image));
+            match photon_image_from(&bytes).await {
+                Ok(photon_image) => {
+                    // TODO should i remove this?
+                    let photon_image = maybe_resize_photon_image(photon_image);
+                    link.send_message(Msg::PhotonImageReady(photon_image));
+                }
+                Err(e) => link.send_message(Msg::Failed(e)),
+            }
         });
         Self {
             canvas_loaded: false,
@@ -70,6 +79,7 @@ impl Component for ThresholdImage {
             canvas_ref: NodeRef::default(),
             last_threshold_value: None,
             photon_image: None,
+            error: None,
         }
     }
 
@@ -77,11 +87,14 @@ impl Component for ThresholdImage {
         let bytes = ctx.props().bytes.clone();
         let link = ctx.link().clone();
         spawn_local(async move {
-            let photon_image = photon_image_from(&bytes).await;
-
-            // TODO should i remove this?
-            let photon_image = maybe_resize_photon_image(photon_image);
-            link.send_message(Msg::PhotonImageReady(photon_image));
+            match photon_image_from(&bytes).await {
+                Ok(photon_image) => {
+                    // TODO should i remove this?
+                    let photon_image = maybe_resize_photon_image(photon_image);
+                    link.send_message(Msg::PhotonImageReady(photon_image));
+                }
+                Err(e) => link.send_message(Msg::Failed(e)),
+            }
         });
         self.canvas_loaded = false;
         true
@@ -104,16 +117,26 @@ impl Component for ThresholdImage {
             Msg::PhotonImageReady(value) => {
                 info!("Photon image ready");
                 self.photon_image = Some(value);
+                let had_error = self.error.take().is_some();
                 ctx.link().send_message(Msg::UpdateImage);
-                false
+                had_error
             }
             Msg::UpdateImage => {
                 if let (Some(state), Some(photon_image)) = (&self.state, &self.photon_image) {
                     if self.canvas_loaded {
-                        let canvas = self.canvas_ref.cast::<HtmlCanvasElement>().unwrap();
+                        let canvas = match self.canvas_ref.cast::<HtmlCanvasElement>() {
+                            Some(canvas) => canvas,
+                            None => return false,
+                        };
                         canvas.set_width(photon_image.get_width());
                         canvas.set_height(photon_image.get_height());
-                        let ctx = context_from_canvas(&canvas);
+                        let ctx = match context_from_canvas(&canvas) {
+                            Ok(ctx) => ctx,
+                            Err(e) => {
+                                ctx.link().send_message(Msg::Failed(e));
+                                return false;
+                            }
+                        };
 
                         let mut img = photon_image.clone();
                         info!("threshold value = {}", state.threshold_value);
@@ -127,6 +150,11 @@ impl Component for ThresholdImage {
                 }
                 false
             }
+            Msg::Failed(error) => {
+                info!("could not show the thresholded image: {}", error);
+                self.error = Some(error);
+                true
+            }
         }
     }
 
@@ -141,9 +169,64 @@ impl Component for ThresholdImage {
                 });
 
                 let height_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, v: f64| state.stl_height = v);
+                let profile_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, e: Event| {
+                    let select: HtmlSelectElement = e.target_unchecked_into();
+                    if let Some(profile) = EdgeProfile::from_name(&select.value(), state.edge_radius) {
+                        state.edge_profile = profile;
+                    }
+                });
+                let radius_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, v: f64| {
+                    state.edge_radius = v;
+                    if let Some(profile) = EdgeProfile::from_name(state.edge_profile.name(), v) {
+                        state.edge_profile = profile;
+                    }
+                });
+                let line_art_onclick = self.dispatch.reduce_callback_with(|state: &mut GlobalState, _v| state.line_art = !state.line_art);
+                let line_width_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, v: f64| state.line_width = v);
+                let frame_inset_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, v: f64| state.border.inset = v);
+                let frame_width_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, v: f64| state.border.frame_width = v);
+                let marker_size_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, v: f64| state.border.marker_size = v);
+                // a mold and an engraved plate can not be made at once, turning one on turns the other off
+                let mold_onclick = self.dispatch.reduce_callback_with(|state: &mut GlobalState, _v| {
+                    state.mold_enabled = !state.mold_enabled;
+                    state.engrave_enabled &= !state.mold_enabled;
+                });
+                let mold_kind_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, e: Event| {
+                    let select: HtmlSelectElement = e.target_unchecked_into();
+                    if let Some(kind) = MoldKind::from_name(&select.value()) {
+                        state.mold.kind = kind;
+                    }
+                });
+                let draft_angle_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, v: f64| state.mold.draft_angle = v);
+                let mold_base_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, v: f64| state.mold.base_thickness = v);
+                let vent_diameter_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, v: f64| state.mold.vent_diameter = v);
+                let vent_spacing_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, v: f64| state.mold.vent_spacing = v);
+                let engrave_onclick = self.dispatch.reduce_callback_with(|state: &mut GlobalState, _v| {
+                    state.engrave_enabled = !state.engrave_enabled;
+                    state.mold_enabled &= !state.engrave_enabled;
+                });
+                let floor_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, v: f64| state.engrave.floor_thickness = v);
+                let plate_margin_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, v: f64| state.engrave.margin = v);
+                let hollow_onclick = self.dispatch.reduce_callback_with(|state: &mut GlobalState, _v| state.hollow_enabled = !state.hollow_enabled);
+                let wall_width_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, v: f64| state.hollow.wall_width = v);
+                let join_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, e: Event| {
+                    let select: HtmlSelectElement = e.target_unchecked_into();
+                    if let Some(join) = OffsetJoin::from_name(&select.value()) {
+                        state.hollow.join = join;
+                    }
+                });
+                // "flat" leaves the inside of the walls empty
+                let fill_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, e: Event| {
+                    let select: HtmlSelectElement = e.target_unchecked_into();
+                    state.hollow.fill = Texture::from_name(&select.value());
+                });
+                let fill_height_onchange = self.dispatch.reduce_callback_with(|state: &mut GlobalState, v: f64| state.hollow.fill_height = v);
                 let onclick = self.dispatch.reduce_callback_with(|state: &mut GlobalState, _v| state.display_stl = !state.display_stl);
                 html! {
                     <div>
+                        if let Some(error) = &self.error {
+                            <p class="error">{ error }</p>
+                        }
                         <canvas ref={ self.canvas_ref.clone() } />
                         <Slider label="Threshold Value"
                             min=1.0
@@ -159,6 +242,142 @@ impl Component for ThresholdImage {
                             onchange={height_onchange}
                             value={ state.stl_height }
                         />
+                        <label for="edge-profile">{ "Edge profile" }</label>
+                        <select id="edge-profile" onchange={profile_onchange}>
+                            { for EdgeProfile::NAMES.iter().map(|name| html! {
+                                <option value={ *name } selected={ state.edge_profile.name() == *name }>{ name }</option>
+                            }) }
+                        </select>
+                        <NumberInput label="Edge radius"
+                            min=0.0
+                            max=200.0
+                            onchange={radius_onchange}
+                            value={ state.edge_radius }
+                        />
+                        <label for="line-art">{ "Line drawing" }</label>
+                        <input type="checkbox"
+                            id="line-art"
+                            checked={ state.line_art }
+                            onclick={line_art_onclick}
+                        />
+                        if state.line_art {
+                            <NumberInput label="Line width"
+                                min=1.0
+                                max=200.0
+                                onchange={line_width_onchange}
+                                value={ state.line_width }
+                            />
+                        }
+                        <NumberInput label="Frame width"
+                            min=0.0
+                            max=500.0
+                            onchange={frame_width_onchange}
+                            value={ state.border.frame_width }
+                        />
+                        <NumberInput label="Frame inset"
+                            min=0.0
+                            max=500.0
+                            onchange={frame_inset_onchange}
+                            value={ state.border.inset }
+                        />
+                        <NumberInput label="Corner marker size"
+                            min=0.0
+                            max=500.0
+                            onchange={marker_size_onchange}
+                            value={ state.border.marker_size }
+                        />
+                        <label for="mold">{ "Thermoform mold" }</label>
+                        <input type="checkbox"
+                            id="mold"
+                            checked={ state.mold_enabled }
+                            onclick={mold_onclick}
+                        />
+                        if state.mold_enabled {
+                            <select id="mold-kind" onchange={mold_kind_onchange}>
+                                { for MoldKind::NAMES.iter().map(|name| html! {
+                                    <option value={ *name } selected={ state.mold.kind.name() == *name }>{ name }</option>
+                                }) }
+                            </select>
+                            <NumberInput label="Draft angle"
+                                min=0.0
+                                max=45.0
+                                onchange={draft_angle_onchange}
+                                value={ state.mold.draft_angle }
+                            />
+                            <NumberInput label="Mold base thickness"
+                                min=0.0
+                                max=500.0
+                                onchange={mold_base_onchange}
+                                value={ state.mold.base_thickness }
+                            />
+                            <NumberInput label="Vent diameter"
+                                min=0.0
+                                max=100.0
+                                onchange={vent_diameter_onchange}
+                                value={ state.mold.vent_diameter }
+                            />
+                            <NumberInput label="Vent spacing"
+                                min=0.0
+                                max=2000.0
+                                onchange={vent_spacing_onchange}
+                                value={ state.mold.vent_spacing }
+                            />
+                        }
+                        <label for="engrave">{ "Engrave into a plate" }</label>
+                        <input type="checkbox"
+                            id="engrave"
+                            checked={ state.engrave_enabled }
+                            onclick={engrave_onclick}
+                        />
+                        if state.engrave_enabled {
+                            <NumberInput label="Floor thickness"
+                                min=0.0
+                                max=500.0
+                                onchange={floor_onchange}
+                                value={ state.engrave.floor_thickness }
+                            />
+                            <NumberInput label="Plate margin"
+                                min=0.0
+                                max=500.0
+                                onchange={plate_margin_onchange}
+                                value={ state.engrave.margin }
+                            />
+                        }
+                        <label for="hollow">{ "Raise only the walls" }</label>
+                        <input type="checkbox"
+                            id="hollow"
+                            checked={ state.hollow_enabled }
+                            onclick={hollow_onclick}
+                        />
+                        if state.hollow_enabled {
+                            <NumberInput label="Wall width"
+                                min=0.0
+                                max=500.0
+                                onchange={wall_width_onchange}
+                                value={ state.hollow.wall_width }
+                            />
+                            <label for="hollow-join">{ "Wall corners" }</label>
+                            <select id="hollow-join" onchange={join_onchange}>
+                                { for OffsetJoin::NAMES.iter().map(|name| html! {
+                                    <option value={ *name } selected={ state.hollow.join.name() == *name }>{ name }</option>
+                                }) }
+                            </select>
+                            <label for="hollow-fill">{ "Fill" }</label>
+                            <select id="hollow-fill" onchange={fill_onchange}>
+                                <option value="flat" selected={ state.hollow.fill.is_none() }>{ "flat" }</option>
+                                { for Texture::NAMES.iter().map(|name| html! {
+                                    <option value={ *name } selected={ state.hollow.fill.map(|t| t.name()) == Some(*name) }>{ name }</option>
+                                }) }
+                            </select>
+                            if state.hollow.fill.is_some() {
+                                <NumberInput label="Fill height"
+                                    min=0.0
+                                    max=1.0
+                                    onchange={fill_height_onchange}
+                                    value={ state.hollow.fill_height }
+                                />
+                            }
+                        }
                         <p> { "Choose a good threshold value" } </p>
                         <button
                             class={ "convert-to-stl" }
@@ -9,36 +9,52 @@ use super::external::canvas_from_image;
 use super::image::data_url_from_img_bytes;
 
 use log::info;
+use std::cell::RefCell;
 
 use wasm_bindgen::JsCast;
 
 
+/// Gives what was made for the same key last time, or makes it again when the key changed.
+/// For work too slow to redo on every render, with the cache from `use_mut_ref`
+pub fn cached<K: PartialEq, V: Clone>(
+    cache: &RefCell<Option<(K, V)>>,
+    key: K,
+    make: impl FnOnce(&K) -> V,
+) -> V {
+    let mut cache = cache.borrow_mut();
+    match &*cache {
+        Some((k, v)) if *k == key => v.clone(),
+        _ => {
+            let v = make(&key);
+            *cache = Some((key, v.clone()));
+            v
+        }
+    }
+}
+
+
 /// Faster than reading bytes to with photon_rs::native::open_image. but async
-pub async fn photon_image_from(data: &Vec<u8>) -> PhotonImage {
-    let durl = data_url_from_img_bytes(data).unwrap();
+pub async fn photon_image_from(data: &Vec<u8>) -> Result<PhotonImage, String> {
+    let durl = data_url_from_img_bytes(data).map_err(|e| e.to_string())?;
     let canvas = canvas_from_image(&durl)
         .await
         .dyn_into::<HtmlCanvasElement>()
-        .unwrap();
+        .map_err(|_| "the image could not be drawn on a canvas".to_string())?;
 
-    let ctx = canvas
-        .get_context("2d")
-        .unwrap()
-        .unwrap()
-        .dyn_into::<web_sys::CanvasRenderingContext2d>()
-        .unwrap();
-    info!("unwraped the context");
+    let ctx = context_from_canvas(&canvas)?;
+    info!("got the context");
 
-    photon_rs::open_image(canvas, ctx)
+    Ok(photon_rs::open_image(canvas, ctx))
 }
 
-pub fn context_from_canvas(canvas: &HtmlCanvasElement) -> CanvasRenderingContext2d {
+/// the 2d context of the canvas, or a message when the browser gives none
+pub fn context_from_canvas(canvas: &HtmlCanvasElement) -> Result<CanvasRenderingContext2d, String> {
         canvas
             .get_context("2d")
-            .unwrap()
-            .unwrap()
-            .dyn_into::<web_sys::CanvasRenderingContext2d>()
-            .unwrap()
+            .ok()
+            .flatten()
+            .and_then(|ctx| ctx.dyn_into::<web_sys::CanvasRenderingContext2d>().ok())
+            .ok_or_else(|| "the canvas has no 2d context".to_string())
 }
 pub fn threshold_canvas(
     canvas: HtmlCanvasElement,
@@ -46,7 +62,13 @@ pub fn threshold_canvas(
     ) {
     wasm_bindgen_futures::spawn_local(async move {
         info!("from draw_data_to_canvas");
-        let ctx = context_from_canvas(&canvas);
+        let ctx = match context_from_canvas(&canvas) {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                info!("could not threshold the canvas: {}", e);
+                return;
+            }
+        };
 
         let mut new_image = timeit!(
             "reading canvas into photon image took {}",
@@ -101,6 +123,13 @@ pub fn draw_data_to_canvas(
             photon_image_from(&data.to_vec()).await
             }
         );
+        let new_image = match new_image {
+            Ok(img) => img,
+            Err(e) => {
+                info!("could not draw the image: {}", e);
+                return;
+            }
+        };
 
         //let new_image = if new_image.get_width() > VIEW_WIDTH_PX {
 
@@ -125,13 +154,13 @@ pub fn draw_data_to_canvas(
         canvas.set_width(new_image.get_width());
         canvas.set_height(new_image.get_height());
 
-        let ctx = canvas
-            .get_context("2d")
-            .unwrap()
-            .unwrap()
-            .dyn_into::<web_sys::CanvasRenderingContext2d>()
-            .unwrap();
-
+        let ctx = match context_from_canvas(&canvas) {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                info!("could not draw the image: {}", e);
+                return;
+            }
+        };
 
         timeit!(
             "photon_rs::putImageData took {}",
@@ -0,0 +1,407 @@
+use image::DynamicImage;
+use log::info;
+
+use crate::boolean::{boolean_edges, BooleanOp};
+use crate::braille::{braille_edges, BrailleDimensions};
+use crate::edge::Edge;
+use crate::error::ConversionError;
+use crate::font::PrintText;
+use crate::line_style::LineStyle;
+use crate::ribbon::EdgeProfile;
+use crate::shapes::{
+    arc_points, arrow, circle, dashes, rectangle, regular_polygon, ring_contains, stroke,
+};
+use crate::steps::extrude_levels;
+use crate::stl::write_stl_3d;
+use crate::tree::{sort_edges, Node};
+use crate::triangle::{image_to_edges, nodes_to_triangles, StlOptions};
+use crate::vert::Vert;
+
+/// number of sides used for circles
+static CIRCLE_SEGMENTS: usize = 48;
+/// number of pieces of a full turn of an arc
+static ARC_SEGMENTS: usize = 48;
+
+/// Closed edges raised together to one height
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shape {
+    pub edges: Vec<Edge>,
+    pub height: f64,
+    pub profile: EdgeProfile,
+}
+
+/// A plate to draw tactile graphics on, in millimeters with y going up from the lower left
+/// corner. Shapes can overlap each other and traced images, the ones that overlap or touch
+/// are joined and raised to the tallest of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drawing {
+    pub width: f64,
+    pub height: f64,
+    /// thickness of the plate under the shapes, no plate is made when this is zero
+    pub base_thickness: f64,
+    pub shapes: Vec<Shape>,
+}
+
+impl Drawing {
+    pub fn new(width: f64, height: f64, base_thickness: f64) -> Self {
+        Self {
+            width,
+            height,
+            base_thickness,
+            shapes: vec![],
+        }
+    }
+
+    /// add closed edges, with holes, raised with straight walls
+    pub fn shape(&mut self, edges: Vec<Edge>, height: f64) -> &mut Self {
+        self.shape_with_profile(edges, height, EdgeProfile::Vertical)
+    }
+
+    pub fn shape_with_profile(
+        &mut self,
+        edges: Vec<Edge>,
+        height: f64,
+        profile: EdgeProfile,
+    ) -> &mut Self {
+        // rings without three corners have no inside to raise
+        let edges: Vec<Edge> = edges.into_iter().filter(|e| e.len() > 3).collect();
+        if !edges.is_empty() {
+            self.shapes.push(Shape {
+                edges,
+                height,
+                profile,
+            });
+        }
+        self
+    }
+
+    pub fn circle(&mut self, x: f64, y: f64, radius: f64, height: f64) -> &mut Self {
+        self.shape(vec![circle(x, y, radius, CIRCLE_SEGMENTS, false)], height)
+    }
+
+    /// a rectangle with its lower left corner at (x, y)
+    pub fn rectangle(
+        &mut self,
+        x: f64,
+        y: f64,
+        width: f64,
+        rect_height: f64,
+        height: f64,
+    ) -> &mut Self {
+        self.shape(vec![rectangle(x, y, width, rect_height, false)], height)
+    }
+
+    /// see `regular_polygon`
+    pub fn regular_polygon(
+        &mut self,
+        x: f64,
+        y: f64,
+        radius: f64,
+        sides: usize,
+        rotation: f64,
+        height: f64,
+    ) -> &mut Self {
+        self.shape(
+            vec![regular_polygon(x, y, radius, sides, rotation, false)],
+            height,
+        )
+    }
+
+    /// a line through the points with square ends and mitred corners
+    pub fn polyline(&mut self, points: &[Vert], width: f64, height: f64) -> &mut Self {
+        self.shape(stroke(points, width).into_iter().collect(), height)
+    }
+
+    /// a line broken into dashes `dash` long with `gap` between them
+    pub fn dashed_line(
+        &mut self,
+        points: &[Vert],
+        width: f64,
+        dash: f64,
+        gap: f64,
+        height: f64,
+    ) -> Result<&mut Self, ConversionError> {
+        for piece in dashes(points, dash, gap)? {
+            self.polyline(&piece, width, height);
+        }
+        Ok(self)
+    }
+
+    /// a line through the points drawn in one of the line styles
+    pub fn line(
+        &mut self,
+        points: &[Vert],
+        style: &LineStyle,
+        height: f64,
+    ) -> Result<&mut Self, ConversionError> {
+        // pieces of a line can touch at sharp corners, so each one is raised on its own
+        for piece in style.line(points)? {
+            self.shape(vec![piece], height);
+        }
+        Ok(self)
+    }
+
+    /// a line following an open or closed edge, see `LineStyle::edge_line`
+    pub fn edge_line(
+        &mut self,
+        edge: &Edge,
+        style: &LineStyle,
+        height: f64,
+    ) -> Result<&mut Self, ConversionError> {
+        if edge.is_closed() {
+            Ok(self.shape(style.edge_line(edge)?, height))
+        } else {
+            self.line(&edge.iter().cloned().collect::<Vec<Vert>>(), style, height)
+        }
+    }
+
+    /// a curved line around the center from the start angle to the end angle, in degrees
+    /// counterclockwise from the right
+    pub fn arc(
+        &mut self,
+        center: &Vert,
+        radius: f64,
+        start: f64,
+        end: f64,
+        width: f64,
+        height: f64,
+    ) -> &mut Self {
+        let segments = ((end - start).abs() / 360. * ARC_SEGMENTS as f64).ceil() as usize;
+        self.polyline(
+            &arc_points(center.x, center.y, radius, start, end, segments),
+            width,
+            height,
+        )
+    }
+
+    /// see `arrow`
+    pub fn arrow(
+        &mut self,
+        from: &Vert,
+        to: &Vert,
+        width: f64,
+        head_length: f64,
+        head_width: f64,
+        height: f64,
+    ) -> &mut Self {
+        self.shape(
+            arrow(from, to, width, head_length, head_width)
+                .into_iter()
+                .collect(),
+            height,
+        )
+    }
+
+    /// domed braille dots, with (x, y) the top left corner of the first cell
+    pub fn braille(&mut self, text: &str, x: f64, y: f64, dims: &BrailleDimensions) -> &mut Self {
+        self.shape_with_profile(
+            braille_edges(text, x, y, dims),
+            dims.dot_height,
+            EdgeProfile::Dome,
+        )
+    }
+
+    /// raised print letters, with (x, y) the top left corner of the first letter
+    pub fn print(
+        &mut self,
+        text: &str,
+        x: f64,
+        y: f64,
+        style: &PrintText,
+    ) -> Result<&mut Self, ConversionError> {
+        Ok(self.shape(style.edges(text, x, y)?, style.height))
+    }
+
+    /// Trace an image like `image_to_stl_with_options` does and put it on the plate with its
+    /// lower left corner at `origin`, with each pixel `pixel_size` millimeters across.
+    /// Molds and engraving from the options are not used, the shapes are raised.
+    pub fn image(
+        &mut self,
+        img: DynamicImage,
+        threshold: u8,
+        origin: &Vert,
+        pixel_size: f64,
+        height: f64,
+        options: &StlOptions,
+    ) -> Result<&mut Self, ConversionError> {
+        let edges = image_to_edges(img, threshold, options)?
+            .into_iter()
+            .map(|mut e| {
+                e.scale(pixel_size);
+                e.translate(origin.x, origin.y);
+                e
+            })
+            .collect();
+        Ok(self.shape_with_profile(edges, height, options.profile))
+    }
+
+    /// the plate with every shape standing on it, as one closed solid
+    pub fn triangles(&self) -> Result<Vec<f64>, ConversionError> {
+        let base = self.base_thickness;
+        let plate = sort_edges(vec![rectangle(0., 0., self.width, self.height, false)])?;
+        let edges: Vec<Edge> = self
+            .shapes
+            .iter()
+            .flat_map(|s| s.edges.iter().cloned())
+            .collect();
+        let raised = boolean_edges(&edges, &[], BooleanOp::Union)?;
+        let points = self.inner_points()?;
+        let tris = extrude_levels(&plate, &raised, base, |node| {
+            points
+                .iter()
+                .filter(|(p, _)| node_contains(node, p))
+                .map(|(_, s)| s)
+                .fold(None, |top: Option<&Shape>, s| match top {
+                    Some(t) if t.height >= s.height => Some(t),
+                    _ => Some(s),
+                })
+                .map_or((base, EdgeProfile::Vertical), |s| {
+                    (base + s.height, s.profile)
+                })
+        })?;
+        info!("made drawing with {} shapes", self.shapes.len());
+        Ok(tris)
+    }
+
+    /// a point inside each polygon of each shape, to find the shapes a joined polygon is made of
+    fn inner_points(&self) -> Result<Vec<(Vert, &Shape)>, ConversionError> {
+        let mut points = vec![];
+        for shape in self.shapes.iter() {
+            for node in sort_edges(shape.edges.clone())? {
+                let tris = nodes_to_triangles(vec![node])?;
+                if let Some(t) = tris.chunks(6).next() {
+                    let p = Vert::new((t[0] + t[2] + t[4]) / 3., (t[1] + t[3] + t[5]) / 3.);
+                    points.push((p, shape));
+                }
+            }
+        }
+        Ok(points)
+    }
+
+    pub fn write_stl(&self, filename: &str) -> Result<(), ConversionError> {
+        write_stl_3d(filename, self.triangles()?)
+    }
+}
+
+/// inside the outline and none of the holes
+fn node_contains(node: &Node, p: &Vert) -> bool {
+    ring_contains(&node.data, p) && !node.children.iter().any(|c| ring_contains(&c.data, p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::is_watertight;
+    use image::{GrayImage, Luma};
+    use std::error::Error;
+
+    fn z_range(tris: &[f64]) -> (f64, f64) {
+        tris.chunks(3)
+            .map(|v| v[2])
+            .fold((f64::MAX, f64::MIN), |(lo, hi), z| (lo.min(z), hi.max(z)))
+    }
+
+    #[test]
+    fn test_drawing_primitives() -> Result<(), Box<dyn Error>> {
+        let mut drawing = Drawing::new(100., 100., 2.);
+        drawing
+            .circle(20., 20., 5., 1.)
+            .rectangle(40., 10., 10., 20., 1.)
+            .regular_polygon(70., 20., 6., 6, 0., 1.)
+            .polyline(&[Vert::new(10., 50.), Vert::new(50., 60.)], 1., 1.)
+            .dashed_line(&[Vert::new(10., 70.), Vert::new(90., 70.)], 1., 4., 2., 0.5)?
+            .arc(&Vert::new(50., 50.), 20., 0., 180., 1., 1.)
+            .arrow(&Vert::new(60., 90.), &Vert::new(90., 90.), 1., 4., 4., 1.)
+            .braille("ab", 10., 95., &BrailleDimensions::default())
+            .line(
+                &[Vert::new(10., 80.), Vert::new(90., 80.)],
+                &LineStyle::Double { width: 1., gap: 1. },
+                1.,
+            )?
+            .edge_line(
+                &rectangle(5., 5., 90., 90., false),
+                &LineStyle::Solid { width: 1. },
+                1.,
+            )?;
+        // the dashed line is 14 dashes and the double line is two lines
+        assert_eq!(drawing.shapes.len(), 7 + 14 + 2 + 1);
+        // only the frame around the plate has a hole
+        let holes = drawing
+            .shapes
+            .iter()
+            .flat_map(|s| s.edges.iter())
+            .filter(|e| e.is_hole())
+            .count();
+        assert_eq!(holes, 1);
+        let tris = drawing.triangles()?;
+        assert_eq!(tris.len() % 9, 0);
+        assert_eq!(z_range(&tris), (0., 3.));
+        assert!(is_watertight(&tris));
+        Ok(())
+    }
+
+    #[test]
+    fn test_shapes_without_length_are_skipped() -> Result<(), Box<dyn Error>> {
+        let mut drawing = Drawing::new(10., 10., 0.);
+        let p = Vert::new(1., 1.);
+        drawing
+            .polyline(&[p.clone()], 1., 1.)
+            .arrow(&p, &p, 1., 1., 1., 1.)
+            .regular_polygon(1., 1., 1., 2, 0., 1.)
+            .dashed_line(&[], 1., 1., 1., 1.)?;
+        assert!(drawing.shapes.is_empty());
+        assert!(drawing.dashed_line(&[p], 1., 0., 1., 1.).is_err());
+        assert!(drawing.triangles()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_on_the_plate() -> Result<(), Box<dyn Error>> {
+        let img = GrayImage::from_fn(20, 20, |x, y| {
+            if (5..15).contains(&x) && (5..15).contains(&y) {
+                Luma([0])
+            } else {
+                Luma([255])
+            }
+        });
+        let mut drawing = Drawing::new(100., 100., 1.);
+        drawing
+            .image(
+                img.into(),
+                128,
+                &Vert::new(30., 40.),
+                0.5,
+                2.,
+                &StlOptions::default(),
+            )?
+            .circle(10., 10., 3., 1.);
+        let image_shape = &drawing.shapes[0];
+        for v in image_shape.edges.iter().flat_map(|e| e.iter()) {
+            assert!(v.x >= 30. && v.x <= 40. && v.y >= 40. && v.y <= 50.);
+        }
+        let tris = drawing.triangles()?;
+        assert_eq!(z_range(&tris), (0., 3.));
+        assert!(is_watertight(&tris));
+        Ok(())
+    }
+
+    #[test]
+    fn test_overlapping_shapes_are_joined() -> Result<(), Box<dyn Error>> {
+        for base in [0., 1.] {
+            let mut drawing = Drawing::new(50., 50., base);
+            drawing
+                .rectangle(10., 10., 10., 10., 1.)
+                .rectangle(15., 15., 10., 10., 2.)
+                .circle(40., 40., 3., 0.5);
+            let tris = drawing.triangles()?;
+            assert!(is_watertight(&tris));
+            assert_eq!(z_range(&tris), (0., base + 2.));
+            // the circle on its own keeps its height
+            let circle_top = tris
+                .chunks(9)
+                .any(|t| t[2] == base + 0.5 && t[5] == base + 0.5 && t[8] == base + 0.5);
+            assert!(circle_top);
+        }
+        Ok(())
+    }
+}
@@ -2,8 +2,11 @@ use std::collections::vec_deque::{IntoIter, Iter};
 use std::collections::VecDeque;
 use std::ops::Index;
 
+use robust::Coord;
 use serde::{Deserialize, Serialize};
 
+use crate::error::ConversionError;
+use crate::shapes::signed_area;
 use crate::vert::Vert;
 
 #[derive(Debug, PartialEq, PartialOrd, Clone, Serialize, Deserialize)]
@@ -28,38 +31,22 @@ impl Edge {
         }
     }
 
+    /// grow the bounding box to take in a vertex
     pub fn maybe_set_extremes(&mut self, v: &Vert) {
-        if let Some(min_x) = self.min_x {
-            if v.x < min_x {
-                self.min_x = Some(min_x);
-            }
-        } else {
-            self.min_x = Some(v.x);
-        }
-
-        if let Some(max_x) = self.max_x {
-            if v.x < max_x {
-                self.max_x = Some(max_x);
-            }
-        } else {
-            self.max_x = Some(v.x);
-        }
-
-        if let Some(min_y) = self.min_y {
-            if v.y < min_y {
-                self.min_y = Some(min_y);
-            }
-        } else {
-            self.min_y = Some(v.y);
-        }
+        self.min_x = Some(self.min_x.map_or(v.x, |min_x| min_x.min(v.x)));
+        self.max_x = Some(self.max_x.map_or(v.x, |max_x| max_x.max(v.x)));
+        self.min_y = Some(self.min_y.map_or(v.y, |min_y| min_y.min(v.y)));
+        self.max_y = Some(self.max_y.map_or(v.y, |max_y| max_y.max(v.y)));
+    }
 
-        if let Some(max_y) = self.max_y {
-            if v.y < max_y {
-                self.max_y = Some(max_y);
-            }
-        } else {
-            self.max_y = Some(v.y);
+    /// the lower left and upper right corners of the box around the edge
+    pub fn bounding_box(&self) -> Option<([f64; 2], [f64; 2])> {
+        if let (Some(min_x), Some(max_x), Some(min_y), Some(max_y)) =
+            (self.min_x, self.max_x, self.min_y, self.max_y)
+        {
+            return Some(([min_x, min_y], [max_x, max_y]));
         }
+        None
     }
 
     pub fn front(&self) -> Option<&Vert> {
@@ -99,18 +86,35 @@ impl Edge {
     pub fn is_closed(&self) -> bool {
         self.verts[0] == self.verts[self.verts.len() - 1]
     }
-    pub fn set_is_hole(&mut self) {
-        if !self.is_closed() {
-            panic!("we can only determine if closed edges are holes or not");
-        }
-        self._is_hole = Some(is_hole(self));
+    /// work out and remember the winding, failing for edges that do not close around an area
+    pub fn set_is_hole(&mut self) -> Result<(), ConversionError> {
+        self._is_hole = Some(try_is_hole(self)?);
+        Ok(())
     }
+    /// record the winding when it is already known, like for generated shapes
+    pub fn mark_hole(&mut self, hole: bool) {
+        self._is_hole = Some(hole);
+    }
+    /// see `is_hole`
     pub fn is_hole(&self) -> bool {
-        if !self.is_closed() {
-            panic!("we can only determine if closed edges are holes or not");
-        }
         is_hole(self)
     }
+    /// scale every vertex about the origin
+    pub fn scale(&mut self, factor: f64) {
+        for v in self.verts.iter_mut() {
+            v.x *= factor;
+            v.y *= factor;
+        }
+        (self.min_x, self.max_x, self.min_y, self.max_y) =
+            get_extremes(self.verts.make_contiguous());
+    }
+    pub fn translate(&mut self, x: f64, y: f64) {
+        for v in self.verts.iter_mut() {
+            v.translate(x, y);
+        }
+        (self.min_x, self.max_x, self.min_y, self.max_y) =
+            get_extremes(self.verts.make_contiguous());
+    }
     pub fn possibly_contains(&self, v: &Vert) -> bool {
         if let (Some(min_x), Some(max_x), Some(min_y), Some(max_y)) =
             (self.min_x, self.max_x, self.min_y, self.max_y)
@@ -221,136 +225,111 @@ fn edge_to_segments<'a>(edge: &'a Edge) -> Box<dyn Iterator<Item = [&Vert; 2]> +
     )
 }
 
-fn get_upper_and_lower_bounds<'a>(
-    edge: &'a Edge,
-    target: &Vert,
-) -> (Option<[&'a Vert; 2]>, Option<[&'a Vert; 2]>) {
-    let segments = edge_to_segments(edge);
-    let xcross = segments.filter(|[before, after]| {
-        (before.x <= target.x && after.x >= target.x)
-            || (before.x >= target.x && after.x <= target.x)
-    });
-
-    // find the least upper bound and greatest lower bound
-    let (above, below) = xcross.fold((None, None), |acc, [before, after]| {
-        let current_y = y_value_at(target.x, [before, after]);
-
-        if current_y > target.y {
-            if let Some((lowest_y, _, _)) = acc.0 {
-                if current_y < lowest_y {
-                    (Some((current_y, before, after)), acc.1)
-                } else {
-                    acc
-                }
-            } else {
-                (Some((current_y, before, after)), acc.1)
+/// Twice the signed area of the triangle a, b, c, worked out exactly enough that the sign is
+/// always right. Positive when c is to the left of the line from a to b, zero when collinear.
+pub fn orient2d(a: &Vert, b: &Vert, c: &Vert) -> f64 {
+    robust::orient2d(
+        Coord { x: a.x, y: a.y },
+        Coord { x: b.x, y: b.y },
+        Coord { x: c.x, y: c.y },
+    )
+}
+
+/// How many times a closed edge winds counterclockwise around a point, negative for clockwise.
+/// None when the point is on the edge, where it is neither inside nor outside.
+pub fn winding_number(edge: &Edge, target: &Vert) -> Option<i32> {
+    let mut winding = 0;
+    for [a, b] in edge_to_segments(edge) {
+        if a == target {
+            return None;
+        }
+        if a.y == target.y && b.y == target.y {
+            if a.x.min(b.x) <= target.x && target.x <= a.x.max(b.x) {
+                return None;
+            }
+            continue;
+        }
+        // segments going up count their lower end and not their upper end, and the other
+        // way around going down, so a ray through a vertex is only counted once
+        if a.y <= target.y && b.y > target.y {
+            match orient2d(a, b, target) {
+                o if o > 0. => winding += 1,
+                o if o == 0. => return None,
+                _ => {}
             }
-        } else if let Some((highest, _, _)) = acc.1 {
-            if current_y > highest {
-                (acc.0, Some((current_y, before, after)))
-            } else {
-                acc
+        } else if a.y > target.y && b.y <= target.y {
+            match orient2d(a, b, target) {
+                o if o < 0. => winding -= 1,
+                o if o == 0. => return None,
+                _ => {}
             }
-        } else {
-            (acc.0, Some((current_y, before, after)))
         }
-    });
-    let mut out: (Option<[&Vert; 2]>, Option<[&Vert; 2]>) = (None, None);
-    if let Some((_, before, after)) = above {
-        out.0 = Some([before, after]);
-    }
-    if let Some((_, before, after)) = below {
-        out.1 = Some([before, after]);
     }
-    out
+    Some(winding)
 }
 
-// check a closed edge includes a point
+/// whether a point is inside a counterclockwise closed edge, points on the edge are not
 pub fn edge_contains(edge: &Edge, target: &Vert) -> bool {
-    // find the least upper bound and greatest lower bound
-    let (above, below) = get_upper_and_lower_bounds(edge, target);
-    // check the handedness is correct
-    if let Some([a_before, a_after]) = above {
-        if a_before.x > a_after.x {
-            // point is below least upper bound
-            if let Some([b_before, b_after]) = below {
-                if b_after.x > b_before.x {
-                    // point is abover greatest lower bound
-                    return true;
-                }
-            }
-        }
-    }
-    false
+    winding_number(edge, target).map_or(false, |w| w > 0)
 }
 
+/// whether a point is inside a clockwise closed edge, points on the edge are not
 pub fn hole_contains(edge: &Edge, target: &Vert) -> bool {
-    // find the least upper bound and greatest lower bound
-    let (above, below) = get_upper_and_lower_bounds(edge, target);
-    // check the handedness is correct
-    if let Some([a_before, a_after]) = above {
-        if a_before.x < a_after.x {
-            // point is below least upper bound
-            if let Some([b_before, b_after]) = below {
-                if b_after.x < b_before.x {
-                    // point is abover greatest lower bound
-                    return true;
-                }
-            }
-        }
-    }
-    false
+    winding_number(edge, target).map_or(false, |w| w < 0)
 }
 
-fn y_value_at(x: f64, segment: [&Vert; 2]) -> f64 {
-    let left = segment[0];
-    let right = segment[1];
-
-    if left.y == right.y {
-        return left.y;
-    }
-
-    if left.x == right.x {
-        return left.y; // left or right should not matter since this just selects this segment, which isn't crossed by another segment
-    }
-
-    // the above removes the div by zero case
-    let m = (right.y - left.y) / (right.x - left.x);
-    let b = left.y - (m * left.x);
-    m * x + b // = y
+/// Whether a closed edge is wound clockwise, like the holes traced by `edge_img`.
+/// Edges that `try_is_hole` can not decide on count as outlines.
+pub fn is_hole(edge: &Edge) -> bool {
+    try_is_hole(edge).unwrap_or(false)
 }
 
-pub fn is_hole(edge: &Edge) -> bool {
+/// Like `is_hole`, failing for edges that are not closed or do not enclose any area
+pub fn try_is_hole(edge: &Edge) -> Result<bool, ConversionError> {
     if let Some(result) = edge._is_hole {
-        return result;
-    }
-    let first_x_point = edge[0].x;
-    let next_x = edge
-        .iter()
-        .find(|vert| vert.x != first_x_point)
-        .expect("this is a closed edge so it must have some change in x")
-        .x;
-    let some_x_point = (first_x_point + next_x) / 2.;
-
-    let segments = edge_to_segments(edge);
-    let xcross = segments.filter(|[before, after]| {
-        (before.x < some_x_point && after.x > some_x_point)
-            || (before.x > some_x_point && after.x < some_x_point)
-    });
-
-    let lowest = xcross.reduce(|acc, segment| {
-        if y_value_at(some_x_point, segment) < y_value_at(some_x_point, acc) {
-            segment
-        } else {
-            acc
-        }
-    });
-
-    if let Some([before, after]) = lowest {
-        after.x - before.x <= 0.
+        return Ok(result);
+    }
+    if edge.len() < 4 || !edge.is_closed() {
+        return Err(ConversionError::DegenerateContour(format!(
+            "an outline with {} points starting at {:?} is not closed",
+            edge.len(),
+            edge.front()
+        )));
+    }
+    // the turn at the lowest of the leftmost points is the way the whole edge turns
+    let n = edge.len() - 1;
+    let lowest = (0..n)
+        .min_by(|&i, &j| {
+            edge[i]
+                .x
+                .total_cmp(&edge[j].x)
+                .then(edge[i].y.total_cmp(&edge[j].y))
+        })
+        .unwrap();
+    let before = (1..n)
+        .map(|k| &edge[(lowest + n - k) % n])
+        .find(|v| *v != &edge[lowest]);
+    let after = (1..n)
+        .map(|k| &edge[(lowest + k) % n])
+        .find(|v| *v != &edge[lowest]);
+    let turn = match (before, after) {
+        (Some(before), Some(after)) => orient2d(before, &edge[lowest], after),
+        _ => 0.,
+    };
+    // a spike at that point has no turn, so fall back on the area
+    let orientation = if turn != 0. {
+        turn
     } else {
-        panic!("edges should always have two points so this should never happen");
-    }
+        let points: Vec<Vert> = edge.iter().take(n).cloned().collect();
+        signed_area(&points)
+    };
+    if orientation == 0. {
+        return Err(ConversionError::DegenerateContour(format!(
+            "the outline starting at {:?} does not enclose any area",
+            edge.front()
+        )));
+    }
+    Ok(orientation < 0.)
 }
 
 #[cfg(test)]
@@ -374,6 +353,24 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_extremes_grow() {
+        let mut e = Edge::from(vec![Vert::new(1., 1.)]);
+        e.push_front(Vert::new(3., -2.));
+        e.append(&mut Edge::from(vec![Vert::new(-1., 4.)]));
+        assert_eq!(e.bounding_box(), Some(([-1., -2.], [3., 4.])));
+        assert!(e.possibly_contains(&Vert::new(2.5, 3.5)));
+    }
+
+    #[test]
+    fn test_scale_and_translate() {
+        let mut e = closed_edge(10., 0., 0., false);
+        e.scale(0.5);
+        e.translate(1., 2.);
+        assert_eq!(e[2], Vert::new(6., 7.));
+        assert_eq!((e.min_x, e.max_y), (Some(1.), Some(7.)));
+    }
+
     #[test]
     fn test_edge_contains_broken() -> Result<(), Box<dyn Error>> {
         let child: Edge = serde_json::from_value(read_file_to_value(
@@ -448,4 +445,117 @@ mod tests {
 
         assert_eq!(hole_contains(&e, &Vert::new(10., 0.5)), false);
     }
+
+    /// a counterclockwise hexagon with pointed sides and a flat top and bottom
+    fn hexagon() -> Edge {
+        Edge::from(vec![
+            Vert::new(1., 0.),
+            Vert::new(2., 0.),
+            Vert::new(3., 1.),
+            Vert::new(2., 2.),
+            Vert::new(1., 2.),
+            Vert::new(0., 1.),
+            Vert::new(1., 0.),
+        ])
+    }
+
+    #[test]
+    fn test_winding_number_on_the_edge() {
+        let e = hexagon();
+        // corners, the middle of sloped sides and the middle of flat sides
+        for v in [
+            (1., 0.),
+            (3., 1.),
+            (0., 1.),
+            (0.5, 0.5),
+            (2.5, 1.5),
+            (1.5, 0.),
+            (1.5, 2.),
+        ] {
+            let v = Vert::new(v.0, v.1);
+            assert_eq!(winding_number(&e, &v), None, "{:?} is on the edge", v);
+            assert!(!edge_contains(&e, &v));
+        }
+    }
+
+    #[test]
+    fn test_winding_number_level_with_corners() {
+        let e = hexagon();
+        // rays from these points go through corners and along the flat sides
+        for (v, inside) in [
+            ((0.5, 1.), true),
+            ((2.9, 1.), true),
+            ((-1., 1.), false),
+            ((4., 1.), false),
+            ((-1., 0.), false),
+            ((5., 2.), false),
+            ((1.5, 1.999), true),
+            ((1.5, 0.001), true),
+        ] {
+            let v = Vert::new(v.0, v.1);
+            assert_eq!(edge_contains(&e, &v), inside, "{:?}", v);
+            assert!(!hole_contains(&e, &v));
+        }
+        let mut hole = e.clone();
+        hole.verts.make_contiguous().reverse();
+        assert_eq!(winding_number(&hole, &Vert::new(0.5, 1.)), Some(-1));
+        assert!(hole_contains(&hole, &Vert::new(0.5, 1.)));
+    }
+
+    #[test]
+    fn test_winding_number_collinear() {
+        // extra points along the sides of a square do not change anything
+        let e = Edge::from(vec![
+            Vert::new(0., 0.),
+            Vert::new(1., 0.),
+            Vert::new(2., 0.),
+            Vert::new(2., 1.),
+            Vert::new(2., 2.),
+            Vert::new(0., 2.),
+            Vert::new(0., 1.),
+            Vert::new(0., 0.),
+        ]);
+        assert_eq!(winding_number(&e, &Vert::new(1., 1.)), Some(1));
+        assert_eq!(winding_number(&e, &Vert::new(3., 1.)), Some(0));
+        assert_eq!(winding_number(&e, &Vert::new(2., 1.5)), None);
+        // a point just off a long thin sloped side is on the right side of it
+        let thin = Edge::from(vec![
+            Vert::new(0., 0.),
+            Vert::new(1e6, 1.),
+            Vert::new(0., 1.),
+            Vert::new(0., 0.),
+        ]);
+        assert!(edge_contains(&thin, &Vert::new(5e5, 0.5 + 1e-9)));
+        assert!(!edge_contains(&thin, &Vert::new(5e5, 0.5 - 1e-9)));
+        assert_eq!(winding_number(&thin, &Vert::new(5e5, 0.5)), None);
+    }
+
+    #[test]
+    fn test_orientation() {
+        assert!(!is_hole(&hexagon()));
+        // the lowest left corner has a spike coming out of it
+        let spiky = Edge::from(vec![
+            Vert::new(0., 0.),
+            Vert::new(2., 0.),
+            Vert::new(2., 2.),
+            Vert::new(0., 2.),
+            Vert::new(0., 0.),
+            Vert::new(0., 1.),
+            Vert::new(0., 0.),
+        ]);
+        assert!(matches!(try_is_hole(&spiky), Ok(false)));
+        let flat = Edge::from(vec![
+            Vert::new(0., 0.),
+            Vert::new(1., 1.),
+            Vert::new(2., 2.),
+            Vert::new(0., 0.),
+        ]);
+        assert!(try_is_hole(&flat).is_err());
+        let open = Edge::from(vec![
+            Vert::new(0., 0.),
+            Vert::new(1., 0.),
+            Vert::new(1., 1.),
+        ]);
+        assert!(try_is_hole(&open).is_err());
+    }
 }
@@ -1,9 +1,20 @@
-use crate::{vert::Vert, edge::Edge};
+use std::collections::HashMap;
+
+use crate::{vert::Vert, edge::Edge, error::ConversionError};
 
 #[derive(Debug)]
 pub struct Edges {
     pub edges: Vec<Edge>,
     pub closed_edges: Vec<Edge>,
+    /// The open edge with an end at each point, so joining an edge does not look at all of them.
+    /// A point on the grid is shared by two cells, so only one open edge can be waiting there.
+    ends: HashMap<(i64, i64), usize>,
+}
+
+/// Where a vertex is on the half pixel grid the marching squares vertices are all on,
+/// which unlike the float coordinates can be hashed
+fn lattice_key(v: &Vert) -> (i64, i64) {
+    ((v.x * 2.).round() as i64, (v.y * 2.).round() as i64)
 }
 
 /// check if two edge tips have a redundant vertex when combining them
@@ -24,14 +35,14 @@ fn tip_redundant(left: &Vert, middle: &Vert, right: &Vert) -> bool {
 }
 
 /// Connect other edge to target edge
-fn connect(target: &mut Edge, mut other: Edge) {
+fn connect(target: &mut Edge, mut other: Edge) -> Result<(), ConversionError> {
     let target_front = target.front();
     let target_back = target.back();
 
     let other_front = other.front();
     let other_back = other.back();
 
-    return match (target_front, target_back) {
+    match (target_front, target_back) {
         _ if (target_back == other_front && target_front == other_back) => {
             other.pop_front();
             let l = target.len();
@@ -90,16 +101,19 @@ fn connect(target: &mut Edge, mut other: Edge) {
                 target.push_front(v);
             }
         }
-        _ => panic!(
-            "No edges lined up, can't connect
+        _ => {
+            return Err(ConversionError::DegenerateContour(format!(
+                "no edges lined up, can't connect
                     target_front = {:?}
                     target_back = {:?}
                     other_front = {:?}
                     other_back = {:?}
                     ",
-            target_front, target_back, other_front, other_back
-        ),
+                target_front, target_back, other_front, other_back
+            )))
+        }
     };
+    Ok(())
 }
 
 impl Default for Edges {
@@ -113,87 +127,131 @@ impl Edges {
         Self {
             edges: vec![],
             closed_edges: vec![],
+            ends: HashMap::new(),
         }
     }
 
-    /// handle when an edge connects to itself
-    pub fn mark_closed(&mut self, edge_i: usize) {
-        let mut e = self.edges.remove(edge_i);
-        if e[0] != e[e.len() - 1] {
-            panic!("tail and head should match!");
+    /// edges traced elsewhere, with the open ones ready to be joined to more pieces
+    pub fn from_traced(edges: Vec<Edge>, closed_edges: Vec<Edge>) -> Self {
+        let mut out = Self {
+            edges,
+            closed_edges,
+            ends: HashMap::new(),
+        };
+        for edge_i in 0..out.edges.len() {
+            out.index_ends(edge_i);
+        }
+        out
+    }
+
+    fn end_keys(&self, edge_i: usize) -> [(i64, i64); 2] {
+        let e = &self.edges[edge_i];
+        [lattice_key(&e[0]), lattice_key(&e[e.len() - 1])]
+    }
+
+    fn index_ends(&mut self, edge_i: usize) {
+        for key in self.end_keys(edge_i) {
+            self.ends.insert(key, edge_i);
+        }
+    }
+
+    fn unindex_ends(&mut self, edge_i: usize) {
+        for key in self.end_keys(edge_i) {
+            if self.ends.get(&key) == Some(&edge_i) {
+                self.ends.remove(&key);
+            }
+        }
+    }
+
+    /// take out an open edge, the last open edge takes its place
+    fn remove_edge(&mut self, edge_i: usize) -> Edge {
+        let last = self.edges.len() - 1;
+        self.unindex_ends(edge_i);
+        if edge_i != last {
+            self.unindex_ends(last);
         }
+        let e = self.edges.swap_remove(edge_i);
+        if edge_i != last {
+            self.index_ends(edge_i);
+        }
+        e
+    }
 
-        e.set_is_hole();
+    /// handle when an edge connects to itself
+    pub fn mark_closed(&mut self, edge_i: usize) -> Result<(), ConversionError> {
+        let mut e = self.remove_edge(edge_i);
+        e.set_is_hole()?;
 
         self.closed_edges.push(e);
+        Ok(())
+    }
+
+    /// join the new edge to the one or two open edges it shares an end with
+    fn join(
+        &mut self,
+        target_i: usize,
+        x: Edge,
+        other: Option<Edge>,
+    ) -> Result<(), ConversionError> {
+        self.unindex_ends(target_i);
+        connect(&mut self.edges[target_i], x)?;
+        if let Some(other) = other {
+            connect(&mut self.edges[target_i], other)?;
+        }
+        self.index_ends(target_i);
+        let edge = &self.edges[target_i];
+        if edge[0] == edge[edge.len() - 1] {
+            self.mark_closed(target_i)?;
+        }
+        Ok(())
     }
 
     /// a GeoJSON Polygon is composed of "linear rings" which must have the same first and last
     /// value
     /// https://datatracker.ietf.org/doc/html/rfc7946#section-3.1.6
-    pub fn add_edge(&mut self, x: Edge) {
+    pub fn add_edge(&mut self, x: Edge) -> Result<(), ConversionError> {
         let other_back = x.back();
         let other_front = x.front();
 
         if other_back == other_front {
             self.closed_edges.push(x);
-            return;
-        }
-        let mut i = 0;
-        let mut found: Vec<usize> = vec![];
-
-        while i < self.edges.len() {
-            let target_front = self.edges[i].front();
-            let target_back = self.edges[i].back();
-
-            if other_front == target_front
-                || other_front == target_back
-                || other_back == target_front
-                || other_back == target_back
-            {
-                found.push(i);
-                if found.len() == 2 {
-                    break;
-                }
-            }
-            i += 1;
+            return Ok(());
         }
+        let front_i = other_front.and_then(|v| self.ends.get(&lattice_key(v)).copied());
+        let back_i = other_back.and_then(|v| self.ends.get(&lattice_key(v)).copied());
 
-        match found.len() {
-            0 => self.edges.push(x),
-            1 => {
-                connect(&mut self.edges[found[0]], x);
-                let edge = &self.edges[found[0]];
-                if edge[0] == edge[edge.len() - 1] {
-                    self.mark_closed(found[0]);
-                }
+        match (front_i, back_i) {
+            (None, None) => {
+                self.edges.push(x);
+                self.index_ends(self.edges.len() - 1);
             }
-            2 => {
-                connect(&mut self.edges[found[0]], x);
-                let other = self.edges.remove(found[1]);
-                connect(&mut self.edges[found[0]], other);
-                let edge = &self.edges[found[0]];
-                if edge[0] == edge[edge.len() - 1] {
-                    self.mark_closed(found[1]);
-                }
+            (Some(target), None) | (None, Some(target)) => self.join(target, x, None)?,
+            (Some(a), Some(b)) if a == b => self.join(a, x, None)?,
+            // the second edge is after the first one, so taking it out leaves the first in place
+            (Some(a), Some(b)) => {
+                let (target, other) = (a.min(b), a.max(b));
+                let other = self.remove_edge(other);
+                self.join(target, x, Some(other))?;
             }
-            _ => panic!("We should only have 0, 1, or 2 matched edges"),
         }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::error::Error;
 
     #[test]
-    fn test_add_edge_creates_closed_edge() {
+    fn test_add_edge_creates_closed_edge() -> Result<(), Box<dyn Error>> {
         let mut es = Edges::new();
-        es.add_edge(Edge::from([Vert::new(0., 0.), Vert::new(100., 0.)]));
-        es.add_edge(Edge::from([Vert::new(100., 0.), Vert::new(100., 100.)]));
-        es.add_edge(Edge::from([Vert::new(100., 100.), Vert::new(0., 100.)]));
-        es.add_edge(Edge::from([Vert::new(0., 100.), Vert::new(0., 0.)]));
+        es.add_edge(Edge::from([Vert::new(0., 0.), Vert::new(100., 0.)]))?;
+        es.add_edge(Edge::from([Vert::new(100., 0.), Vert::new(100., 100.)]))?;
+        es.add_edge(Edge::from([Vert::new(100., 100.), Vert::new(0., 100.)]))?;
+        es.add_edge(Edge::from([Vert::new(0., 100.), Vert::new(0., 0.)]))?;
         assert_eq!(es.closed_edges.len(), 1, "closed edge created");
+        Ok(())
     }
 
     #[test]
@@ -207,30 +265,64 @@ mod tests {
         //assert_eq!(e2, vec![vec![0., 0.], vec![0., 0.1]]);
     }
     #[test]
-    fn test_edges_add_edge() {
+    fn test_edges_add_edge() -> Result<(), Box<dyn Error>> {
         let e = Edge::from([Vert::new(0., 0.), Vert::new(0., 0.1)]);
         let mut es = Edges::new();
-        es.add_edge(e);
+        es.add_edge(e)?;
+        Ok(())
     }
     #[test]
-    fn test_edges_add_edge_back_to_front() {
+    fn test_edges_add_edge_back_to_front() -> Result<(), Box<dyn Error>> {
         let e = Edge::from([Vert::new(0., 0.), Vert::new(0., 1.)]);
         let i = Edge::from([Vert::new(0., 1.), Vert::new(2., 2.)]);
         let mut es = Edges::new();
-        es.add_edge(e);
-        es.add_edge(i);
+        es.add_edge(e)?;
+        es.add_edge(i)?;
         assert_eq!(es.edges.len(), 1);
         assert_eq!(es.edges[0].len(), 3);
+        Ok(())
     }
     #[test]
-    fn test_edges_add_edge_front_to_front() {
+    fn test_edges_add_edge_front_to_front() -> Result<(), Box<dyn Error>> {
         let e = Edge::from([Vert::new(0., 0.), Vert::new(0., 1.)]);
         let i = Edge::from([Vert::new(0., 0.), Vert::new(2., 2.)]);
         let mut es = Edges::new();
-        es.add_edge(e);
-        es.add_edge(i);
+        es.add_edge(e)?;
+        es.add_edge(i)?;
         assert_eq!(es.edges.len(), 1);
         assert_eq!(es.edges[0].len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_edges_joined_in_any_order() -> Result<(), Box<dyn Error>> {
+        // the sides of a row of squares, added side by side across all the squares
+        let mut es = Edges::new();
+        let corners = [(0., 0.), (1., 0.), (1., 1.), (0., 1.), (0., 0.)];
+        for side in [0, 2, 1, 3] {
+            for square in 0..10 {
+                let x = 2. * square as f64;
+                let (a, b) = (corners[side], corners[side + 1]);
+                es.add_edge(Edge::from([
+                    Vert::new(x + a.0, a.1),
+                    Vert::new(x + b.0, b.1),
+                ]))?;
+            }
+        }
+        assert_eq!(es.closed_edges.len(), 10);
+        assert!(es.edges.is_empty());
+        assert!(es.ends.is_empty(), "closed edges are not left in the index");
+        Ok(())
+    }
+
+    #[test]
+    fn test_mismatched_edges_are_an_error() {
+        let mut target = Edge::from([Vert::new(0., 0.), Vert::new(0., 1.)]);
+        let other = Edge::from([Vert::new(5., 5.), Vert::new(6., 6.)]);
+        assert!(matches!(
+            connect(&mut target, other),
+            Err(ConversionError::DegenerateContour(_))
+        ));
     }
 
 }
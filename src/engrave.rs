@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+
+use crate::edge::Edge;
+use crate::error::ConversionError;
+use crate::ribbon::EdgeProfile;
+use crate::shapes::{invert_edge, rectangle};
+use crate::steps::extrude_steps;
+use crate::tree::{sort_edges, tree_from_edges, Node};
+
+/// Cut the shapes into a plate instead of raising them.
+/// Sizes are in the same units as the height, which becomes the depth of the pockets.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EngraveOptions {
+    /// thickness of the plate under the bottom of the pockets
+    pub floor_thickness: f64,
+    /// how far the plate reaches past the image on every side
+    pub margin: f64,
+}
+
+impl Default for EngraveOptions {
+    fn default() -> Self {
+        Self {
+            floor_thickness: 20.,
+            margin: 0.,
+        }
+    }
+}
+
+/// the holes of an outline turned into islands, with the shapes inside those holes as
+/// their holes, all the way down the tree
+fn invert_holes(node: &Node) -> Vec<Node> {
+    node.children
+        .iter()
+        .flat_map(|hole| {
+            let island = Node {
+                data: invert_edge(&hole.data),
+                children: hole
+                    .children
+                    .iter()
+                    .map(|shape| Node {
+                        data: invert_edge(&shape.data),
+                        children: vec![],
+                    })
+                    .collect(),
+            };
+            std::iter::once(island).chain(hole.children.iter().flat_map(invert_holes))
+        })
+        .collect()
+}
+
+/// The surface of a plate with the shapes cut out of it, ready to earcut.
+/// Every level of the tree swaps between pocket floor and plate surface: the outermost
+/// shapes become holes in the plate, their holes become islands standing in the pockets,
+/// shapes inside those holes are pockets again, and so on.
+pub fn pocket_nodes(edges: Vec<Edge>, plate: Edge) -> Result<Vec<Node>, ConversionError> {
+    let roots = tree_from_edges(edges)?.into_roots();
+    let surface = Node {
+        data: plate,
+        children: roots
+            .iter()
+            .map(|shape| Node {
+                data: invert_edge(&shape.data),
+                children: vec![],
+            })
+            .collect(),
+    };
+    Ok(std::iter::once(surface)
+        .chain(roots.iter().flat_map(invert_holes))
+        .collect())
+}
+
+/// Cut the traced edges `depth` deep into a plate covering 0 to width and 0 to height,
+/// plus the margin. The profile shapes the rims of the pockets.
+pub fn engrave_triangles(
+    edges: Vec<Edge>,
+    width: f64,
+    height: f64,
+    depth: f64,
+    profile: EdgeProfile,
+    options: &EngraveOptions,
+) -> Result<Vec<f64>, ConversionError> {
+    let m = options.margin;
+    let plate = rectangle(-m, -m, width + 2. * m, height + 2. * m, false);
+    let nodes = pocket_nodes(edges, plate.clone())?;
+
+    let floor = options.floor_thickness;
+    extrude_steps(
+        &sort_edges(vec![plate])?,
+        &nodes,
+        floor,
+        floor + depth,
+        profile,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use crate::util::testing::{closed_edge, is_watertight};
+
+    #[test]
+    fn test_pocket_nodes_alternate() -> Result<(), Box<dyn Error>> {
+        // a square with a hole, and a smaller square standing in the hole
+        let edges = vec![
+            closed_edge(10., 45., 45., false),
+            closed_edge(30., 35., 35., true),
+            closed_edge(40., 30., 30., false),
+        ];
+        let nodes = pocket_nodes(edges, rectangle(0., 0., 100., 100., false))?;
+        assert_eq!(nodes.len(), 2);
+        // the plate with a pocket for the outer square
+        assert!(!nodes[0].data.is_hole());
+        assert_eq!(nodes[0].children.len(), 1);
+        assert!(nodes[0].children[0].data.is_hole());
+        // the hole of the square is an island, with a pocket for the small square
+        assert!(!nodes[1].data.is_hole());
+        assert_eq!(nodes[1].children.len(), 1);
+        assert!(nodes[1].children[0].data.is_hole());
+        Ok(())
+    }
+
+    #[test]
+    fn test_engrave_heights() -> Result<(), Box<dyn Error>> {
+        let options = EngraveOptions {
+            floor_thickness: 2.,
+            margin: 5.,
+        };
+        let edges = vec![closed_edge(10., 40., 40., false)];
+        let tris = engrave_triangles(edges, 100., 100., 3., EdgeProfile::Vertical, &options)?;
+        let zs: Vec<f64> = tris.chunks(3).map(|v| v[2]).collect();
+        assert_eq!(zs.iter().cloned().fold(f64::MIN, f64::max), 5.);
+        assert_eq!(zs.iter().cloned().fold(f64::MAX, f64::min), 0.);
+        let xs: Vec<f64> = tris.chunks(3).map(|v| v[0]).collect();
+        assert_eq!(xs.iter().cloned().fold(f64::MAX, f64::min), -5.);
+        assert!(is_watertight(&tris));
+        Ok(())
+    }
+}
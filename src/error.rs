@@ -0,0 +1,98 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use image::{ImageError, ImageFormat};
+
+/// What can go wrong turning an image into an stl
+#[derive(Debug)]
+pub enum ConversionError {
+    /// the image could not be read or decoded
+    Image(ImageError),
+    /// the image is in a format that can not be shown or converted
+    UnsupportedFormat(ImageFormat),
+    /// a traced outline that does not close, or is too thin to have an inside
+    DegenerateContour(String),
+    /// a polygon that could not be split into triangles
+    Triangulation(String),
+    Io(io::Error),
+    /// a region map or settings file that could not be read or written as json
+    Json(serde_json::Error),
+    /// options that can not be used, and why
+    InvalidOptions(String),
+    /// chart data or an expression that could not be read, and why
+    Parse(String),
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversionError::Image(e) => write!(f, "could not read the image: {}", e),
+            ConversionError::UnsupportedFormat(format) => write!(
+                f,
+                "{:?} images are not supported, use a png or jpeg image",
+                format
+            ),
+            ConversionError::DegenerateContour(why) => {
+                write!(f, "could not trace the outline of a shape: {}", why)
+            }
+            ConversionError::Triangulation(why) => {
+                write!(f, "could not split a shape into triangles: {}", why)
+            }
+            ConversionError::Io(e) => write!(f, "could not read or write a file: {}", e),
+            ConversionError::Json(e) => write!(f, "could not read or write the json: {}", e),
+            ConversionError::InvalidOptions(why) => {
+                write!(f, "the options can not be used: {}", why)
+            }
+            ConversionError::Parse(why) => write!(f, "could not read the text: {}", why),
+        }
+    }
+}
+
+impl Error for ConversionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConversionError::Image(e) => Some(e),
+            ConversionError::Io(e) => Some(e),
+            ConversionError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ImageError> for ConversionError {
+    fn from(e: ImageError) -> Self {
+        ConversionError::Image(e)
+    }
+}
+
+impl From<io::Error> for ConversionError {
+    fn from(e: io::Error) -> Self {
+        ConversionError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ConversionError {
+    fn from(e: serde_json::Error) -> Self {
+        ConversionError::Json(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages() {
+        let e = ConversionError::UnsupportedFormat(ImageFormat::Gif);
+        assert_eq!(
+            e.to_string(),
+            "Gif images are not supported, use a png or jpeg image"
+        );
+        let e: ConversionError = io::Error::new(io::ErrorKind::NotFound, "gone").into();
+        assert!(e.source().is_some());
+        // fits in with the functions that return any error
+        let boxed: Box<dyn Error> = ConversionError::Triangulation("no area".to_string()).into();
+        assert!(boxed.to_string().contains("no area"));
+    }
+}
@@ -0,0 +1,281 @@
+use image::{GrayImage, Luma};
+use serde::{Deserialize, Serialize};
+
+use crate::edge::Edge;
+use crate::error::ConversionError;
+use crate::edge_img;
+use crate::raster::draw_polyline;
+use crate::ribbon::EdgeProfile;
+use crate::triangle::{extrude_edges_with_profile, translate_triangles};
+use crate::vert::Vert;
+
+/// height of capital letters in glyph units
+static CAP_UNITS: f64 = 6.;
+/// distance from the start of one letter to the start of the next, in glyph units
+static ADVANCE_UNITS: f64 = 6.;
+/// pixels per millimeter used to draw the strokes before tracing them
+static PIXELS_PER_MM: f64 = 10.;
+
+type Stroke = &'static [(f64, f64)];
+
+/// The strokes of a character on a grid 4 units wide with capitals 6 units tall, y going up
+/// from the baseline. Lower case letters are drawn as capitals, which are easier to feel.
+#[rustfmt::skip]
+fn glyph(c: char) -> Option<&'static [Stroke]> {
+    let strokes: &'static [Stroke] = match c.to_ascii_uppercase() {
+        'A' => &[&[(0., 0.), (0., 4.), (2., 6.), (4., 4.), (4., 0.)], &[(0., 3.), (4., 3.)]],
+        'B' => &[
+            &[(0., 0.), (0., 6.), (3., 6.), (4., 5.), (4., 4.), (3., 3.), (0., 3.)],
+            &[(3., 3.), (4., 2.), (4., 1.), (3., 0.), (0., 0.)],
+        ],
+        'C' => &[&[(4., 5.), (3., 6.), (1., 6.), (0., 5.), (0., 1.), (1., 0.), (3., 0.), (4., 1.)]],
+        'D' => &[&[(0., 0.), (0., 6.), (2., 6.), (4., 4.), (4., 2.), (2., 0.), (0., 0.)]],
+        'E' => &[&[(4., 6.), (0., 6.), (0., 0.), (4., 0.)], &[(0., 3.), (3., 3.)]],
+        'F' => &[&[(4., 6.), (0., 6.), (0., 0.)], &[(0., 3.), (3., 3.)]],
+        'G' => &[&[(4., 5.), (3., 6.), (1., 6.), (0., 5.), (0., 1.), (1., 0.), (3., 0.), (4., 1.), (4., 3.), (2., 3.)]],
+        'H' => &[&[(0., 0.), (0., 6.)], &[(4., 0.), (4., 6.)], &[(0., 3.), (4., 3.)]],
+        'I' => &[&[(1., 6.), (3., 6.)], &[(2., 6.), (2., 0.)], &[(1., 0.), (3., 0.)]],
+        'J' => &[&[(4., 6.), (4., 1.), (3., 0.), (1., 0.), (0., 1.)]],
+        'K' => &[&[(0., 0.), (0., 6.)], &[(4., 6.), (0., 2.)], &[(1., 3.), (4., 0.)]],
+        'L' => &[&[(0., 6.), (0., 0.), (4., 0.)]],
+        'M' => &[&[(0., 0.), (0., 6.), (2., 3.), (4., 6.), (4., 0.)]],
+        'N' => &[&[(0., 0.), (0., 6.), (4., 0.), (4., 6.)]],
+        'O' => &[&[(1., 0.), (0., 1.), (0., 5.), (1., 6.), (3., 6.), (4., 5.), (4., 1.), (3., 0.), (1., 0.)]],
+        'P' => &[&[(0., 0.), (0., 6.), (3., 6.), (4., 5.), (4., 4.), (3., 3.), (0., 3.)]],
+        'Q' => &[
+            &[(1., 0.), (0., 1.), (0., 5.), (1., 6.), (3., 6.), (4., 5.), (4., 1.), (3., 0.), (1., 0.)],
+            &[(2., 2.), (4., 0.)],
+        ],
+        'R' => &[
+            &[(0., 0.), (0., 6.), (3., 6.), (4., 5.), (4., 4.), (3., 3.), (0., 3.)],
+            &[(2., 3.), (4., 0.)],
+        ],
+        'S' => &[&[
+            (4., 5.), (3., 6.), (1., 6.), (0., 5.), (0., 4.), (1., 3.),
+            (3., 3.), (4., 2.), (4., 1.), (3., 0.), (1., 0.), (0., 1.),
+        ]],
+        'T' => &[&[(0., 6.), (4., 6.)], &[(2., 6.), (2., 0.)]],
+        'U' => &[&[(0., 6.), (0., 1.), (1., 0.), (3., 0.), (4., 1.), (4., 6.)]],
+        'V' => &[&[(0., 6.), (2., 0.), (4., 6.)]],
+        'W' => &[&[(0., 6.), (1., 0.), (2., 3.), (3., 0.), (4., 6.)]],
+        'X' => &[&[(0., 0.), (4., 6.)], &[(0., 6.), (4., 0.)]],
+        'Y' => &[&[(0., 6.), (2., 3.), (4., 6.)], &[(2., 3.), (2., 0.)]],
+        'Z' => &[&[(0., 6.), (4., 6.), (0., 0.), (4., 0.)]],
+        '0' => &[
+            &[(1., 0.), (0., 1.), (0., 5.), (1., 6.), (3., 6.), (4., 5.), (4., 1.), (3., 0.), (1., 0.)],
+            &[(1., 1.5), (3., 4.5)],
+        ],
+        '1' => &[&[(1., 5.), (2., 6.), (2., 0.)], &[(1., 0.), (3., 0.)]],
+        '2' => &[&[(0., 5.), (1., 6.), (3., 6.), (4., 5.), (4., 4.), (0., 0.), (4., 0.)]],
+        '3' => &[
+            &[(0., 5.), (1., 6.), (3., 6.), (4., 5.), (4., 4.), (3., 3.), (2., 3.)],
+            &[(3., 3.), (4., 2.), (4., 1.), (3., 0.), (1., 0.), (0., 1.)],
+        ],
+        '4' => &[&[(3., 0.), (3., 6.), (0., 2.), (4., 2.)]],
+        '5' => &[&[(4., 6.), (0., 6.), (0., 3.), (3., 3.), (4., 2.), (4., 1.), (3., 0.), (1., 0.), (0., 1.)]],
+        '6' => &[&[
+            (4., 5.), (3., 6.), (1., 6.), (0., 5.), (0., 1.), (1., 0.),
+            (3., 0.), (4., 1.), (4., 2.), (3., 3.), (0., 3.),
+        ]],
+        '7' => &[&[(0., 6.), (4., 6.), (1., 0.)]],
+        '8' => &[
+            &[(1., 3.), (0., 4.), (0., 5.), (1., 6.), (3., 6.), (4., 5.), (4., 4.), (3., 3.), (1., 3.)],
+            &[(1., 3.), (0., 2.), (0., 1.), (1., 0.), (3., 0.), (4., 1.), (4., 2.), (3., 3.)],
+        ],
+        '9' => &[&[
+            (0., 1.), (1., 0.), (3., 0.), (4., 1.), (4., 5.), (3., 6.),
+            (1., 6.), (0., 5.), (0., 4.), (1., 3.), (4., 3.),
+        ]],
+        '.' => &[&[(2., 0.)]],
+        ',' => &[&[(2., 0.5), (1.5, -1.)]],
+        ':' => &[&[(2., 1.)], &[(2., 4.)]],
+        '!' => &[&[(2., 6.), (2., 2.)], &[(2., 0.)]],
+        '?' => &[&[(0., 5.), (1., 6.), (3., 6.), (4., 5.), (4., 4.), (2., 3.), (2., 2.)], &[(2., 0.)]],
+        '\'' => &[&[(2., 6.), (2., 4.5)]],
+        '-' => &[&[(1., 3.), (3., 3.)]],
+        '+' => &[&[(0.5, 3.), (3.5, 3.)], &[(2., 1.5), (2., 4.5)]],
+        '=' => &[&[(0.5, 2.), (3.5, 2.)], &[(0.5, 4.), (3.5, 4.)]],
+        '/' => &[&[(0., 0.), (4., 6.)]],
+        '(' => &[&[(3., 6.), (2., 5.), (2., 1.), (3., 0.)]],
+        ')' => &[&[(1., 6.), (2., 5.), (2., 1.), (1., 0.)]],
+        _ => return None,
+    };
+    Some(strokes)
+}
+
+/// Sizes of raised print text, in millimeters
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PrintText {
+    /// height of the capital letters, from the baseline to the top of the strokes' centers
+    pub size: f64,
+    pub stroke_width: f64,
+    /// how high the letters stand
+    pub height: f64,
+}
+
+impl Default for PrintText {
+    fn default() -> Self {
+        Self {
+            size: 10.,
+            stroke_width: 1.5,
+            height: 1.,
+        }
+    }
+}
+
+impl PrintText {
+    fn unit(&self) -> f64 {
+        self.size / CAP_UNITS
+    }
+
+    /// Width of a line of text, with every character taking up the width of a wide letter.
+    /// Characters without a glyph take up a space.
+    pub fn width(&self, text: &str) -> f64 {
+        let n = text.chars().count();
+        if n == 0 {
+            return 0.;
+        }
+        ((n - 1) as f64 * ADVANCE_UNITS + 4.) * self.unit() + self.stroke_width
+    }
+
+    /// height of a line of capitals, from the edge of the strokes at the top to those at the bottom
+    pub fn line_height(&self) -> f64 {
+        self.size + self.stroke_width
+    }
+
+    /// the center lines of the strokes, with the baseline of the first letter starting at (x, y)
+    fn strokes(&self, text: &str, x: f64, y: f64) -> Vec<Vec<Vert>> {
+        let unit = self.unit();
+        text.chars()
+            .enumerate()
+            .flat_map(|(i, c)| {
+                let left = x + i as f64 * ADVANCE_UNITS * unit;
+                glyph(c).unwrap_or(&[]).iter().map(move |stroke| {
+                    stroke
+                        .iter()
+                        .map(|(gx, gy)| Vert::new(left + gx * unit, y + gy * unit))
+                        .collect()
+                })
+            })
+            .collect()
+    }
+
+    /// Closed edges of the outlines of the text, ready for `sort_edges` and extrusion.
+    /// (x, y) is the top left corner of the first letter, with y going up, like for braille.
+    /// The strokes are drawn and traced, so crossing strokes come out as one shape.
+    pub fn edges(&self, text: &str, x: f64, y: f64) -> Result<Vec<Edge>, ConversionError> {
+        let radius = self.stroke_width / 2.;
+        let strokes = self.strokes(text, radius, radius + self.unit() * 1.5);
+        if strokes.is_empty() {
+            return Ok(vec![]);
+        }
+        // room for the comma below the baseline and a clear pixel all round
+        let below = self.unit() * 1.5;
+        let (w, h) = (self.width(text), self.line_height() + below);
+        let margin = 2.;
+        let to_px = |mm: f64| mm * PIXELS_PER_MM + margin;
+        let (px_w, px_h) = (
+            (w * PIXELS_PER_MM + 2. * margin).ceil() as u32 + 1,
+            (h * PIXELS_PER_MM + 2. * margin).ceil() as u32 + 1,
+        );
+        let mut img = GrayImage::from_pixel(px_w, px_h, Luma([255]));
+        for stroke in strokes {
+            // image rows go down, so flip the strokes over
+            let points: Vec<Vert> = stroke
+                .iter()
+                .map(|v| Vert::new(to_px(v.x), (px_h - 1) as f64 - to_px(v.y)))
+                .collect();
+            draw_polyline(&mut img, &points, self.stroke_width * PIXELS_PER_MM);
+        }
+        // traced edges have y going up from the bottom row, which is the same way as the strokes
+        let edges = edge_img(&img.into())?.closed_edges;
+        let top = y - self.line_height();
+        Ok(edges
+            .into_iter()
+            .map(|mut edge| {
+                edge.translate(-margin, -margin);
+                edge.scale(1. / PIXELS_PER_MM);
+                edge.translate(x, top - below);
+                edge
+            })
+            .collect())
+    }
+
+    /// Raised letters sitting on a surface at height z
+    pub fn triangles(
+        &self,
+        text: &str,
+        x: f64,
+        y: f64,
+        z: f64,
+    ) -> Result<Vec<f64>, ConversionError> {
+        let mut tris =
+            extrude_edges_with_profile(self.edges(text, x, y)?, self.height, EdgeProfile::Vertical)?;
+        translate_triangles(&mut tris, 0., 0., z);
+        Ok(tris)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use crate::tree::sort_edges;
+
+    fn bounds(edges: &[Edge]) -> (f64, f64, f64, f64) {
+        edges.iter().flat_map(|e| e.iter()).fold(
+            (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+            |(x0, y0, x1, y1), v| (x0.min(v.x), y0.min(v.y), x1.max(v.x), y1.max(v.y)),
+        )
+    }
+
+    #[test]
+    fn test_every_glyph_fits_the_grid() {
+        for c in ('A'..='Z').chain('0'..='9') {
+            let strokes = glyph(c).unwrap();
+            for (x, y) in strokes.iter().flat_map(|s| s.iter()) {
+                assert!(
+                    (0. ..=4.).contains(x) && (0. ..=CAP_UNITS).contains(y),
+                    "{}",
+                    c
+                );
+            }
+        }
+        assert_eq!(glyph('a'), glyph('A'));
+        assert!(glyph('~').is_none());
+    }
+
+    #[test]
+    fn test_text_size_in_mm() -> Result<(), Box<dyn Error>> {
+        let text = PrintText::default();
+        let edges = text.edges("HE", 10., 50.)?;
+        let (x0, y0, x1, y1) = bounds(&edges);
+        let tolerance = 2. / PIXELS_PER_MM;
+        assert!((x0 - 10.).abs() < tolerance);
+        assert!((x1 - 10. - text.width("HE")).abs() < tolerance);
+        assert!((y1 - 50.).abs() < tolerance);
+        assert!((y0 - 50. + text.line_height()).abs() < tolerance);
+        Ok(())
+    }
+
+    #[test]
+    fn test_crossing_strokes_are_one_shape() -> Result<(), Box<dyn Error>> {
+        let text = PrintText::default();
+        // the strokes of X cross, A has a hole
+        assert_eq!(sort_edges(text.edges("X", 0., 0.)?)?.len(), 1);
+        let a = sort_edges(text.edges("A", 0., 0.)?)?;
+        assert_eq!(a.len(), 1);
+        assert_eq!(a[0].children.len(), 1);
+        assert!(text.edges(" ", 0., 0.)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_triangles_height() -> Result<(), Box<dyn Error>> {
+        let text = PrintText::default();
+        let tris = text.triangles("1", 0., 0., 2.)?;
+        let max_z = tris.chunks(3).map(|v| v[2]).fold(f64::MIN, f64::max);
+        assert_eq!(max_z, 2. + text.height);
+        Ok(())
+    }
+}
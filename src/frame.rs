@@ -0,0 +1,183 @@
+use image::{DynamicImage, GenericImage, Rgba};
+use serde::{Deserialize, Serialize};
+
+use crate::edge::Edge;
+use crate::shapes::close_ring;
+use crate::vert::Vert;
+
+/// gap, in pixels, kept clear between the border and the traced image
+static BORDER_GAP: f64 = 1.;
+
+/// A raised frame around the graphic and a marker in the upper right corner so readers
+/// know which way is up. Sizes are in pixels of the image, like the traced edges.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BorderOptions {
+    /// distance from the edge of the image to the outside of the frame
+    pub inset: f64,
+    /// width of the raised frame, no frame is made when this is zero
+    pub frame_width: f64,
+    /// How much of the upper right corner is cut off, no marker is made when this is zero.
+    /// With a frame the corner of the frame is clipped, otherwise the marker is a
+    /// solid triangle in the corner.
+    pub marker_size: f64,
+}
+
+impl BorderOptions {
+    /// how far in from the edge of the image the border reaches
+    fn reach(&self) -> f64 {
+        self.inset + self.frame_width
+    }
+
+    /// how much is cut off the inside of the frame, keeping the diagonal as wide as the sides
+    fn inner_clip(&self) -> f64 {
+        if self.marker_size <= 0. {
+            return 0.;
+        }
+        (self.marker_size - self.frame_width * (2. - std::f64::consts::SQRT_2)).max(0.)
+    }
+}
+
+/// corners of a rectangle going counterclockwise from the lower left, with the upper right
+/// corner cut off by `clip`
+fn clipped_rectangle(x0: f64, y0: f64, x1: f64, y1: f64, clip: f64) -> Vec<Vert> {
+    let mut out = vec![Vert::new(x0, y0), Vert::new(x1, y0)];
+    if clip > 0. {
+        out.push(Vert::new(x1, y1 - clip));
+        out.push(Vert::new(x1 - clip, y1));
+    } else {
+        out.push(Vert::new(x1, y1));
+    }
+    out.push(Vert::new(x0, y1));
+    out
+}
+
+/// Create the closed edges of the border for an image of the given size.
+/// The coordinates match those of the edges from `edge_img`, where the image covers
+/// 0 to width - 1 and 0 to height - 1 with y going up, so the size must pass `check_traceable`.
+pub fn border_edges(image_width: u32, image_height: u32, options: &BorderOptions) -> Vec<Edge> {
+    let (x0, y0) = (options.inset, options.inset);
+    let x1 = (image_width - 1) as f64 - options.inset;
+    let y1 = (image_height - 1) as f64 - options.inset;
+    let w = options.frame_width;
+    let clip = options.marker_size;
+
+    if w > 0. {
+        let inner = clipped_rectangle(x0 + w, y0 + w, x1 - w, y1 - w, options.inner_clip());
+        vec![
+            close_ring(inner, true),
+            close_ring(clipped_rectangle(x0, y0, x1, y1, clip), false),
+        ]
+    } else if clip > 0. {
+        vec![close_ring(
+            vec![Vert::new(x1, y1), Vert::new(x1 - clip, y1), Vert::new(x1, y1 - clip)],
+            false,
+        )]
+    } else {
+        vec![]
+    }
+}
+
+/// Clear the parts of a thresholded image that the border covers,
+/// so the traced shapes never overlap the frame or the marker.
+pub fn clear_border(img: &mut DynamicImage, options: &BorderOptions) {
+    let (width, height) = (img.width(), img.height());
+    let white = Rgba([255, 255, 255, 0]);
+
+    let has_frame = options.frame_width > 0.;
+    let margin = if has_frame {
+        options.reach() + BORDER_GAP
+    } else {
+        0.
+    };
+    // Pixels past the diagonal of the marker, measured as the distance to the right
+    // plus the distance to the top of the image. Up in the edges is the top of the image.
+    let corner = match (has_frame, options.marker_size > 0.) {
+        (_, false) => 0.,
+        (true, true) => 2. * options.reach() + options.inner_clip() + BORDER_GAP,
+        (false, true) => 2. * options.inset + options.marker_size + BORDER_GAP,
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let (fx, fy) = (x as f64, y as f64);
+            let from_right = (width - 1) as f64 - fx;
+            let from_bottom = (height - 1) as f64 - fy;
+            let in_margin = fx.min(fy).min(from_right).min(from_bottom) < margin;
+            let in_corner = from_right + fy < corner;
+            if in_margin || in_corner {
+                img.put_pixel(x, y, white);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edge::{edge_contains, is_hole};
+    use image::GenericImageView;
+
+    #[test]
+    fn test_frame_with_clipped_corner() {
+        let options = BorderOptions {
+            inset: 2.,
+            frame_width: 3.,
+            marker_size: 10.,
+        };
+        let edges = border_edges(101, 101, &options);
+        assert_eq!(edges.len(), 2);
+        assert!(is_hole(&edges[0]));
+        assert!(!is_hole(&edges[1]));
+        assert_eq!(edges[1].len(), 6, "the outside has a clipped corner");
+        assert!(edge_contains(&edges[1], &Vert::new(3., 50.)), "on the frame");
+        assert!(!edge_contains(&edges[1], &Vert::new(97., 97.)), "in the clipped corner");
+    }
+
+    #[test]
+    fn test_marker_without_frame() {
+        let options = BorderOptions {
+            inset: 0.,
+            frame_width: 0.,
+            marker_size: 10.,
+        };
+        let edges = border_edges(101, 101, &options);
+        assert_eq!(edges.len(), 1);
+        assert!(!is_hole(&edges[0]));
+        assert!(edge_contains(&edges[0], &Vert::new(98., 98.)));
+    }
+
+    #[test]
+    fn test_no_border() {
+        assert!(border_edges(10, 10, &BorderOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_clear_border() {
+        let mut img = DynamicImage::new_rgba8(20, 20);
+        let options = BorderOptions {
+            inset: 1.,
+            frame_width: 2.,
+            marker_size: 0.,
+        };
+        clear_border(&mut img, &options);
+        assert_eq!(img.get_pixel(3, 10)[0], 255);
+        assert_eq!(img.get_pixel(4, 10)[0], 0);
+        assert_eq!(img.get_pixel(15, 10)[0], 0);
+        assert_eq!(img.get_pixel(16, 10)[0], 255);
+    }
+
+    #[test]
+    fn test_clear_marker_corner() {
+        let mut img = DynamicImage::new_rgba8(20, 20);
+        let options = BorderOptions {
+            inset: 0.,
+            frame_width: 0.,
+            marker_size: 5.,
+        };
+        clear_border(&mut img, &options);
+        assert_eq!(img.get_pixel(19, 0)[0], 255);
+        assert_eq!(img.get_pixel(17, 3)[0], 255);
+        assert_eq!(img.get_pixel(16, 3)[0], 0);
+        assert_eq!(img.get_pixel(0, 0)[0], 0);
+    }
+}
@@ -0,0 +1,191 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::boolean::{boolean_edges, difference, into_edges, union, BooleanOp};
+use crate::error::ConversionError;
+use crate::offset::{offset_nodes, OffsetJoin};
+use crate::ribbon::EdgeProfile;
+use crate::steps::extrude_steps;
+use crate::texture::Texture;
+use crate::tree::Node;
+use crate::triangle::extrude_nodes_with_profile;
+
+/// Raise only a wall along the outline and holes of each shape, instead of the whole shape.
+/// Sizes are in the same units as the height, before scaling.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HollowOptions {
+    /// how far the wall reaches in from the edges of the shape
+    pub wall_width: f64,
+    /// how the wall goes around the corners that point into the shape
+    pub join: OffsetJoin,
+    /// what is raised inside the wall, it is left flat when there is nothing
+    pub fill: Option<Texture>,
+    /// height of the fill as a part of the height of the wall
+    pub fill_height: f64,
+}
+
+impl Default for HollowOptions {
+    fn default() -> Self {
+        Self {
+            wall_width: 4.,
+            join: OffsetJoin::default(),
+            fill: None,
+            fill_height: 0.25,
+        }
+    }
+}
+
+/// The walls of the shapes and the fill inside them, both as polygons nested like the ones
+/// from `sort_edges`. Shapes thinner than two walls are all wall.
+pub fn hollow_nodes(
+    nodes: &[Node],
+    options: &HollowOptions,
+) -> Result<(Vec<Node>, Vec<Node>), ConversionError> {
+    let inside = offset_nodes(nodes, -options.wall_width, options.join)?;
+    let walls = difference(nodes, &inside)?;
+    let fill = match options.fill {
+        None => vec![],
+        Some(Texture::Solid) => inside,
+        Some(_) if inside.is_empty() => vec![],
+        Some(texture) => {
+            let edges = into_edges(inside);
+            let (x0, y0, x1, y1) = edges.iter().flat_map(|e| e.iter()).fold(
+                (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+                |(x0, y0, x1, y1), v| (x0.min(v.x), y0.min(v.y), x1.max(v.x), y1.max(v.y)),
+            );
+            let pattern = texture.pattern_edges(x0, y0, x1, y1);
+            boolean_edges(&edges, &pattern, BooleanOp::Intersection)?
+        }
+    };
+    info!(
+        "hollowed {} shapes into {} walls and {} fills",
+        nodes.len(),
+        walls.len(),
+        fill.len()
+    );
+    Ok((walls, fill))
+}
+
+/// Extrude the walls of the shapes to the height and the fill inside them to its part of it,
+/// as one solid. With a fill, the profile shapes the walls above the top of the fill.
+pub fn hollow_triangles(
+    nodes: &[Node],
+    height: f64,
+    profile: EdgeProfile,
+    options: &HollowOptions,
+) -> Result<Vec<f64>, ConversionError> {
+    let (walls, fill) = hollow_nodes(nodes, options)?;
+    if fill.is_empty() {
+        return extrude_nodes_with_profile(walls, height, profile);
+    }
+    let footprint = union(&walls, &fill)?;
+    extrude_steps(
+        &footprint,
+        &walls,
+        height * options.fill_height,
+        height,
+        profile,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edge::Edge;
+    use crate::shapes::{rectangle, signed_area};
+    use crate::tree::sort_edges;
+    use crate::util::testing::{donut, is_watertight};
+    use crate::vert::Vert;
+    use std::error::Error;
+
+    fn area(edge: &Edge) -> f64 {
+        signed_area(&edge.iter().cloned().collect::<Vec<Vert>>()).abs() / 2.
+    }
+
+    fn filled_area(nodes: &[Node]) -> f64 {
+        nodes
+            .iter()
+            .map(|n| area(&n.data) - n.children.iter().map(|c| area(&c.data)).sum::<f64>())
+            .sum()
+    }
+
+    fn max_z(tris: &[f64]) -> f64 {
+        tris.chunks(3).map(|v| v[2]).fold(f64::MIN, f64::max)
+    }
+
+    fn square() -> Result<Vec<Node>, ConversionError> {
+        sort_edges(vec![rectangle(0., 0., 20., 20., false)])
+    }
+
+    #[test]
+    fn test_walls_of_a_square() -> Result<(), Box<dyn Error>> {
+        let options = HollowOptions::default();
+        let (walls, fill) = hollow_nodes(&square()?, &options)?;
+        assert!(fill.is_empty());
+        assert_eq!(walls.len(), 1);
+        assert_eq!(walls[0].children.len(), 1);
+        assert!(walls[0].children[0].data.is_hole());
+        assert!((filled_area(&walls) - (400. - 144.)).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_holes_get_walls_too() -> Result<(), Box<dyn Error>> {
+        let nodes = sort_edges(donut(20., 6., 0., 0.))?;
+        // the corners of the wall around the hole point out of it, keep them square
+        let options = HollowOptions {
+            wall_width: 2.,
+            join: OffsetJoin::Miter { limit: 2. },
+            ..Default::default()
+        };
+        let (walls, _) = hollow_nodes(&nodes, &options)?;
+        // a wall along the outside and one along the hole, with the flat ring between them
+        assert_eq!(walls.len(), 2);
+        let inside = 16. * 16. - 12. * 12.;
+        assert!((filled_area(&walls) - (400. - 64. - inside)).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_thin_shapes_are_all_wall() -> Result<(), Box<dyn Error>> {
+        let nodes = sort_edges(vec![rectangle(0., 0., 50., 6., false)])?;
+        let (walls, fill) = hollow_nodes(
+            &nodes,
+            &HollowOptions {
+                fill: Some(Texture::Solid),
+                ..Default::default()
+            },
+        )?;
+        assert!(fill.is_empty());
+        assert!((filled_area(&walls) - 300.).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fill_inside_the_walls() -> Result<(), Box<dyn Error>> {
+        let solid = HollowOptions {
+            fill: Some(Texture::Solid),
+            ..Default::default()
+        };
+        let (_, fill) = hollow_nodes(&square()?, &solid)?;
+        assert!((filled_area(&fill) - 144.).abs() < 1e-6);
+        let grid = HollowOptions {
+            fill: Texture::from_name("grid"),
+            ..Default::default()
+        };
+        let (_, fill) = hollow_nodes(&square()?, &grid)?;
+        let textured = filled_area(&fill);
+        assert!(textured > 0. && textured < 144.);
+
+        let tris = hollow_triangles(&square()?, 2., EdgeProfile::Vertical, &grid)?;
+        assert_eq!(max_z(&tris), 2.);
+        assert!(is_watertight(&tris));
+        let low = tris
+            .chunks(3)
+            .filter(|v| (v[0] - 10.).abs() < 5. && (v[1] - 10.).abs() < 5.)
+            .map(|v| v[2])
+            .fold(f64::MIN, f64::max);
+        assert_eq!(low, 0.5);
+        Ok(())
+    }
+}
@@ -82,7 +82,7 @@ mod tests {
     #[test]
     fn test_geojson() -> Result<(), Box<dyn Error>> {
         let edges = edge_file(DEFAULT_THRESHOLD_VALUE, "./images/stick-figure.png")?;
-        let nodes = sort_edges(edges.closed_edges);
+        let nodes = sort_edges(edges.closed_edges)?;
         for (i, n) in nodes.into_iter().enumerate() {
             println!("node {:?} has {:?} children", i, n.children.len());
             let json_value = multipolygon_from_vec_edge_and_holes(vec![n]);
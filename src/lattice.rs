@@ -0,0 +1,427 @@
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+
+use image::error::{LimitError, LimitErrorKind};
+use image::{GrayImage, ImageError};
+use log::info;
+
+use crate::edge::Edge;
+use crate::edge_collection::Edges;
+use crate::error::ConversionError;
+use crate::vert::{quad_segments, Vert};
+
+/// A point on the grid of pixel centers and the middles between them, counted in half pixels
+/// right and down from the top left pixel. Integers can be compared and hashed exactly.
+pub type LatticePoint = (i32, i32);
+
+/// whether b turns neither left nor right going from a through b to c
+fn collinear(a: LatticePoint, b: LatticePoint, c: LatticePoint) -> bool {
+    let (ab, bc) = ((b.0 - a.0, b.1 - a.1), (c.0 - b.0, c.1 - b.1));
+    (ab.0 as i64) * (bc.1 as i64) == (ab.1 as i64) * (bc.0 as i64)
+}
+
+/// The row of cells a ring is closed in when tracing from the top, the row of its lowest point.
+/// Rows start and end on pixel centers, so a point on a center is in the row above it.
+fn closing_row(ring: &[LatticePoint]) -> i32 {
+    let lowest = ring.iter().map(|p| p.1).max().unwrap_or(1);
+    (lowest - 1) / 2
+}
+
+/// Marching squares on a thresholded image, a row of cells at a time from the top.
+/// Pixels that are 0 are ink. The pieces of outline are joined on the lattice and only turned
+/// into edges in image coordinates, with y going up, once they are closed.
+///
+/// The edges come out the same however the rows are traced, each starting at its top left
+/// corner and in the order of the row they close in. In each row the holes come first, so
+/// the holes of an outline are always out before it.
+pub struct Tracer {
+    /// the pixels of the rows of cells being traced
+    img: GrayImage,
+    /// the rows of cells of the whole image being traced
+    rows: Range<u32>,
+    /// the rows of cells in the whole image
+    image_rows: u32,
+    /// the next row of cells to trace
+    row: u32,
+    /// chains of points that are not closed yet, each going the way its outline goes
+    open: Vec<VecDeque<LatticePoint>>,
+    /// the open chain starting and ending at each point
+    starts: HashMap<LatticePoint, usize>,
+    ends: HashMap<LatticePoint, usize>,
+    closed: Vec<Vec<LatticePoint>>,
+}
+
+/// tracing needs at least one cell of two by two pixels
+pub fn check_traceable(width: u32, height: u32) -> Result<(), ConversionError> {
+    if width < 2 || height < 2 {
+        return Err(
+            ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError)).into(),
+        );
+    }
+    Ok(())
+}
+
+impl Tracer {
+    pub fn new(img: GrayImage) -> Result<Self, ConversionError> {
+        check_traceable(img.width(), img.height())?;
+        let image_rows = img.height() - 1;
+        Ok(Self::with_rows(img, 0..image_rows, image_rows))
+    }
+
+    /// Trace only some of the rows of cells of an image. Strips next to each other can be
+    /// traced on their own and then joined, see `join`.
+    pub fn strip(img: &GrayImage, rows: Range<u32>) -> Result<Self, ConversionError> {
+        check_traceable(img.width(), img.height())?;
+        let image_rows = img.height() - 1;
+        let rows = rows.start.min(image_rows)..rows.end.min(image_rows);
+        let width = img.width() as usize;
+        // the cells of the last row take in the row of pixels below it
+        let pixels = &img.as_raw()[rows.start as usize * width..(rows.end as usize + 1) * width];
+        let strip = GrayImage::from_raw(img.width(), rows.len() as u32 + 1, pixels.to_vec())
+            .expect("the strip is cut from a whole image");
+        Ok(Self::with_rows(strip, rows, image_rows))
+    }
+
+    fn with_rows(img: GrayImage, rows: Range<u32>, image_rows: u32) -> Self {
+        Self {
+            img,
+            row: rows.start,
+            rows,
+            image_rows,
+            open: vec![],
+            starts: HashMap::new(),
+            ends: HashMap::new(),
+            closed: vec![],
+        }
+    }
+
+    /// the number of rows of cells to trace
+    pub fn rows(&self) -> u32 {
+        self.rows.len() as u32
+    }
+
+    /// how many rows of cells have been traced
+    pub fn traced_rows(&self) -> u32 {
+        self.row - self.rows.start
+    }
+
+    /// trace the next row of cells, if there is one left
+    pub fn trace_row(&mut self) {
+        if self.row >= self.rows.end {
+            return;
+        }
+        let y = self.row;
+        let local = (y - self.rows.start) as usize;
+        let width = self.img.width() as usize;
+        let cells = self.img.width() - 1;
+        let pixels = self.img.as_raw();
+        let top = &pixels[local * width..(local + 1) * width];
+        let bottom = &pixels[(local + 1) * width..(local + 2) * width];
+        let mut segments = vec![];
+        for x in 0..cells {
+            let i = x as usize;
+            let quad = (
+                top[i] == 0,
+                top[i + 1] == 0,
+                bottom[i + 1] == 0,
+                bottom[i] == 0,
+            );
+            let border_case = (y == 0, x == cells - 1, y == self.image_rows - 1, x == 0);
+            for (a, b) in quad_segments(border_case, quad) {
+                let point = |p: u8| (2 * x as i32 + (p % 3) as i32, 2 * y as i32 + (p / 3) as i32);
+                segments.push((point(a), point(b)));
+            }
+        }
+        for (a, b) in segments {
+            self.add_chain(VecDeque::from([a, b]));
+        }
+        self.row += 1;
+    }
+
+    /// trace every row left
+    pub fn trace(&mut self) {
+        while self.row < self.rows.end {
+            self.trace_row();
+        }
+    }
+
+    /// Take in the chains and rings of the traced strip right below this one, as if this
+    /// tracer had gone on to trace its rows.
+    pub fn join(&mut self, below: Tracer) {
+        debug_assert_eq!(self.row, below.rows.start);
+        self.closed.extend(below.closed);
+        for chain in below.open {
+            self.add_chain(chain);
+        }
+        self.rows.end = below.rows.end;
+        self.row = below.row;
+    }
+
+    /// take out an open chain, the last open chain takes its place
+    fn remove_chain(&mut self, i: usize) -> VecDeque<LatticePoint> {
+        let chain = self.open.swap_remove(i);
+        self.starts.remove(&chain[0]);
+        self.ends.remove(&chain[chain.len() - 1]);
+        if i < self.open.len() {
+            let moved = &self.open[i];
+            self.starts.insert(moved[0], i);
+            self.ends.insert(moved[moved.len() - 1], i);
+        }
+        chain
+    }
+
+    /// add a piece of outline, joining it to the chains it shares an end with
+    fn add_chain(&mut self, chain: VecDeque<LatticePoint>) {
+        let (a, b) = (chain[0], chain[chain.len() - 1]);
+        let before = self.ends.get(&a).copied();
+        let after = self.starts.get(&b).copied();
+        match (before, after) {
+            (None, None) => {
+                self.starts.insert(a, self.open.len());
+                self.ends.insert(b, self.open.len());
+                self.open.push(chain);
+            }
+            (Some(i), None) => {
+                self.ends.remove(&a);
+                for p in chain.into_iter().skip(1) {
+                    push_back(&mut self.open[i], p);
+                }
+                self.ends.insert(b, i);
+            }
+            (None, Some(i)) => {
+                self.starts.remove(&b);
+                for p in chain.into_iter().rev().skip(1) {
+                    push_front(&mut self.open[i], p);
+                }
+                self.starts.insert(a, i);
+            }
+            (Some(i), Some(j)) if i == j => {
+                let mut ring = self.remove_chain(i);
+                for p in chain.into_iter().skip(1) {
+                    push_back(&mut ring, p);
+                }
+                self.close(ring);
+            }
+            (Some(i), Some(j)) => {
+                let after = self.remove_chain(j);
+                // the chain before may have been moved into the place of the one taken out
+                let i = if i == self.open.len() { j } else { i };
+                self.ends.remove(&a);
+                let open = &mut self.open[i];
+                for p in chain.into_iter().chain(after).skip(1) {
+                    push_back(open, p);
+                }
+                self.ends.insert(open[open.len() - 1], i);
+            }
+        }
+    }
+
+    /// keep a chain that came back to its first point
+    fn close(&mut self, mut ring: VecDeque<LatticePoint>) {
+        ring.pop_back();
+        // the ring can start in the middle of a side
+        let n = ring.len();
+        if n > 3 && collinear(ring[n - 1], ring[0], ring[1]) {
+            ring.pop_front();
+        }
+        // the top left point is always a corner
+        let first = (0..ring.len())
+            .min_by_key(|&i| (ring[i].1, ring[i].0))
+            .unwrap_or(0);
+        ring.rotate_left(first);
+        ring.push_back(ring[0]);
+        self.closed.push(ring.into());
+    }
+
+    /// where a lattice point is in image coordinates, with y going up from the bottom row
+    fn to_vert(&self, p: LatticePoint) -> Vert {
+        Vert::new(p.0 as f64 / 2., self.image_rows as f64 - p.1 as f64 / 2.)
+    }
+
+    fn to_edge(&self, points: impl IntoIterator<Item = LatticePoint>) -> Edge {
+        Edge::from(
+            points
+                .into_iter()
+                .map(|p| self.to_vert(p))
+                .collect::<Vec<Vert>>(),
+        )
+    }
+
+    /// the edges closed since the last time, with their winding worked out
+    pub fn take_closed(&mut self) -> Result<Vec<Edge>, ConversionError> {
+        let mut closed = std::mem::take(&mut self.closed)
+            .into_iter()
+            .map(|ring| {
+                let order = (closing_row(&ring), ring[0].1, ring[0].0);
+                let mut edge = self.to_edge(ring);
+                edge.set_is_hole()?;
+                Ok(((order.0, !edge.is_hole(), order.1, order.2), edge))
+            })
+            .collect::<Result<Vec<_>, ConversionError>>()?;
+        closed.sort_by_key(|(order, _)| *order);
+        Ok(closed.into_iter().map(|(_, edge)| edge).collect())
+    }
+
+    /// the closed edges, and any that could not be closed
+    pub fn finish(mut self) -> Result<Edges, ConversionError> {
+        let closed_edges = self.take_closed()?;
+        let open = std::mem::take(&mut self.open);
+        if !open.is_empty() {
+            info!("{} traced edges were left open", open.len());
+        }
+        let edges = open.into_iter().map(|chain| self.to_edge(chain)).collect();
+        Ok(Edges::from_traced(edges, closed_edges))
+    }
+}
+
+/// add a point to the end of a chain, dropping the old end when it is on the way
+fn push_back(chain: &mut VecDeque<LatticePoint>, p: LatticePoint) {
+    let n = chain.len();
+    if n > 1 && collinear(chain[n - 2], chain[n - 1], p) {
+        chain.pop_back();
+    }
+    chain.push_back(p);
+}
+
+fn push_front(chain: &mut VecDeque<LatticePoint>, p: LatticePoint) {
+    if chain.len() > 1 && collinear(p, chain[0], chain[1]) {
+        chain.pop_front();
+    }
+    chain.push_front(p);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::constants::DEFAULT_THRESHOLD_VALUE;
+    use crate::edge::is_hole;
+    use crate::shapes::signed_area;
+    use crate::threshold_img;
+    use crate::vert::get_quad_edge;
+    use image::io::Reader as ImageReader;
+    use image::{DynamicImage, GenericImageView, Luma};
+    use std::error::Error;
+
+    extern crate test;
+    use test::Bencher;
+
+    /// the way edges were traced before, a column at a time on floats
+    fn trace_by_columns(img: &DynamicImage) -> Result<Edges, ConversionError> {
+        let mut out = Edges::new();
+        let (width, height) = (img.width() - 1, img.height() - 1);
+        for x in 0..width {
+            for y in 0..height {
+                let ink = |x, y| img.get_pixel(x, y)[0] == 0;
+                let quad = (ink(x, y), ink(x + 1, y), ink(x + 1, y + 1), ink(x, y + 1));
+                let border_case = (y == 0, x == width - 1, y == height - 1, x == 0);
+                for e in get_quad_edge(border_case, quad, x as f64, y as f64, height as f64) {
+                    out.add_edge(e)?;
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// whether it is a hole, its area and its corners, as bits to compare exactly
+    type Outline = (bool, u64, Vec<(u64, u64)>);
+
+    /// what is left of each edge when the order and starting point of its points do not matter
+    fn outlines(edges: &[Edge]) -> Vec<Outline> {
+        let mut out: Vec<_> = edges
+            .iter()
+            .map(|e| {
+                let points: Vec<Vert> = e.iter().cloned().collect();
+                let mut corners: Vec<(u64, u64)> = points
+                    .iter()
+                    .map(|v| (v.x.to_bits(), v.y.to_bits()))
+                    .collect();
+                corners.sort();
+                corners.dedup();
+                (is_hole(e), signed_area(&points).abs().to_bits(), corners)
+            })
+            .collect();
+        out.sort();
+        out
+    }
+
+    fn thresholded(filename: &str) -> Result<DynamicImage, Box<dyn Error>> {
+        let mut img = ImageReader::open(filename)?
+            .with_guessed_format()?
+            .decode()?;
+        threshold_img(&mut img, DEFAULT_THRESHOLD_VALUE);
+        Ok(img)
+    }
+
+    #[test]
+    fn test_same_outlines_as_tracing_by_columns() -> Result<(), Box<dyn Error>> {
+        for name in ["./images/stick-figure.png", "./images/moontower.png"] {
+            let img = thresholded(name)?;
+            let mut tracer = Tracer::new(img.to_luma8())?;
+            tracer.trace();
+            let edges = tracer.finish()?;
+            assert!(edges.edges.is_empty());
+            let before = trace_by_columns(&img)?;
+            // the old way can leave a point in the middle of a straight side
+            let corners =
+                |edges: &[Edge]| -> usize { outlines(edges).iter().map(|(_, _, c)| c.len()).sum() };
+            assert!(corners(&edges.closed_edges) <= corners(&before.closed_edges));
+            let strip = |o: Vec<Outline>| -> Vec<(bool, u64)> {
+                o.into_iter().map(|(h, a, _)| (h, a)).collect()
+            };
+            assert_eq!(
+                strip(outlines(&edges.closed_edges)),
+                strip(outlines(&before.closed_edges)),
+                "{}",
+                name
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_square_with_a_hole() -> Result<(), Box<dyn Error>> {
+        // ink from 1 to 5 with a hole at 3, the image is 7 pixels high so y = 6 - row
+        let img = GrayImage::from_fn(7, 7, |x, y| {
+            let inside = (1..6).contains(&x) && (1..6).contains(&y);
+            Luma([if inside && (x, y) != (3, 3) { 0 } else { 255 }])
+        });
+        let mut tracer = Tracer::new(img)?;
+        tracer.trace_row();
+        assert_eq!(tracer.traced_rows(), 1);
+        tracer.trace();
+        assert_eq!(tracer.traced_rows(), 6);
+        let edges = tracer.finish()?;
+        assert_eq!(edges.closed_edges.len(), 2);
+        let hole = edges.closed_edges.iter().find(|e| e.is_hole()).unwrap();
+        for v in hole.iter() {
+            assert!((2.5..=3.5).contains(&v.x) && (2.5..=3.5).contains(&v.y));
+        }
+        // straight sides are only their corners, with the first point again at the end
+        let outline = edges.closed_edges.iter().find(|e| !e.is_hole()).unwrap();
+        assert_eq!((outline.len(), hole.len()), (9, 5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_too_small() {
+        assert!(Tracer::new(GrayImage::new(1, 1)).is_err());
+    }
+
+    #[bench]
+    fn bench_trace_moontower(b: &mut Bencher) -> Result<(), Box<dyn Error>> {
+        let img = thresholded("./images/moontower.png")?;
+        b.iter(|| {
+            let mut tracer = Tracer::new(img.to_luma8()).unwrap();
+            tracer.trace();
+            tracer.finish().unwrap()
+        });
+        Ok(())
+    }
+
+    #[bench]
+    fn bench_trace_moontower_by_columns(b: &mut Bencher) -> Result<(), Box<dyn Error>> {
+        let img = thresholded("./images/moontower.png")?;
+        b.iter(|| trace_by_columns(&img).unwrap());
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+
+use crate::braille::braille_cells;
+use crate::chart::{Chart, ChartData, ChartKind, ChartOptions, BAR_TEXTURES, LABEL_GAP, MARGIN};
+use crate::error::ConversionError;
+use crate::line_style::LineStyle;
+use crate::region::{RegionMap, RegionSettings};
+use crate::texture::Texture;
+use crate::vert::Vert;
+
+/// size of the sample drawn for each entry, in millimeters
+static SWATCH_WIDTH: f64 = 20.;
+static SWATCH_HEIGHT: f64 = 8.;
+/// space between the rows of the legend
+static ROW_GAP: f64 = 4.;
+
+/// A sample of something drawn on a plate
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Swatch {
+    /// a texture with `scale` millimeters for each of its units
+    Texture {
+        texture: Texture,
+        scale: f64,
+    },
+    Line(LineStyle),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LegendEntry {
+    pub swatch: Swatch,
+    pub label: String,
+}
+
+/// A key to the textures and line styles used on a plate, with a swatch of each next to its
+/// braille label, one entry to a row
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Legend {
+    pub entries: Vec<LegendEntry>,
+}
+
+impl Legend {
+    /// add an entry, unless the same swatch already has the same label
+    pub fn add(&mut self, swatch: Swatch, label: &str) {
+        let entry = LegendEntry {
+            swatch,
+            label: label.to_string(),
+        };
+        if !self.entries.contains(&entry) {
+            self.entries.push(entry);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The bar textures or line styles of each series, named after the series.
+    /// Scatter charts tell their series apart by markers, so they have no entries.
+    pub fn from_chart(data: &ChartData, options: &ChartOptions) -> Self {
+        let mut legend = Legend::default();
+        for (i, series) in data.series.iter().enumerate() {
+            match options.kind {
+                ChartKind::Bar => legend.add(
+                    Swatch::Texture {
+                        texture: BAR_TEXTURES[i % BAR_TEXTURES.len()],
+                        scale: 1.,
+                    },
+                    &series.name,
+                ),
+                ChartKind::Line => {
+                    let name = LineStyle::NAMES[i % LineStyle::NAMES.len()];
+                    let style = LineStyle::from_name(name, options.line_width).unwrap();
+                    legend.add(Swatch::Line(style), &series.name);
+                }
+                ChartKind::Scatter => {}
+            }
+        }
+        legend
+    }
+
+    /// The textures of the regions that are made, labeled with the region labels, or the name
+    /// of the texture for regions without one. `scale` is the millimeters for each pixel.
+    pub fn from_regions(map: &RegionMap, settings: &RegionSettings, scale: f64) -> Self {
+        let mut legend = Legend::default();
+        for region in map.regions.iter() {
+            if let Some((_, texture)) = settings.resolve(region, 0.) {
+                let label = settings
+                    .properties
+                    .get(&region.id)
+                    .map_or("", |p| p.label.as_str());
+                let label = if label.is_empty() {
+                    texture.name()
+                } else {
+                    label
+                };
+                legend.add(Swatch::Texture { texture, scale }, label);
+            }
+        }
+        legend
+    }
+
+    fn row_height(options: &ChartOptions) -> f64 {
+        SWATCH_HEIGHT.max(options.braille.height())
+    }
+
+    /// width and height of the legend in millimeters
+    pub fn size(&self, options: &ChartOptions) -> (f64, f64) {
+        if self.is_empty() {
+            return (0., 0.);
+        }
+        let label_width = self
+            .entries
+            .iter()
+            .map(|e| options.braille.width(braille_cells(&e.label).len()))
+            .fold(0., f64::max);
+        let n = self.entries.len() as f64;
+        (
+            SWATCH_WIDTH + LABEL_GAP + label_width,
+            n * Self::row_height(options) + (n - 1.) * ROW_GAP,
+        )
+    }
+
+    /// Draw the legend on a chart with its top left corner at (x, y).
+    /// Heights, line widths and braille sizes are taken from the chart options.
+    pub fn draw(
+        &self,
+        chart: &mut Chart,
+        x: f64,
+        y: f64,
+        options: &ChartOptions,
+    ) -> Result<(), ConversionError> {
+        let row = Self::row_height(options);
+        let relief = options.relief_height;
+        for (i, entry) in self.entries.iter().enumerate() {
+            let top = y - i as f64 * (row + ROW_GAP);
+            let middle = top - row / 2.;
+            let (x0, y0) = (x, middle - SWATCH_HEIGHT / 2.);
+            match entry.swatch {
+                Swatch::Texture { texture, scale } => chart.bar(
+                    Vert::new(x0, y0),
+                    Vert::new(x0 + SWATCH_WIDTH, y0 + SWATCH_HEIGHT),
+                    texture,
+                    scale,
+                    options,
+                ),
+                Swatch::Line(style) => {
+                    let points = [Vert::new(x0, middle), Vert::new(x0 + SWATCH_WIDTH, middle)];
+                    for e in style.line(&points)? {
+                        chart.shapes.push((vec![e], relief));
+                    }
+                }
+            }
+            let at = Vert::new(
+                x + SWATCH_WIDTH + LABEL_GAP,
+                middle + options.braille.height() / 2.,
+            );
+            chart.labels.push((entry.label.clone(), at));
+        }
+        Ok(())
+    }
+
+    /// the legend on a plate of its own, to go with the plate it is a key to
+    pub fn to_chart(&self, options: &ChartOptions) -> Result<Chart, ConversionError> {
+        let (width, height) = self.size(options);
+        let mut chart = Chart {
+            width: width + 2. * MARGIN,
+            height: height + 2. * MARGIN,
+            shapes: vec![],
+            labels: vec![],
+        };
+        self.draw(&mut chart, MARGIN, MARGIN + height, options)?;
+        Ok(chart)
+    }
+}
+
+/// the plate a legend goes on, when it is not part of the plate it is a key to
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LegendPlacement {
+    #[default]
+    None,
+    /// above the graphic, on the same plate
+    Plate,
+    /// on a separate key plate
+    Page,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::region::{image_to_region_map, RegionProperties};
+    use crate::triangle::StlOptions;
+    use image::{GrayImage, Luma};
+    use std::error::Error;
+
+    static CSV: &str = "month, rain, sun\nJan, 10, 2\nFeb, 25.5, 4\nMar, 5, 7\n";
+
+    #[test]
+    fn test_chart_legend() -> Result<(), Box<dyn Error>> {
+        let data = ChartData::from_csv(CSV)?;
+        let mut options = ChartOptions::default();
+        let legend = Legend::from_chart(&data, &options);
+        assert_eq!(legend.entries.len(), 2);
+        assert_eq!(legend.entries[1].label, "sun");
+        options.kind = ChartKind::Line;
+        let legend = Legend::from_chart(&data, &options);
+        assert!(matches!(
+            legend.entries[1].swatch,
+            Swatch::Line(LineStyle::Dashed { .. })
+        ));
+        options.kind = ChartKind::Scatter;
+        assert!(Legend::from_chart(&data, &options).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_page() -> Result<(), Box<dyn Error>> {
+        let data = ChartData::from_csv(CSV)?;
+        let options = ChartOptions::default();
+        let key = Legend::from_chart(&data, &options).to_chart(&options)?;
+        assert_eq!(key.labels.len(), 2);
+        for v in key
+            .shapes
+            .iter()
+            .flat_map(|(edges, _)| edges.iter().flat_map(|e| e.iter()))
+        {
+            assert!(v.x >= MARGIN && v.x <= key.width - MARGIN);
+            assert!(v.y >= MARGIN && v.y <= key.height - MARGIN);
+        }
+        assert_eq!(key.triangles(&options)?.len() % 9, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_legend_on_the_plate() -> Result<(), Box<dyn Error>> {
+        let data = ChartData::from_csv(CSV)?;
+        let mut options = ChartOptions::default();
+        let plain = Chart::new(&data, &options)?;
+        options.legend = LegendPlacement::Plate;
+        let chart = Chart::new(&data, &options)?;
+        assert!(chart.height > plain.height);
+        assert_eq!(chart.labels.len(), plain.labels.len() + 2);
+        // the legend goes above the chart
+        let (_, top) = chart.labels.last().unwrap();
+        assert!(top.y > plain.height - MARGIN && top.y < chart.height);
+        Ok(())
+    }
+
+    #[test]
+    fn test_region_legend() -> Result<(), Box<dyn Error>> {
+        let img = GrayImage::from_fn(40, 20, |x, y| {
+            let inside = (5..15).contains(&x) || (25..35).contains(&x);
+            if inside && (5..15).contains(&y) {
+                Luma([0])
+            } else {
+                Luma([255])
+            }
+        });
+        let map = image_to_region_map(img.into(), 128, 0.5, &StlOptions::default())?;
+        let mut settings = RegionSettings::default();
+        settings.defaults.min_area = 0.;
+        let legend = Legend::from_regions(&map, &settings, 0.5);
+        // both regions are solid without labels
+        assert_eq!(legend.entries.len(), 1);
+        assert_eq!(legend.entries[0].label, "solid");
+        settings.properties.insert(
+            2,
+            RegionProperties {
+                label: "lake".to_string(),
+                texture: Texture::from_name("dots"),
+                ..Default::default()
+            },
+        );
+        let legend = Legend::from_regions(&map, &settings, 0.5);
+        assert_eq!(legend.entries.len(), 2);
+        assert_eq!(legend.entries[1].label, "lake");
+        Ok(())
+    }
+}
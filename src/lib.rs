@@ -1,12 +1,38 @@
 #![feature(extract_if, const_float_bits_conv, test)]
 
+pub mod boolean;
+pub mod braille;
+pub mod chart;
 pub mod components;
+pub mod drawing;
 pub mod earcut;
 pub mod edge;
 pub mod edge_collection;
+pub mod engrave;
+pub mod error;
+pub mod font;
+pub mod frame;
+pub mod hollow;
 pub mod json;
+pub mod lattice;
+pub mod legend;
+pub mod line_style;
+pub mod mold;
+pub mod offset;
+pub mod palette;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod plot;
+pub mod raster;
+pub mod region;
 pub mod ribbon;
+pub mod shapes;
+pub mod skeleton;
+pub mod steps;
 pub mod stl;
+pub mod stream;
+pub mod texture;
+pub mod tile;
 pub mod tree;
 pub mod triangle;
 pub mod util;
@@ -15,15 +41,12 @@ pub mod vert;
 use std::error::Error;
 
 use components::utils::SplitColor;
-use image::error::{LimitError, LimitErrorKind};
 use image::imageops::ColorMap;
 use image::io::Reader as ImageReader;
-use image::{
-    DynamicImage, GenericImage, GenericImageView, GrayImage, ImageBuffer, ImageError, Luma, Rgba,
-};
+use image::{DynamicImage, GenericImage, GenericImageView, GrayImage, ImageBuffer, Luma, Rgba};
 
-use vert::{get_quad_edge, QuadCase};
 use edge_collection::Edges;
+use error::ConversionError;
 
 pub fn rgb_to_greyscale(p: Rgba<u8>) -> Rgba<u8> {
     let x: u8 = (0.2126 * (p[0] as f64) + 0.7152 * (p[1] as f64) + 0.0722 * (p[2] as f64)) as u8;
@@ -94,44 +117,20 @@ pub fn ret_thresholded_img(image: DynamicImage, cmap: SplitColor) -> DynamicImag
     threshold_img_buffer(greyed, cmap)
 }
 
-fn get_cell_case(image: &DynamicImage, x: u32, y: u32) -> Result<QuadCase, ImageError> {
-    if x >= image.width() - 1 {
-        return Err(ImageError::Limits(LimitError::from_kind(
-            LimitErrorKind::DimensionError,
-        )));
-    }
-    if y >= image.height() - 1 {
-        return Err(ImageError::Limits(LimitError::from_kind(
-            LimitErrorKind::DimensionError,
-        )));
-    }
-
-    let top_left = image.get_pixel(x, y)[0] == 0;
-    let top_right = image.get_pixel(x + 1, y)[0] == 0;
-    let bottom_right = image.get_pixel(x + 1, y + 1)[0] == 0;
-    let bottom_left = image.get_pixel(x, y + 1)[0] == 0;
-    Ok((top_left, top_right, bottom_right, bottom_left))
-}
+/// trace the outlines of a thresholded image, where pixels that are 0 are ink
+pub fn edge_img(img: &DynamicImage) -> Result<Edges, ConversionError> {
+    #[cfg(feature = "parallel")]
+    return parallel::edge_img_in_strips(&img.to_luma8());
 
-pub fn edge_img(img: &DynamicImage) -> Result<Edges, Box<dyn Error>> {
-    let mut out = Edges::new();
-
-    let width = img.width() - 1;
-    let height = img.height() - 1;
-    for x in 0..width {
-        for y in 0..height {
-            let border_case = (y == 0, x == width - 1, y == height - 1, x == 0);
-            let quad = get_cell_case(img, x, y)?;
-            let edges = get_quad_edge(border_case, quad, x as f64, y as f64, height as f64);
-            for e in edges {
-                out.add_edge(e);
-            }
-        }
+    #[cfg(not(feature = "parallel"))]
+    {
+        let mut tracer = lattice::Tracer::new(img.to_luma8())?;
+        tracer.trace();
+        tracer.finish()
     }
-    Ok(out)
 }
 
-pub fn edge_file(threshold: u8, filename: &str) -> Result<Edges, Box<dyn Error>> {
+pub fn edge_file(threshold: u8, filename: &str) -> Result<Edges, ConversionError> {
     let mut img = ImageReader::open(filename)?
         .with_guessed_format()?
         .decode()?;
@@ -193,6 +192,12 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn edge_img_too_small() {
+        let img = DynamicImage::new_luma8(1, 5);
+        assert!(matches!(edge_img(&img), Err(ConversionError::Image(_))));
+    }
+
     #[bench]
     fn edge_image_doggy(b: &mut Bencher) -> Result<(), Box<dyn Error>> {
         let mut img = ImageReader::open("./images/doggy.png")?
@@ -206,6 +211,30 @@ mod tests {
         return Ok(());
     }
 
+    #[bench]
+    fn edge_image_moontower(b: &mut Bencher) -> Result<(), Box<dyn Error>> {
+        let mut img = ImageReader::open("./images/moontower.png")?
+            .with_guessed_format()?
+            .decode()?;
+        threshold_img(&mut img, DEFAULT_THRESHOLD_VALUE);
+
+        b.iter(|| {
+            edge_img(&img).unwrap();
+        });
+        Ok(())
+    }
+
+    /// thin stripes across the image leave an open edge along every stripe while tracing
+    #[bench]
+    fn edge_image_stripes(b: &mut Bencher) {
+        let img: DynamicImage =
+            GrayImage::from_fn(800, 800, |_, y| Luma([if y % 4 < 2 { 0 } else { 255 }])).into();
+
+        b.iter(|| {
+            edge_img(&img).unwrap();
+        });
+    }
+
     #[bench]
     fn edge_image_eagle_png(b: &mut Bencher) -> Result<(), Box<dyn Error>> {
         let mut img = ImageReader::open("./images/eagle.png")?
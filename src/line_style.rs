@@ -0,0 +1,306 @@
+use serde::{Deserialize, Serialize};
+
+use crate::edge::Edge;
+use crate::error::ConversionError;
+use crate::shapes::{circle, dashes, offset_line, points_along, polygon, signed_area, stroke};
+use crate::vert::Vert;
+
+/// number of sides used for the round bumps of dotted lines
+static DOT_SEGMENTS: usize = 16;
+
+/// A way of drawing a raised line so lines can be told apart by touch.
+/// Dimensions are in the units of the points the line goes through.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LineStyle {
+    Solid {
+        width: f64,
+    },
+    /// pieces `dash` long with `gap` between them
+    Dashed {
+        width: f64,
+        dash: f64,
+        gap: f64,
+    },
+    /// round bumps every `spacing` along the line
+    Dotted {
+        radius: f64,
+        spacing: f64,
+    },
+    /// two lines side by side with `gap` between them
+    Double {
+        width: f64,
+        gap: f64,
+    },
+}
+
+impl Default for LineStyle {
+    fn default() -> Self {
+        LineStyle::Solid { width: 1. }
+    }
+}
+
+impl LineStyle {
+    /// the names used to choose a line style in the ui
+    pub const NAMES: [&'static str; 4] = ["solid", "dashed", "dotted", "double"];
+
+    /// a line style with its other sizes picked from the width of the line
+    pub fn from_name(name: &str, width: f64) -> Option<Self> {
+        match name {
+            "solid" => Some(LineStyle::Solid { width }),
+            "dashed" => Some(LineStyle::Dashed {
+                width,
+                dash: 4. * width,
+                gap: 2. * width,
+            }),
+            "dotted" => Some(LineStyle::Dotted {
+                radius: width,
+                spacing: 3. * width,
+            }),
+            "double" => Some(LineStyle::Double { width, gap: width }),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineStyle::Solid { .. } => "solid",
+            LineStyle::Dashed { .. } => "dashed",
+            LineStyle::Dotted { .. } => "dotted",
+            LineStyle::Double { .. } => "double",
+        }
+    }
+
+    /// how wide the raised part is across the line
+    pub fn total_width(&self) -> f64 {
+        match *self {
+            LineStyle::Solid { width } | LineStyle::Dashed { width, .. } => width,
+            LineStyle::Dotted { radius, .. } => 2. * radius,
+            LineStyle::Double { width, gap } => 2. * width + gap,
+        }
+    }
+
+    /// Sizes have to be more than 0, except the gap between double lines, which can be 0.
+    pub fn check(&self) -> Result<(), ConversionError> {
+        let sizes = match *self {
+            LineStyle::Solid { width } => vec![("width", width)],
+            LineStyle::Dashed { width, dash, gap } => {
+                vec![("width", width), ("dash", dash), ("gap", gap)]
+            }
+            LineStyle::Dotted { radius, spacing } => vec![("radius", radius), ("spacing", spacing)],
+            LineStyle::Double { width, gap } => {
+                if !(gap >= 0. && gap.is_finite()) {
+                    return Err(ConversionError::InvalidOptions(format!(
+                        "the gap of a double line can not be {}",
+                        gap
+                    )));
+                }
+                vec![("width", width)]
+            }
+        };
+        match sizes
+            .into_iter()
+            .find(|(_, size)| !(*size > 0. && size.is_finite()))
+        {
+            Some((name, size)) => Err(ConversionError::InvalidOptions(format!(
+                "the {} of a {} line has to be more than 0, not {}",
+                name,
+                self.name(),
+                size
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// closed edges of a line through the points, none when the points do not go anywhere
+    pub fn line(&self, points: &[Vert]) -> Result<Vec<Edge>, ConversionError> {
+        self.check()?;
+        if points.is_empty() {
+            return Ok(vec![]);
+        }
+        Ok(match *self {
+            LineStyle::Solid { width } => stroke(points, width).into_iter().collect(),
+            LineStyle::Dashed { width, dash, gap } => dashes(points, dash, gap)?
+                .iter()
+                .filter_map(|piece| stroke(piece, width))
+                .collect(),
+            LineStyle::Dotted { radius, spacing } => points_along(points, spacing)?
+                .iter()
+                .map(|p| circle(p.x, p.y, radius, DOT_SEGMENTS, false))
+                .collect(),
+            LineStyle::Double { width, gap } => {
+                let apart = (width + gap) / 2.;
+                [apart, -apart]
+                    .iter()
+                    .filter_map(|d| stroke(&offset_line(points, *d, false), width))
+                    .collect()
+            }
+        })
+    }
+
+    /// Closed edges of a line following an edge. Open edges, like the ones left in
+    /// `Edges::edges`, are drawn from end to end and closed edges all the way around.
+    pub fn edge_line(&self, edge: &Edge) -> Result<Vec<Edge>, ConversionError> {
+        self.check()?;
+        let points: Vec<Vert> = edge.iter().cloned().collect();
+        if points.len() < 3 || !edge.is_closed() {
+            return self.line(&points);
+        }
+        Ok(match *self {
+            LineStyle::Solid { width } => ring(&points, 0., width),
+            LineStyle::Double { width, gap } => {
+                let apart = (width + gap) / 2.;
+                let mut out = ring(&points, apart, width);
+                out.append(&mut ring(&points, -apart, width));
+                out
+            }
+            LineStyle::Dotted { spacing, .. } => {
+                let mut out = self.line(&points)?;
+                // the last bump would land on the first one when the loop is a whole number
+                // of spacings around
+                let first = &points[0];
+                let last = points_along(&points, spacing)?.pop();
+                if out.len() > 1 && last.map_or(false, |p| p.diff(first).magnitude() < spacing / 2.)
+                {
+                    out.pop();
+                }
+                out
+            }
+            LineStyle::Dashed { .. } => self.line(&points)?,
+        })
+    }
+}
+
+/// a band `width` wide around a closed ring of points, moved `offset` to the left of it
+fn ring(points: &[Vert], offset: f64, width: f64) -> Vec<Edge> {
+    let a = offset_line(points, offset + width / 2., true);
+    let b = offset_line(points, offset - width / 2., true);
+    if a.len() < 4 {
+        return vec![];
+    }
+    let (outer, inner) = if signed_area(&a).abs() > signed_area(&b).abs() {
+        (a, b)
+    } else {
+        (b, a)
+    };
+    match (polygon(outer, false), polygon(inner, true)) {
+        (Some(outer), Some(inner)) => vec![outer, inner],
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::rectangle;
+    use std::error::Error;
+
+    fn area(edges: &[Edge]) -> f64 {
+        edges
+            .iter()
+            .map(|e| {
+                let points: Vec<Vert> = e.iter().cloned().collect();
+                signed_area(&points[..points.len() - 1]) / 2.
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_names() {
+        for name in LineStyle::NAMES {
+            assert_eq!(LineStyle::from_name(name, 1.).unwrap().name(), name);
+        }
+        assert!(LineStyle::from_name("wavy", 1.).is_none());
+    }
+
+    #[test]
+    fn test_line_styles() -> Result<(), Box<dyn Error>> {
+        let line = [Vert::new(0., 0.), Vert::new(12., 0.)];
+        let style = |name| LineStyle::from_name(name, 1.).unwrap();
+        assert_eq!(style("solid").line(&line)?.len(), 1);
+        assert!((area(&style("solid").line(&line)?) - 12.).abs() < 1e-9);
+        // dashes 4 long with gaps of 2
+        assert_eq!(style("dashed").line(&line)?.len(), 2);
+        // bumps every 3
+        assert_eq!(style("dotted").line(&line)?.len(), 5);
+        let double = style("double").line(&line)?;
+        assert_eq!(double.len(), 2);
+        for v in double.iter().flat_map(|e| e.iter()) {
+            assert!((v.y.abs() - 0.5).abs() < 1e-9 || (v.y.abs() - 1.5).abs() < 1e-9);
+        }
+        assert!(style("solid").line(&[])?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_edge_line() -> Result<(), Box<dyn Error>> {
+        let edge = Edge::from(vec![
+            Vert::new(0., 0.),
+            Vert::new(10., 0.),
+            Vert::new(10., 10.),
+        ]);
+        let solid = LineStyle::Solid { width: 2. }.edge_line(&edge)?;
+        assert_eq!(solid.len(), 1);
+        assert!((area(&solid) - 40.).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_closed_edge_line() -> Result<(), Box<dyn Error>> {
+        let square = rectangle(0., 0., 10., 10., false);
+        let solid = LineStyle::Solid { width: 2. }.edge_line(&square)?;
+        assert_eq!(solid.len(), 2);
+        assert!(solid[1].is_hole());
+        // a band 2 wide from 8 by 8 out to 12 by 12
+        assert!((area(&solid) - (144. - 64.)).abs() < 1e-9);
+        assert_eq!(
+            LineStyle::Double { width: 1., gap: 1. }
+                .edge_line(&square)?
+                .len(),
+            4
+        );
+        // 40 around with bumps every 4, without a second bump at the start
+        let dotted = LineStyle::Dotted {
+            radius: 1.,
+            spacing: 4.,
+        };
+        assert_eq!(dotted.edge_line(&square)?.len(), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_line_styles() {
+        let line = [Vert::new(0., 0.), Vert::new(12., 0.)];
+        let square = rectangle(0., 0., 10., 10., false);
+        for style in [
+            LineStyle::Solid { width: 0. },
+            LineStyle::Dashed {
+                width: 1.,
+                dash: 0.,
+                gap: 2.,
+            },
+            LineStyle::Dashed {
+                width: 1.,
+                dash: 4.,
+                gap: 0.,
+            },
+            LineStyle::Dotted {
+                radius: 1.,
+                spacing: 0.,
+            },
+            LineStyle::Dotted {
+                radius: 1.,
+                spacing: f64::NAN,
+            },
+            LineStyle::Double {
+                width: 1.,
+                gap: -1.,
+            },
+        ] {
+            assert!(style.check().is_err());
+            assert!(style.line(&line).is_err());
+            assert!(style.edge_line(&square).is_err());
+        }
+        // double lines can touch
+        assert!(LineStyle::Double { width: 1., gap: 0. }.check().is_ok());
+    }
+}
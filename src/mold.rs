@@ -0,0 +1,285 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::edge::Edge;
+use crate::error::ConversionError;
+use crate::ribbon::EdgeProfile;
+use crate::engrave::pocket_nodes;
+use crate::shapes::{circle, rectangle, ring_contains};
+use crate::steps::extrude_steps;
+use crate::tree::{sort_edges, Node};
+use crate::triangle::nodes_to_triangles;
+use crate::vert::Vert;
+
+/// number of sides of the vent holes
+static VENT_SEGMENTS: usize = 12;
+
+/// Which way round the mold is made
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MoldKind {
+    /// the relief standing on a block, for pulling a sheet over
+    #[default]
+    Positive,
+    /// a block with the relief cut into it
+    Negative,
+}
+
+impl MoldKind {
+    /// the names used to choose a kind in the ui
+    pub const NAMES: [&'static str; 2] = ["positive", "negative"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "positive" => Some(MoldKind::Positive),
+            "negative" => Some(MoldKind::Negative),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MoldKind::Positive => "positive",
+            MoldKind::Negative => "negative",
+        }
+    }
+}
+
+/// A master for thermoforming, with the relief on a solid block and vent holes through
+/// the block so air trapped under the sheet can escape.
+/// Sizes are in the same units as the height, like the other stl options.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MoldOptions {
+    pub kind: MoldKind,
+    /// how far the walls lean from vertical, in degrees
+    pub draft_angle: f64,
+    /// thickness of the block under the relief
+    pub base_thickness: f64,
+    /// vents through the block in the low areas, no vents are made when this is zero
+    pub vent_diameter: f64,
+    /// distance between vents in open areas, every enclosed low area gets at least one
+    pub vent_spacing: f64,
+}
+
+impl Default for MoldOptions {
+    fn default() -> Self {
+        Self {
+            kind: MoldKind::Positive,
+            draft_angle: 3.,
+            base_thickness: 20.,
+            vent_diameter: 10.,
+            vent_spacing: 200.,
+        }
+    }
+}
+
+fn rings(nodes: &[Node]) -> Vec<&Edge> {
+    nodes
+        .iter()
+        .flat_map(|n| std::iter::once(&n.data).chain(n.children.iter().map(|c| &c.data)))
+        .collect()
+}
+
+/// inside an odd number of rings means inside the material
+fn in_material(rings: &[&Edge], v: &Vert) -> bool {
+    rings.iter().filter(|r| ring_contains(r, v)).count() % 2 == 1
+}
+
+fn clear_of_walls(rings: &[&Edge], v: &Vert, clearance: f64) -> bool {
+    rings.iter().all(|r| {
+        r.iter()
+            .zip(r.iter().skip(1))
+            .all(|(a, b)| v.distance_to_segment(a, b) >= clearance)
+    })
+}
+
+/// Centers of the triangles of a ring, biggest triangle first.
+/// At least one of these is inside the ring, even when the ring is not convex.
+fn interior_points(ring: &Edge) -> Vec<Vert> {
+    // a ring earcut can not split gets no vents
+    let tris = nodes_to_triangles(vec![Node {
+        data: ring.clone(),
+        children: vec![],
+    }])
+    .unwrap_or_default();
+    let mut centers: Vec<(f64, Vert)> = tris
+        .chunks(6)
+        .map(|t| {
+            let (a, b, c) = (
+                Vert::new(t[0], t[1]),
+                Vert::new(t[2], t[3]),
+                Vert::new(t[4], t[5]),
+            );
+            let area = b.diff(&a).determinant(&c.diff(&a)).abs();
+            (
+                area,
+                Vert::new((t[0] + t[2] + t[4]) / 3., (t[1] + t[3] + t[5]) / 3.),
+            )
+        })
+        .collect();
+    centers.sort_by(|a, b| b.0.total_cmp(&a.0));
+    centers.into_iter().map(|(_, v)| v).collect()
+}
+
+/// Where the vents go: on a grid over the parts of the block not covered by the top layer,
+/// kept a vent's width from any wall, plus one in each enclosed low area the grid missed.
+fn vent_positions(
+    nodes: &[Node],
+    block: &Edge,
+    width: f64,
+    height: f64,
+    options: &MoldOptions,
+) -> Vec<Vert> {
+    let radius = options.vent_diameter / 2.;
+    let mut walls = rings(nodes);
+    walls.push(block);
+    let clearance = 2. * radius;
+    let fits = |v: &Vert| {
+        !in_material(&walls[..walls.len() - 1], v) && clear_of_walls(&walls, v, clearance)
+    };
+
+    let mut vents = vec![];
+    if options.vent_spacing > 0. {
+        let spacing = options.vent_spacing;
+        let mut y = spacing / 2.;
+        while y < height {
+            let mut x = spacing / 2.;
+            while x < width {
+                let v = Vert::new(x, y);
+                if fits(&v) {
+                    vents.push(v);
+                }
+                x += spacing;
+            }
+            y += spacing;
+        }
+    }
+
+    for hole in nodes.iter().flat_map(|n| n.children.iter()) {
+        if vents.iter().any(|v| ring_contains(&hole.data, v)) {
+            continue;
+        }
+        if let Some(v) = interior_points(&hole.data).into_iter().find(&fits) {
+            vents.push(v);
+        }
+    }
+    vents
+}
+
+/// Turn the traced edges into a thermoform master covering 0 to width and 0 to height.
+/// A negative mold cuts the shapes into the block, so its pockets are the size of the
+/// shapes at the bottom and open out by the draft angle.
+/// The edge profile from the stl options is not used, the walls always follow the draft.
+pub fn mold_triangles(
+    edges: Vec<Edge>,
+    width: f64,
+    height: f64,
+    relief_height: f64,
+    options: &MoldOptions,
+) -> Result<Vec<f64>, ConversionError> {
+    let block = rectangle(0., 0., width, height, false);
+    let nodes = match options.kind {
+        MoldKind::Positive => sort_edges(edges)?,
+        MoldKind::Negative => pocket_nodes(edges, block.clone())?,
+    };
+
+    let vents = if options.vent_diameter > 0. {
+        vent_positions(&nodes, &block, width, height, options)
+    } else {
+        vec![]
+    };
+    info!("made {} vents for the mold", vents.len());
+
+    let mut base_edges: Vec<Edge> = vents
+        .iter()
+        .map(|v| circle(v.x, v.y, options.vent_diameter / 2., VENT_SEGMENTS, true))
+        .collect();
+    base_edges.push(block);
+
+    let profile = EdgeProfile::Draft {
+        angle: options.draft_angle,
+    };
+    let base = options.base_thickness;
+    extrude_steps(
+        &sort_edges(base_edges)?,
+        &nodes,
+        base,
+        base + relief_height,
+        profile,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::is_watertight;
+    use std::error::Error;
+
+    fn ring_with_hole() -> Vec<Edge> {
+        vec![
+            rectangle(30., 30., 40., 40., true),
+            rectangle(20., 20., 60., 60., false),
+        ]
+    }
+
+    fn max_z(tris: &[f64]) -> f64 {
+        tris.chunks(3).map(|v| v[2]).fold(f64::MIN, f64::max)
+    }
+
+    #[test]
+    fn test_positive_vents_avoid_the_relief() -> Result<(), Box<dyn Error>> {
+        let options = MoldOptions {
+            vent_diameter: 2.,
+            vent_spacing: 25.,
+            ..Default::default()
+        };
+        let nodes = sort_edges(ring_with_hole())?;
+        let block = rectangle(0., 0., 100., 100., false);
+        let vents = vent_positions(&nodes, &block, 100., 100., &options);
+        let walls = rings(&nodes);
+        assert!(!vents.is_empty());
+        assert!(vents.iter().all(|v| !in_material(&walls, v)));
+        // the hole in the middle gets a vent from the grid or its own
+        assert!(vents
+            .iter()
+            .any(|v| v.x > 30. && v.x < 70. && v.y > 30. && v.y < 70.));
+        Ok(())
+    }
+
+    #[test]
+    fn test_negative_pocket_gets_a_vent() -> Result<(), Box<dyn Error>> {
+        let options = MoldOptions {
+            kind: MoldKind::Negative,
+            vent_diameter: 2.,
+            vent_spacing: 0.,
+            ..Default::default()
+        };
+        let square = rectangle(10., 10., 10., 10., false);
+        let block = rectangle(0., 0., 100., 100., false);
+        let nodes = pocket_nodes(vec![square.clone()], block.clone())?;
+        let vents = vent_positions(&nodes, &block, 100., 100., &options);
+        assert_eq!(vents.len(), 1);
+        assert!(ring_contains(&square, &vents[0]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_mold_heights() -> Result<(), Box<dyn Error>> {
+        let options = MoldOptions::default();
+        let positive = mold_triangles(ring_with_hole(), 100., 100., 5., &options)?;
+        assert_eq!(max_z(&positive), options.base_thickness + 5.);
+        let negative = mold_triangles(
+            ring_with_hole(),
+            100.,
+            100.,
+            5.,
+            &MoldOptions {
+                kind: MoldKind::Negative,
+                ..options
+            },
+        )?;
+        assert_eq!(max_z(&negative), options.base_thickness + 5.);
+        assert!(is_watertight(&positive));
+        assert!(is_watertight(&negative));
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::boolean::{boolean_edges, BooleanOp};
+use crate::edge::Edge;
+use crate::error::ConversionError;
+use crate::shapes::{arc_points, close_ring};
+use crate::tree::Node;
+use crate::vert::Vert;
+
+/// number of pieces of a full turn of a round corner
+static ROUND_SEGMENTS: usize = 32;
+
+/// How the outside of a corner is filled in when its sides are moved apart
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OffsetJoin {
+    /// The sides are extended until they meet. Sharp corners would reach far away, so a corner
+    /// further than `limit` times the distance is cut off square at that distance.
+    Miter { limit: f64 },
+    /// an arc around the corner
+    #[default]
+    Round,
+    /// cut off square at the distance
+    Square,
+}
+
+impl OffsetJoin {
+    pub const NAMES: [&'static str; 3] = ["miter", "round", "square"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "miter" => Some(OffsetJoin::Miter { limit: 2. }),
+            "round" => Some(OffsetJoin::Round),
+            "square" => Some(OffsetJoin::Square),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OffsetJoin::Miter { .. } => "miter",
+            OffsetJoin::Round => "round",
+            OffsetJoin::Square => "square",
+        }
+    }
+}
+
+fn unit(v: Vert) -> Vert {
+    let length = v.magnitude();
+    Vert::new(v.x / length, v.y / length)
+}
+
+fn along(v: &Vert, direction: &Vert, distance: f64) -> Vert {
+    Vert::new(v.x + direction.x * distance, v.y + direction.y * distance)
+}
+
+/// Where a corner at v ends up when the sides next to it move `distance` along their normals n1
+/// and n2 and are extended until they meet. A corner further than `limit` times the distance from
+/// v is pulled back towards v, and one where a side turns right back does not move.
+fn miter_point(v: &Vert, n1: &Vert, n2: &Vert, distance: f64, limit: f64) -> Vert {
+    let sum = Vert::new(n1.x + n2.x, n1.y + n2.y);
+    if sum.magnitude() < 1e-9 {
+        return v.clone();
+    }
+    let stretch = 1. + n1.dot_product(n2);
+    let reach = (2. / stretch).sqrt();
+    let pull = if reach > limit { limit / reach } else { 1. };
+    along(v, &sum, pull * distance / stretch)
+}
+
+/// Move every corner of a closed edge `distance` towards its filled side, mitred like
+/// `OffsetJoin::Miter`. Unlike `offset_edges` there is one point for each corner, so the result
+/// lines up with the edge point by point, and corners past the limit are pulled in rather than
+/// cut off. It overlaps itself when the distance is more than half the width of the thinnest part.
+pub fn miter_edge(edge: &Edge, distance: f64, limit: f64) -> Edge {
+    // the filled side is on the left of both outlines and holes
+    let left_normal = |from: &Vert, to: &Vert| -> Option<Vert> {
+        let d = to.diff(from);
+        (d.magnitude() > 0.).then(|| unit(Vert::new(-d.y, d.x)))
+    };
+    let n = edge.len() - 1;
+    let verts: Vec<Vert> = (0..n)
+        .map(|i| {
+            let (prev, current, next) = (&edge[(i + n - 1) % n], &edge[i], &edge[(i + 1) % n]);
+            match (left_normal(prev, current), left_normal(current, next)) {
+                (Some(a), Some(b)) => miter_point(current, &a, &b, distance, limit),
+                (Some(a), None) | (None, Some(a)) => along(current, &a, distance),
+                (None, None) => current.clone(),
+            }
+        })
+        .collect();
+    let first = verts[0].clone();
+    Edge::from(verts.into_iter().chain([first]).collect::<Vec<Vert>>())
+}
+
+/// The corner past the ends of the sides moved out from v along the normals n1 and n2, cut off
+/// square `cut` from v. The sides go along t1 into the corner and along t2 out of it.
+fn cut_corner(
+    v: &Vert,
+    n1: &Vert,
+    n2: &Vert,
+    t1: &Vert,
+    t2: &Vert,
+    distance: f64,
+    cut: f64,
+) -> Vec<Vert> {
+    let sum = Vert::new(n1.x + n2.x, n1.y + n2.y);
+    // a side that turns right back goes on past the corner
+    let out = if sum.magnitude() < 1e-9 {
+        t1.clone()
+    } else {
+        unit(sum)
+    };
+    let (p1, p2) = (along(v, n1, distance), along(v, n2, distance));
+    let s1 = (cut - distance * n1.dot_product(&out)) / t1.dot_product(&out);
+    let s2 = (cut - distance * n2.dot_product(&out)) / -t2.dot_product(&out);
+    vec![
+        v.clone(),
+        p1.clone(),
+        along(&p1, t1, s1),
+        along(&p2, t2, -s2),
+        p2,
+    ]
+}
+
+/// The pieces covering everything within the distance of the edge on one side of it, the
+/// empty side when `grow` and the filled side when not
+fn band(edge: &Edge, distance: f64, grow: bool, join: OffsetJoin, out: &mut Vec<Edge>) {
+    let mut points: Vec<Vert> = edge.iter().cloned().collect();
+    points.dedup();
+    if points.len() > 1 && points[0] == points[points.len() - 1] {
+        points.pop();
+    }
+    let n = points.len();
+    if n < 3 {
+        return;
+    }
+    // the filled side is on the left of both outlines and holes
+    let side = if grow { 1. } else { -1. };
+    let directions: Vec<Vert> = (0..n)
+        .map(|i| unit(points[(i + 1) % n].diff(&points[i])))
+        .collect();
+    let normal = |t: &Vert| Vert::new(side * t.y, -side * t.x);
+
+    for i in 0..n {
+        let (a, b) = (&points[i], &points[(i + 1) % n]);
+        let nrm = normal(&directions[i]);
+        out.push(close_ring(
+            vec![
+                a.clone(),
+                b.clone(),
+                along(b, &nrm, distance),
+                along(a, &nrm, distance),
+            ],
+            false,
+        ));
+
+        // only corners pointing to the side need more than the sides next to them
+        let (t1, t2) = (&directions[(i + n - 1) % n], &directions[i]);
+        if t1.determinant(t2) * side <= 0. {
+            continue;
+        }
+        let (n1, n2) = (normal(t1), nrm);
+        let corner = match join {
+            OffsetJoin::Round => {
+                let start = n1.y.atan2(n1.x);
+                let sweep = n1.angle_between(&n2);
+                let segments = (sweep.abs() / std::f64::consts::TAU * ROUND_SEGMENTS as f64).ceil();
+                let mut arc = arc_points(
+                    a.x,
+                    a.y,
+                    distance,
+                    start.to_degrees(),
+                    (start + sweep).to_degrees(),
+                    segments as usize,
+                );
+                arc.insert(0, a.clone());
+                arc
+            }
+            OffsetJoin::Square => cut_corner(a, &n1, &n2, t1, t2, distance, distance),
+            OffsetJoin::Miter { limit } => {
+                let stretch = 1. + n1.dot_product(&n2);
+                let reach = (2. / stretch.max(1e-12)).sqrt();
+                if reach <= limit {
+                    vec![
+                        a.clone(),
+                        along(a, &n1, distance),
+                        miter_point(a, &n1, &n2, distance, limit),
+                        along(a, &n2, distance),
+                    ]
+                } else {
+                    cut_corner(a, &n1, &n2, t1, t2, distance, limit * distance)
+                }
+            }
+        };
+        out.push(close_ring(corner, false));
+    }
+}
+
+/// Move closed edges `distance` away from themselves, growing the filled areas when it is
+/// positive and shrinking them when it is negative. Shapes that grow into each other are joined
+/// and parts thinner than twice the distance shrink away, so the result can have more or fewer
+/// polygons than it started with. The polygons are nested like the ones from `sort_edges`.
+pub fn offset_edges(
+    edges: &[Edge],
+    distance: f64,
+    join: OffsetJoin,
+) -> Result<Vec<Node>, ConversionError> {
+    let grow = distance > 0.;
+    let mut pieces = vec![];
+    if distance != 0. {
+        for edge in edges {
+            band(edge, distance.abs(), grow, join, &mut pieces);
+        }
+    }
+    info!(
+        "offset {} edges by {} with {} pieces",
+        edges.len(),
+        distance,
+        pieces.len()
+    );
+    let op = if grow {
+        BooleanOp::Union
+    } else {
+        BooleanOp::Difference
+    };
+    boolean_edges(edges, &pieces, op)
+}
+
+/// see `offset_edges`
+pub fn offset_nodes(
+    nodes: &[Node],
+    distance: f64,
+    join: OffsetJoin,
+) -> Result<Vec<Node>, ConversionError> {
+    let mut edges = vec![];
+    let mut stack: Vec<&Node> = nodes.iter().collect();
+    while let Some(node) = stack.pop() {
+        edges.push(node.data.clone());
+        stack.extend(node.children.iter());
+    }
+    offset_edges(&edges, distance, join)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::constants::DEFAULT_THRESHOLD_VALUE;
+    use crate::edge_file;
+    use crate::shapes::{rectangle, signed_area};
+    use crate::tree::sort_edges;
+    use crate::util::testing::donut;
+    use std::error::Error;
+
+    fn area(edge: &Edge) -> f64 {
+        signed_area(&edge.iter().cloned().collect::<Vec<Vert>>()).abs() / 2.
+    }
+
+    fn filled_area(nodes: &[Node]) -> f64 {
+        nodes
+            .iter()
+            .map(|n| area(&n.data) - n.children.iter().map(|c| area(&c.data)).sum::<f64>())
+            .sum()
+    }
+
+    fn square() -> Vec<Edge> {
+        vec![rectangle(0., 0., 10., 10., false)]
+    }
+
+    #[test]
+    fn test_grow_a_square() -> Result<(), Box<dyn Error>> {
+        let corner = (2f64.sqrt() - 1.).powi(2);
+        for (join, expected) in [
+            (OffsetJoin::Miter { limit: 2. }, 144.),
+            (OffsetJoin::Square, 144. - 4. * corner),
+            (OffsetJoin::Round, 140. + std::f64::consts::PI),
+        ] {
+            let nodes = offset_edges(&square(), 1., join)?;
+            assert_eq!(nodes.len(), 1);
+            assert!(nodes[0].children.is_empty());
+            // the arcs are cut short by their straight pieces
+            assert!((filled_area(&nodes) - expected).abs() < 0.05, "{:?}", join);
+        }
+        // past the limit the miter is cut square
+        let cut = offset_edges(&square(), 1., OffsetJoin::Miter { limit: 1. })?;
+        assert!((filled_area(&cut) - (144. - 4. * corner)).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_shrink_a_square() -> Result<(), Box<dyn Error>> {
+        for name in OffsetJoin::NAMES {
+            let join = OffsetJoin::from_name(name).unwrap();
+            let nodes = offset_edges(&square(), -1., join)?;
+            assert!((filled_area(&nodes) - 64.).abs() < 1e-6, "{}", name);
+            assert!(offset_edges(&square(), -5.5, join)?.is_empty());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_holes_shrink_when_shapes_grow() -> Result<(), Box<dyn Error>> {
+        let join = OffsetJoin::Miter { limit: 2. };
+        let nodes = offset_edges(&donut(10., 2., 0., 0.), 1., join)?;
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].children.len(), 1);
+        assert!(nodes[0].children[0].data.is_hole());
+        assert!((filled_area(&nodes) - (144. - 16.)).abs() < 1e-6);
+        // the hole closes up
+        let nodes = offset_edges(&donut(10., 2., 0., 0.), 3.5, join)?;
+        assert!(nodes[0].children.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_shapes_join_and_split() -> Result<(), Box<dyn Error>> {
+        let apart = vec![
+            rectangle(0., 0., 10., 10., false),
+            rectangle(11., 0., 10., 10., false),
+        ];
+        assert_eq!(offset_edges(&apart, 1., OffsetJoin::Square)?.len(), 1);
+        // two squares with a thin bridge between them
+        let dumbbell = sort_edges(vec![close_ring(
+            vec![
+                Vert::new(0., 0.),
+                Vert::new(10., 0.),
+                Vert::new(10., 4.5),
+                Vert::new(15., 4.5),
+                Vert::new(15., 0.),
+                Vert::new(25., 0.),
+                Vert::new(25., 10.),
+                Vert::new(15., 10.),
+                Vert::new(15., 5.5),
+                Vert::new(10., 5.5),
+                Vert::new(10., 10.),
+                Vert::new(0., 10.),
+            ],
+            false,
+        )])?;
+        let nodes = offset_nodes(&dumbbell, -1., OffsetJoin::Round)?;
+        assert_eq!(nodes.len(), 2);
+        // two 8 by 8 squares, with a little bump where the bridge was
+        assert!((filled_area(&nodes) - 128.).abs() < 0.2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sharp_corners_are_limited() -> Result<(), Box<dyn Error>> {
+        let spike = vec![close_ring(
+            vec![Vert::new(0., 0.), Vert::new(20., 1.), Vert::new(0., 2.)],
+            false,
+        )];
+        let far = offset_edges(&spike, 1., OffsetJoin::Miter { limit: 100. })?;
+        let near = offset_edges(&spike, 1., OffsetJoin::Miter { limit: 2. })?;
+        assert!(filled_area(&near) < filled_area(&far));
+        let right = far[0].data.iter().map(|v| v.x).fold(f64::MIN, f64::max);
+        assert!(right > 35.);
+        let right = near[0].data.iter().map(|v| v.x).fold(f64::MIN, f64::max);
+        assert!(right < 23.);
+        Ok(())
+    }
+
+    #[test]
+    fn test_miter_edge_keeps_the_corners() {
+        let inset = miter_edge(&square()[0], 1., 2.);
+        assert_eq!(inset.len(), square()[0].len());
+        assert_eq!(inset[0], Vert::new(1., 1.));
+        assert_eq!(inset[2], Vert::new(9., 9.));
+        // the tip of the spike would move 20 in, it is pulled back to twice the distance
+        let spike = close_ring(
+            vec![Vert::new(0., 0.), Vert::new(20., 1.), Vert::new(0., 2.)],
+            false,
+        );
+        let inset = miter_edge(&spike, 1., 2.);
+        assert!((inset[1].diff(&spike[1]).magnitude() - 2.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_offset_traced_image() -> Result<(), Box<dyn Error>> {
+        let edges = edge_file(DEFAULT_THRESHOLD_VALUE, "./images/stick-figure.png")?.closed_edges;
+        let traced = filled_area(&sort_edges(edges.clone())?);
+        let grown = offset_edges(&edges, 2., OffsetJoin::Round)?;
+        let shrunk = offset_edges(&edges, -0.5, OffsetJoin::Round)?;
+        assert!(filled_area(&grown) > traced);
+        assert!(filled_area(&shrunk) < traced);
+        for node in grown.iter().chain(shrunk.iter()) {
+            assert!(!node.data.is_hole());
+            assert!(node.children.iter().all(|c| c.data.is_hole()));
+        }
+        Ok(())
+    }
+}
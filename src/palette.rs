@@ -0,0 +1,310 @@
+use image::imageops::{index_colors, ColorMap};
+use image::{DynamicImage, GrayImage, Luma, Rgb};
+
+use crate::error::ConversionError;
+use crate::texture::Texture;
+
+/// the classes are indexed with a u8
+static MAX_COLORS: usize = 256;
+static MAX_KMEANS_ITERATIONS: usize = 20;
+/// the number of pixels used to fit the k-means palette
+static MAX_KMEANS_SAMPLES: usize = 20_000;
+
+/// A fixed set of colours that an image gets quantized to.
+/// Each colour is a class which is traced separately.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    colors: Vec<Rgb<u8>>,
+}
+
+/// How a single colour class of a palette is turned into relief
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Layer {
+    pub height: f64,
+    pub texture: Texture,
+}
+
+fn distance_squared(a: &[f64; 3], b: &Rgb<u8>) -> f64 {
+    (0..3).map(|i| (a[i] - b[i] as f64).powi(2)).sum()
+}
+
+fn luma(c: &Rgb<u8>) -> f64 {
+    0.2126 * (c[0] as f64) + 0.7152 * (c[1] as f64) + 0.0722 * (c[2] as f64)
+}
+
+fn to_f64(c: &Rgb<u8>) -> [f64; 3] {
+    [c[0] as f64, c[1] as f64, c[2] as f64]
+}
+
+impl Palette {
+    /// A palette of between one and 256 colours
+    pub fn new(colors: Vec<Rgb<u8>>) -> Result<Self, ConversionError> {
+        if colors.is_empty() {
+            return Err(ConversionError::InvalidOptions(
+                "a palette needs at least one color".to_string(),
+            ));
+        }
+        if colors.len() > MAX_COLORS {
+            return Err(ConversionError::InvalidOptions(format!(
+                "a palette can have at most {} colors, not {}",
+                MAX_COLORS,
+                colors.len()
+            )));
+        }
+        Ok(Self { colors })
+    }
+
+    /// Fit a palette of at most k colours to the image with k-means.
+    /// The colours are ordered from darkest to lightest, so the result is
+    /// the same every time for a given image.
+    pub fn kmeans(img: &DynamicImage, k: usize) -> Result<Self, ConversionError> {
+        let rgb = img.to_rgb8();
+        let n_pixels = rgb.pixels().len();
+        let step = (n_pixels / MAX_KMEANS_SAMPLES).max(1);
+        let samples: Vec<Rgb<u8>> = rgb.pixels().step_by(step).copied().collect();
+        if samples.is_empty() {
+            return Err(ConversionError::InvalidOptions(
+                "can not make a palette from an empty image".to_string(),
+            ));
+        }
+
+        // initialize with the samples that are furthest from the chosen centers
+        let mut centers: Vec<[f64; 3]> = vec![to_f64(&samples[0])];
+        while centers.len() < k {
+            let (furthest, dist) = samples
+                .iter()
+                .map(|s| {
+                    centers
+                        .iter()
+                        .map(|c| distance_squared(c, s))
+                        .fold(f64::INFINITY, f64::min)
+                })
+                .enumerate()
+                .fold((0, 0.), |acc, (i, d)| if d > acc.1 { (i, d) } else { acc });
+            if dist == 0. {
+                // fewer distinct colours than k
+                break;
+            }
+            centers.push(to_f64(&samples[furthest]));
+        }
+
+        let mut assignments = vec![usize::MAX; samples.len()];
+        for _ in 0..MAX_KMEANS_ITERATIONS {
+            let mut changed = false;
+            for (s, assignment) in samples.iter().zip(assignments.iter_mut()) {
+                let nearest = nearest_center(&centers, s);
+                if nearest != *assignment {
+                    *assignment = nearest;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+
+            let mut sums = vec![([0f64; 3], 0usize); centers.len()];
+            for (s, &a) in samples.iter().zip(assignments.iter()) {
+                for c in 0..3 {
+                    sums[a].0[c] += s[c] as f64;
+                }
+                sums[a].1 += 1;
+            }
+            for (center, (sum, count)) in centers.iter_mut().zip(sums) {
+                // empty clusters keep their old center
+                if count > 0 {
+                    *center = sum.map(|x| x / count as f64);
+                }
+            }
+        }
+
+        let mut colors: Vec<Rgb<u8>> = centers
+            .iter()
+            .map(|c| Rgb(c.map(|x| x.round() as u8)))
+            .collect();
+        colors.sort_by(|a, b| luma(a).total_cmp(&luma(b)));
+        colors.dedup();
+        Self::new(colors)
+    }
+
+    pub fn colors(&self) -> &[Rgb<u8>] {
+        &self.colors
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+}
+
+fn nearest_center(centers: &[[f64; 3]], color: &Rgb<u8>) -> usize {
+    centers
+        .iter()
+        .map(|c| distance_squared(c, color))
+        .enumerate()
+        .fold((0, f64::INFINITY), |acc, (i, d)| if d < acc.1 { (i, d) } else { acc })
+        .0
+}
+
+impl ColorMap for Palette {
+    type Color = Rgb<u8>;
+
+    #[inline(always)]
+    fn index_of(&self, color: &Rgb<u8>) -> usize {
+        let distance = |c: &Rgb<u8>| -> i32 {
+            (0..3).map(|i| (c[i] as i32 - color[i] as i32).pow(2)).sum()
+        };
+        self.colors
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, c)| distance(c))
+            .map(|(i, _)| i)
+            .expect("palettes are never empty")
+    }
+
+    #[inline(always)]
+    fn lookup(&self, idx: usize) -> Option<Self::Color> {
+        self.colors.get(idx).copied()
+    }
+
+    fn has_lookup(&self) -> bool {
+        true
+    }
+
+    #[inline(always)]
+    fn map_color(&self, color: &mut Rgb<u8>) {
+        *color = self.colors[self.index_of(color)];
+    }
+}
+
+/// Split an image into one thresholded mask per palette colour.
+/// Like the output of `ret_thresholded_img`, black pixels in each mask are the shape,
+/// so the masks can be passed straight to `edge_img`.
+pub fn split_layers(img: &DynamicImage, palette: &Palette) -> Vec<DynamicImage> {
+    let indexes = index_colors(&img.to_rgb8(), palette);
+    (0..palette.len())
+        .map(|class| {
+            let mask = GrayImage::from_fn(indexes.width(), indexes.height(), |x, y| {
+                if indexes.get_pixel(x, y).0[0] as usize == class {
+                    Luma([0])
+                } else {
+                    Luma([255])
+                }
+            });
+            mask.into()
+        })
+        .collect()
+}
+
+/// Split an image into per colour masks with the layer's texture applied.
+/// Classes without a layer are left out.
+pub fn textured_layers(
+    img: &DynamicImage,
+    palette: &Palette,
+    layers: &[Option<Layer>],
+) -> Vec<(Layer, DynamicImage)> {
+    split_layers(img, palette)
+        .into_iter()
+        .zip(layers)
+        .filter_map(|(mask, layer)| {
+            layer.map(|layer| {
+                let mut mask = mask.into_luma8();
+                layer.texture.apply(&mut mask);
+                (layer, mask.into())
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+    use std::error::Error;
+
+    static RED: Rgb<u8> = Rgb([200, 20, 20]);
+    static BLUE: Rgb<u8> = Rgb([20, 20, 200]);
+    static WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+
+    /// white image with a red block on the left and a blue block on the right
+    fn two_blocks() -> DynamicImage {
+        RgbImage::from_fn(40, 20, |x, y| {
+            if !(5..15).contains(&y) {
+                WHITE
+            } else if (5..15).contains(&x) {
+                RED
+            } else if (25..35).contains(&x) {
+                BLUE
+            } else {
+                WHITE
+            }
+        })
+        .into()
+    }
+
+    #[test]
+    fn test_index_of_nearest_color() -> Result<(), Box<dyn Error>> {
+        let palette = Palette::new(vec![RED, BLUE, WHITE])?;
+        assert_eq!(palette.index_of(&Rgb([250, 0, 0])), 0);
+        assert_eq!(palette.index_of(&Rgb([0, 0, 250])), 1);
+        assert_eq!(palette.index_of(&Rgb([240, 240, 240])), 2);
+        assert_eq!(palette.lookup(1), Some(BLUE));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_palettes() {
+        assert!(Palette::new(vec![]).is_err());
+        let too_many = (0..=256).map(|i| Rgb([(i % 256) as u8, (i / 256) as u8, 0]));
+        assert!(Palette::new(too_many.collect()).is_err());
+        let empty = DynamicImage::new_rgb8(0, 0);
+        assert!(Palette::kmeans(&empty, 3).is_err());
+    }
+
+    #[test]
+    fn test_kmeans_finds_colors() -> Result<(), Box<dyn Error>> {
+        let palette = Palette::kmeans(&two_blocks(), 3)?;
+        assert_eq!(palette.len(), 3);
+        for c in [RED, BLUE, WHITE] {
+            assert!(palette.colors().contains(&c), "missing {c:?}");
+        }
+        // sorted darkest first
+        assert_eq!(palette.colors()[2], WHITE);
+        Ok(())
+    }
+
+    #[test]
+    fn test_kmeans_fewer_colors_than_k() -> Result<(), Box<dyn Error>> {
+        let palette = Palette::kmeans(&two_blocks(), 8)?;
+        assert_eq!(palette.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_layers() -> Result<(), Box<dyn Error>> {
+        let palette = Palette::new(vec![RED, BLUE, WHITE])?;
+        let layers = split_layers(&two_blocks(), &palette);
+        assert_eq!(layers.len(), 3);
+        let red = layers[0].to_luma8();
+        assert_eq!(red.get_pixel(10, 10).0[0], 0);
+        assert_eq!(red.get_pixel(30, 10).0[0], 255);
+        let black_pixels = red.pixels().filter(|p| p.0[0] == 0).count();
+        assert_eq!(black_pixels, 100);
+        Ok(())
+    }
+
+    #[test]
+    fn test_textured_layers_skips_missing() -> Result<(), Box<dyn Error>> {
+        let palette = Palette::new(vec![RED, BLUE, WHITE])?;
+        let layer = Layer {
+            height: 2.,
+            texture: Texture::default(),
+        };
+        let layers = textured_layers(&two_blocks(), &palette, &[Some(layer), None, None]);
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0].0, layer);
+        Ok(())
+    }
+}
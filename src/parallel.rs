@@ -0,0 +1,152 @@
+use image::{DynamicImage, GrayImage};
+use log::info;
+use rayon::prelude::*;
+
+use crate::edge_collection::Edges;
+use crate::error::ConversionError;
+use crate::frame::{border_edges, clear_border, BorderOptions};
+use crate::lattice::{check_traceable, Tracer};
+use crate::ribbon::EdgeProfile;
+use crate::stream::PolygonSorter;
+use crate::threshold_img;
+use crate::triangle::{extrude_edges_with_profile, extrude_nodes_with_profile};
+
+/// fewest rows of cells in a strip, thinner strips spend more time being joined than traced
+static MIN_STRIP_ROWS: u32 = 64;
+
+/// Like `edge_img` with the image cut into horizontal strips that are traced at the same time.
+/// The outlines crossing from one strip to the next are joined afterwards, so the edges are
+/// the same and in the same order.
+pub fn edge_img_in_strips(img: &GrayImage) -> Result<Edges, ConversionError> {
+    check_traceable(img.width(), img.height())?;
+    let strips =
+        ((img.height() - 1) / MIN_STRIP_ROWS).clamp(1, rayon::current_num_threads() as u32);
+    trace_strips(img, strips)
+}
+
+fn trace_strips(img: &GrayImage, strips: u32) -> Result<Edges, ConversionError> {
+    let rows = img.height() - 1;
+    let tracers = (0..strips)
+        .into_par_iter()
+        .map(|i| {
+            let mut tracer = Tracer::strip(img, i * rows / strips..(i + 1) * rows / strips)?;
+            tracer.trace();
+            Ok(tracer)
+        })
+        .collect::<Result<Vec<Tracer>, ConversionError>>()?;
+    info!("traced {} strips", strips);
+    let mut tracers = tracers.into_iter();
+    let mut out = tracers.next().expect("there is at least one strip");
+    for below in tracers {
+        out.join(below);
+    }
+    out.finish()
+}
+
+/// The triangles `image_to_triangle_stream` hands out, all at once. The image is traced in
+/// strips and the polygons are triangulated at the same time, on every core.
+pub fn image_to_triangles(
+    mut img: DynamicImage,
+    threshold: u8,
+    height: f64,
+    scale_factor: f64,
+    profile: EdgeProfile,
+    border: Option<&BorderOptions>,
+) -> Result<Vec<f64>, ConversionError> {
+    check_traceable(img.width(), img.height())?;
+    threshold_img(&mut img, threshold);
+    info!("thresholded image for stl");
+    let border = match border {
+        Some(border) => {
+            clear_border(&mut img, border);
+            border_edges(img.width(), img.height(), border)
+        }
+        None => vec![],
+    };
+    let edges = edge_img_in_strips(&img.into_luma8())?;
+
+    let mut sorter = PolygonSorter::default();
+    let mut polygons = vec![];
+    for edge in edges.closed_edges {
+        if let Some(node) = sorter.push(edge)? {
+            polygons.push(node);
+        }
+    }
+    sorter.finish()?;
+    info!("sorted {} polygons for stl", polygons.len());
+
+    let mut tris = polygons
+        .into_par_iter()
+        .map(|node| extrude_nodes_with_profile(vec![node], height, profile))
+        .collect::<Result<Vec<Vec<f64>>, ConversionError>>()?;
+    if !border.is_empty() {
+        tris.push(extrude_edges_with_profile(border, height, profile)?);
+    }
+    Ok(tris
+        .concat()
+        .into_iter()
+        .map(|v| v * scale_factor)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::constants::DEFAULT_THRESHOLD_VALUE;
+    use crate::stream::image_to_triangle_stream;
+    use image::io::Reader as ImageReader;
+    use std::error::Error;
+
+    fn open(filename: &str) -> Result<DynamicImage, Box<dyn Error>> {
+        Ok(ImageReader::open(filename)?
+            .with_guessed_format()?
+            .decode()?)
+    }
+
+    #[test]
+    fn test_strips_trace_the_same_edges() -> Result<(), Box<dyn Error>> {
+        for name in ["./images/stick-figure.png", "./images/moontower.png"] {
+            let mut img = open(name)?;
+            threshold_img(&mut img, DEFAULT_THRESHOLD_VALUE);
+            let img = img.into_luma8();
+            let mut tracer = Tracer::new(img.clone())?;
+            tracer.trace();
+            let whole = tracer.finish()?;
+            for strips in [2, 3, 7, 50] {
+                let edges = trace_strips(&img, strips)?;
+                assert!(edges.edges.is_empty());
+                assert_eq!(
+                    edges.closed_edges, whole.closed_edges,
+                    "{} in {}",
+                    name, strips
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_triangles_as_the_stream() -> Result<(), Box<dyn Error>> {
+        let img = open("./images/moontower.png")?;
+        let border = BorderOptions::default();
+        let streamed: Vec<Vec<f64>> = image_to_triangle_stream(
+            img.clone(),
+            DEFAULT_THRESHOLD_VALUE,
+            2.,
+            0.5,
+            EdgeProfile::Vertical,
+            Some(&border),
+        )?
+        .collect::<Result<_, _>>()?;
+        let tris = image_to_triangles(
+            img,
+            DEFAULT_THRESHOLD_VALUE,
+            2.,
+            0.5,
+            EdgeProfile::Vertical,
+            Some(&border),
+        )?;
+        assert_eq!(tris, streamed.concat());
+        Ok(())
+    }
+}
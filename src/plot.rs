@@ -0,0 +1,670 @@
+use serde::{Deserialize, Serialize};
+
+use crate::braille::braille_cells;
+use crate::chart::{
+    nice_range, tick_label, tick_values, Chart, ChartOptions, LABEL_GAP, MARGIN, TICK_LENGTH,
+};
+use crate::error::ConversionError;
+use crate::stl::write_stl_3d;
+use crate::vert::Vert;
+
+/// evenly spaced samples taken before refining
+static INITIAL_SAMPLES: usize = 64;
+/// how many times a step between samples can be halved
+static MAX_DEPTH: usize = 10;
+/// how far, in millimeters on the plot, the curve may be from a straight line between samples
+static TOLERANCE: f64 = 0.05;
+/// part of the samples ignored at each end when finding the range of values automatically
+static OUTLIER_FRACTION: f64 = 0.02;
+
+/// an operator between two expressions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+/// a function of one value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Func {
+    Sin,
+    Cos,
+    Tan,
+    Exp,
+    Ln,
+    Log10,
+    Sqrt,
+    Abs,
+}
+
+impl Func {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sin" => Some(Func::Sin),
+            "cos" => Some(Func::Cos),
+            "tan" => Some(Func::Tan),
+            "exp" => Some(Func::Exp),
+            "ln" | "log" => Some(Func::Ln),
+            "log10" => Some(Func::Log10),
+            "sqrt" => Some(Func::Sqrt),
+            "abs" => Some(Func::Abs),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, v: f64) -> f64 {
+        match self {
+            Func::Sin => v.sin(),
+            Func::Cos => v.cos(),
+            Func::Tan => v.tan(),
+            Func::Exp => v.exp(),
+            Func::Ln => v.ln(),
+            Func::Log10 => v.log10(),
+            Func::Sqrt => v.sqrt(),
+            Func::Abs => v.abs(),
+        }
+    }
+}
+
+/// A parsed expression in x
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    X,
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(Func, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Symbol(char),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, ConversionError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(number.parse().map_err(|_| {
+                ConversionError::Parse(format!("bad number '{}'", number))
+            })?));
+        } else if c.is_ascii_alphabetic() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            tokens.push(Token::Name(
+                chars[start..i].iter().collect::<String>().to_lowercase(),
+            ));
+        } else if "+-*/^()".contains(c) {
+            tokens.push(Token::Symbol(c));
+            i += 1;
+        } else {
+            return Err(ConversionError::Parse(format!(
+                "unexpected '{}' in expression",
+                c
+            )));
+        }
+    }
+    Ok(tokens)
+}
+
+/// recursive descent over the tokens, with the usual precedence
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// sum := product (('+' | '-') product)*
+    fn sum(&mut self) -> Result<Expr, ConversionError> {
+        let mut left = self.product()?;
+        loop {
+            let op = if self.eat('+') {
+                Op::Add
+            } else if self.eat('-') {
+                Op::Sub
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.product()?));
+        }
+    }
+
+    /// product := unary (('*' | '/')? unary)*, where leaving out the '*' like in 2x multiplies
+    fn product(&mut self) -> Result<Expr, ConversionError> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                Op::Mul
+            } else if self.eat('/') {
+                Op::Div
+            } else if matches!(
+                self.peek(),
+                Some(Token::Number(_)) | Some(Token::Name(_)) | Some(Token::Symbol('('))
+            ) {
+                Op::Mul
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    /// unary := '-' unary | power
+    fn unary(&mut self) -> Result<Expr, ConversionError> {
+        if self.eat('-') {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    /// power := primary ('^' unary)?, so 2^-x and 2^3^2 work as written
+    fn power(&mut self) -> Result<Expr, ConversionError> {
+        let base = self.primary()?;
+        if self.eat('^') {
+            return Ok(Expr::Binary(
+                Op::Pow,
+                Box::new(base),
+                Box::new(self.unary()?),
+            ));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Expr, ConversionError> {
+        match self.next() {
+            Some(Token::Number(v)) => Ok(Expr::Number(v)),
+            Some(Token::Symbol('(')) => {
+                let inner = self.sum()?;
+                if !self.eat(')') {
+                    return Err(ConversionError::Parse("missing ')'".to_string()));
+                }
+                Ok(inner)
+            }
+            Some(Token::Name(name)) => match name.as_str() {
+                "x" => Ok(Expr::X),
+                "pi" => Ok(Expr::Number(std::f64::consts::PI)),
+                "e" => Ok(Expr::Number(std::f64::consts::E)),
+                _ => {
+                    let func = Func::from_name(&name).ok_or_else(|| {
+                        ConversionError::Parse(format!("unknown name '{}'", name))
+                    })?;
+                    if !self.eat('(') {
+                        return Err(ConversionError::Parse(format!(
+                            "'{}' needs its argument in brackets",
+                            name
+                        )));
+                    }
+                    let argument = self.sum()?;
+                    if !self.eat(')') {
+                        return Err(ConversionError::Parse("missing ')'".to_string()));
+                    }
+                    Ok(Expr::Call(func, Box::new(argument)))
+                }
+            },
+            Some(token) => Err(ConversionError::Parse(format!(
+                "unexpected {:?} in expression",
+                token
+            ))),
+            None => Err(ConversionError::Parse(
+                "the expression ends too soon".to_string(),
+            )),
+        }
+    }
+}
+
+impl Expr {
+    /// Parse an expression in x with + - * / ^, brackets, the constants pi and e and the
+    /// functions sin, cos, tan, exp, ln (or log), log10, sqrt and abs.
+    pub fn parse(text: &str) -> Result<Self, ConversionError> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+        let expr = parser.sum()?;
+        if let Some(token) = parser.peek() {
+            return Err(ConversionError::Parse(format!(
+                "unexpected {:?} after the expression",
+                token
+            )));
+        }
+        Ok(expr)
+    }
+
+    pub fn eval(&self, x: f64) -> f64 {
+        match self {
+            Expr::Number(v) => *v,
+            Expr::X => x,
+            Expr::Neg(e) => -e.eval(x),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(x), b.eval(x));
+                match op {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    Op::Div => a / b,
+                    Op::Pow => a.powf(b),
+                }
+            }
+            Expr::Call(func, e) => func.apply(e.eval(x)),
+        }
+    }
+}
+
+/// Sample the expression from x0 to x1, halving steps where the curve bends more than the
+/// tolerance. `y_scale` turns values into millimeters. Points where the expression is not
+/// defined are None, which breaks the curve.
+fn sample(expr: &Expr, x0: f64, x1: f64, y_scale: f64, x_scale: f64) -> Vec<Option<Vert>> {
+    let point = |x: f64| {
+        let y = expr.eval(x);
+        if y.is_finite() {
+            Some(Vert::new(x, y))
+        } else {
+            None
+        }
+    };
+
+    fn refine(
+        point: &dyn Fn(f64) -> Option<Vert>,
+        a: (f64, Option<Vert>),
+        b: (f64, Option<Vert>),
+        depth: usize,
+        scales: (f64, f64),
+        out: &mut Vec<Option<Vert>>,
+    ) {
+        let m = (a.0 + b.0) / 2.;
+        let pm = point(m);
+        let bends = match (&a.1, &pm, &b.1) {
+            (Some(pa), Some(pm), Some(pb)) => {
+                let straight = (pa.y + pb.y) / 2.;
+                (pm.y - straight).abs() * scales.0 > TOLERANCE
+            }
+            // find where the curve stops being defined as closely as we can
+            (pa, pm, pb) => pa.is_some() || pm.is_some() || pb.is_some(),
+        };
+        let wide = (b.0 - a.0) * scales.1 > TOLERANCE;
+        if depth < MAX_DEPTH && bends && wide {
+            refine(point, a, (m, pm.clone()), depth + 1, scales, out);
+            refine(point, (m, pm), b, depth + 1, scales, out);
+        } else {
+            out.push(pm);
+            out.push(b.1);
+        }
+    }
+
+    let step = (x1 - x0) / INITIAL_SAMPLES as f64;
+    let mut out = vec![point(x0)];
+    for i in 0..INITIAL_SAMPLES {
+        let a = x0 + i as f64 * step;
+        let b = if i + 1 == INITIAL_SAMPLES {
+            x1
+        } else {
+            a + step
+        };
+        refine(
+            &point,
+            (a, point(a)),
+            (b, point(b)),
+            0,
+            (y_scale, x_scale),
+            &mut out,
+        );
+    }
+    out
+}
+
+/// Whether the curve runs on from a to b rather than breaking between them, like tan(x)
+/// does at its asymptotes. The value halfway is near the middle of the two when it does.
+fn continues(eval: &dyn Fn(f64) -> f64, a: &Vert, b: &Vert) -> bool {
+    let mid = eval((a.x + b.x) / 2.);
+    mid.is_finite() && (mid - (a.y + b.y) / 2.).abs() <= (b.y - a.y).abs() / 4.
+}
+
+/// Split samples of `eval` into lines inside y0 to y1, cutting them where they leave the
+/// range. A jump of more than the whole range between neighboring samples is only drawn
+/// when the curve does not break there.
+fn clip_curve(
+    samples: &[Option<Vert>],
+    eval: &dyn Fn(f64) -> f64,
+    y0: f64,
+    y1: f64,
+) -> Vec<Vec<Vert>> {
+    let mut lines: Vec<Vec<Vert>> = vec![];
+    let mut current: Vec<Vert> = vec![];
+    for pair in samples.windows(2) {
+        let (a, b) = match (&pair[0], &pair[1]) {
+            (Some(a), Some(b)) if (b.y - a.y).abs() <= y1 - y0 || continues(eval, a, b) => (a, b),
+            _ => {
+                if current.len() > 1 {
+                    lines.push(std::mem::take(&mut current));
+                }
+                current.clear();
+                continue;
+            }
+        };
+        // the part of the segment from a to b inside the range
+        let (mut t0, mut t1) = (0f64, 1f64);
+        let dy = b.y - a.y;
+        for (limit, below) in [(y0, true), (y1, false)] {
+            if dy == 0. {
+                if (below && a.y < limit) || (!below && a.y > limit) {
+                    t1 = -1.;
+                }
+                continue;
+            }
+            let t = (limit - a.y) / dy;
+            if (dy > 0.) == below {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+        if t0 > t1 {
+            if current.len() > 1 {
+                lines.push(std::mem::take(&mut current));
+            }
+            current.clear();
+            continue;
+        }
+        let at = |t: f64| Vert::new(a.x + t * (b.x - a.x), a.y + t * dy);
+        let start = at(t0);
+        if current.last() != Some(&start) {
+            if current.len() > 1 {
+                lines.push(std::mem::take(&mut current));
+            }
+            current = vec![start];
+        }
+        current.push(at(t1));
+        if t1 < 1. {
+            lines.push(std::mem::take(&mut current));
+        }
+    }
+    if current.len() > 1 {
+        lines.push(current);
+    }
+    lines
+}
+
+/// the range between the outliers at each end of the values
+fn auto_range(samples: &[Option<Vert>]) -> (f64, f64) {
+    let mut ys: Vec<f64> = samples.iter().flatten().map(|v| v.y).collect();
+    if ys.is_empty() {
+        return (-1., 1.);
+    }
+    ys.sort_by(f64::total_cmp);
+    let skip = (ys.len() as f64 * OUTLIER_FRACTION) as usize;
+    (ys[skip], ys[ys.len() - 1 - skip])
+}
+
+/// What to plot and where. The chart options give the size of the plot and the lines.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PlotOptions {
+    pub x_min: f64,
+    pub x_max: f64,
+    /// The range of values shown. When left out it is found from the curve,
+    /// ignoring the highest and lowest few values so asymptotes do not squash the rest.
+    pub y_range: Option<(f64, f64)>,
+    pub chart: ChartOptions,
+}
+
+impl Default for PlotOptions {
+    fn default() -> Self {
+        Self {
+            x_min: -10.,
+            x_max: 10.,
+            y_range: None,
+            chart: ChartOptions::default(),
+        }
+    }
+}
+
+/// Lay out the graph of an expression in x with its axes, grid and braille tick labels.
+/// Use `Chart::triangles` for a tactile plate or `Chart::to_image` for swell paper.
+pub fn plot_expression(text: &str, options: &PlotOptions) -> Result<Chart, ConversionError> {
+    let expr = Expr::parse(text)?;
+    if options.x_max <= options.x_min {
+        return Err(ConversionError::InvalidOptions(
+            "the end of the x range must be after the start".to_string(),
+        ));
+    }
+    let chart_options = &options.chart;
+    let (w, h) = (chart_options.width, chart_options.height);
+    let ticks = chart_options.ticks;
+    let dims = &chart_options.braille;
+
+    let (x_lo, x_hi, x_step) = nice_range([options.x_min, options.x_max].into_iter(), ticks, false);
+    let (y_min, y_max) = options
+        .y_range
+        .unwrap_or_else(|| auto_range(&sample(&expr, options.x_min, options.x_max, 0., 0.)));
+    let (y_lo, y_hi, y_step) = nice_range([y_min, y_max].into_iter(), ticks, false);
+    let y_ticks = tick_values(y_lo, y_hi, y_step);
+
+    let label_width = |text: &str| dims.width(braille_cells(text).len());
+    let y_label_width = y_ticks
+        .iter()
+        .map(|v| label_width(&tick_label(*v)))
+        .fold(0., f64::max);
+    let ox = MARGIN + y_label_width + LABEL_GAP + TICK_LENGTH;
+    let oy = MARGIN + dims.height() + LABEL_GAP + TICK_LENGTH;
+    let x_at = |x: f64| ox + (x - x_lo) / (x_hi - x_lo) * w;
+    let y_at = |y: f64| oy + (y - y_lo) / (y_hi - y_lo) * h;
+
+    let mut chart = Chart {
+        width: ox + w + MARGIN,
+        height: oy + h + MARGIN,
+        shapes: vec![],
+        labels: vec![],
+    };
+    let relief = chart_options.relief_height;
+    let lw = chart_options.line_width;
+
+    // the axes cross at zero when it is in view, otherwise they run along the edges
+    let axis_x = x_at(0f64.clamp(x_lo, x_hi));
+    let axis_y = y_at(0f64.clamp(y_lo, y_hi));
+    chart.line(
+        &[Vert::new(ox, axis_y), Vert::new(ox + w, axis_y)],
+        chart_options.axis_width,
+        relief,
+    );
+    chart.line(
+        &[Vert::new(axis_x, oy), Vert::new(axis_x, oy + h)],
+        chart_options.axis_width,
+        relief,
+    );
+
+    for v in tick_values(x_lo, x_hi, x_step) {
+        let x = x_at(v);
+        chart.line(
+            &[Vert::new(x, oy - TICK_LENGTH), Vert::new(x, oy)],
+            lw,
+            relief,
+        );
+        if chart_options.grid && (x - axis_x).abs() > chart_options.axis_width {
+            chart.line(
+                &[Vert::new(x, oy), Vert::new(x, oy + h)],
+                lw / 2.,
+                relief / 2.,
+            );
+        }
+        chart.label_below(
+            tick_label(v),
+            Vert::new(x, oy - TICK_LENGTH - LABEL_GAP),
+            dims,
+        );
+    }
+    for v in y_ticks {
+        let y = y_at(v);
+        chart.line(
+            &[Vert::new(ox - TICK_LENGTH, y), Vert::new(ox, y)],
+            lw,
+            relief,
+        );
+        if chart_options.grid && (y - axis_y).abs() > chart_options.axis_width {
+            chart.line(
+                &[Vert::new(ox, y), Vert::new(ox + w, y)],
+                lw / 2.,
+                relief / 2.,
+            );
+        }
+        chart.label_left(
+            tick_label(v),
+            Vert::new(ox - TICK_LENGTH - LABEL_GAP, y),
+            dims,
+        );
+    }
+
+    // the curve is twice as wide as the other lines so it stands out
+    let samples = sample(&expr, x_lo, x_hi, h / (y_hi - y_lo), w / (x_hi - x_lo));
+    for line in clip_curve(&samples, &|x| expr.eval(x), y_lo, y_hi) {
+        let points: Vec<Vert> = line
+            .iter()
+            .map(|v| Vert::new(x_at(v.x), y_at(v.y)))
+            .collect();
+        chart.line(&points, 2. * lw, relief);
+    }
+    Ok(chart)
+}
+
+/// write a tactile plot of the expression as an stl
+pub fn expression_to_plot_stl(
+    text: &str,
+    options: &PlotOptions,
+    output_filename: &str,
+) -> Result<(), ConversionError> {
+    let chart = plot_expression(text, options)?;
+    write_stl_3d(output_filename, chart.triangles(&options.chart)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn test_parse_and_eval() -> Result<(), Box<dyn Error>> {
+        assert_eq!(Expr::parse("1 + 2 * 3")?.eval(0.), 7.);
+        assert_eq!(Expr::parse("-x^2")?.eval(3.), -9.);
+        assert_eq!(Expr::parse("2^3^2")?.eval(0.), 512.);
+        assert_eq!(Expr::parse("2x + 1")?.eval(4.), 9.);
+        assert_eq!(Expr::parse("(x - 1)(x + 1)")?.eval(3.), 8.);
+        assert!((Expr::parse("sin(pi / 2) + ln(e)")?.eval(0.) - 2.).abs() < 1e-12);
+        assert_eq!(Expr::parse("abs(x) / 2")?.eval(-4.), 2.);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Expr::parse("1 +").is_err());
+        assert!(Expr::parse("foo(x)").is_err());
+        assert!(Expr::parse("(x").is_err());
+        assert!(Expr::parse("x $ 2").is_err());
+        assert!(Expr::parse("sin x").is_err());
+    }
+
+    #[test]
+    fn test_adaptive_sampling() -> Result<(), Box<dyn Error>> {
+        // a straight line needs no extra samples
+        let line = sample(&Expr::parse("2x")?, 0., 1., 100., 100.);
+        assert_eq!(line.len(), 2 * INITIAL_SAMPLES + 1);
+        // a sharp bend gets more
+        let bend = sample(&Expr::parse("abs(x - 0.3)")?, 0., 1., 100., 100.);
+        assert!(bend.len() > line.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_clip_curve() {
+        let samples = vec![
+            Some(Vert::new(0., 0.)),
+            Some(Vert::new(1., 2.)),
+            Some(Vert::new(2., 0.)),
+            None,
+            Some(Vert::new(3., 0.)),
+            Some(Vert::new(4., 0.5)),
+        ];
+        let tent = |x: f64| 2. - (x - 1.).abs() * 2.;
+        let lines = clip_curve(&samples, &tent, -1., 1.);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], vec![Vert::new(0., 0.), Vert::new(0.5, 1.)]);
+        assert_eq!(lines[1], vec![Vert::new(1.5, 1.), Vert::new(2., 0.)]);
+    }
+
+    #[test]
+    fn test_clip_steep_curve() -> Result<(), Box<dyn Error>> {
+        // each step of the line climbs more than the whole range, but it is still drawn
+        let expr = Expr::parse("10*x")?;
+        let samples = sample(&expr, -1., 1., 100., 100.);
+        let lines = clip_curve(&samples, &|x| expr.eval(x), -0.05, 0.05);
+        assert_eq!(lines.len(), 1);
+        assert!((lines[0][0].y + 0.05).abs() < 1e-9);
+        assert!((lines[0][lines[0].len() - 1].y - 0.05).abs() < 1e-9);
+        // while tan(x) still breaks at its asymptote
+        let expr = Expr::parse("tan(x)")?;
+        let samples = sample(&expr, 1., 2., 10., 100.);
+        let lines = clip_curve(&samples, &|x| expr.eval(x), -0.1, 0.1);
+        assert!(lines.is_empty());
+        let lines = clip_curve(&samples, &|x| expr.eval(x), -10., 10.);
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|l| l.windows(2).all(|s| s[0].x < s[1].x)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_plot_expression() -> Result<(), Box<dyn Error>> {
+        let options = PlotOptions::default();
+        let chart = plot_expression("x^2 / 10 - 3", &options)?;
+        assert!(!chart.shapes.is_empty());
+        assert!(chart.labels.iter().any(|(text, _)| text == "10"));
+        for v in chart
+            .shapes
+            .iter()
+            .flat_map(|(edges, _)| edges.iter().flat_map(|e| e.iter()))
+        {
+            assert!(v.x >= 0. && v.x <= chart.width && v.y >= 0. && v.y <= chart.height);
+        }
+        // asymptotes are left out rather than drawn across the plot
+        let tan = plot_expression("tan(x)", &options)?;
+        assert!(!tan.shapes.is_empty());
+        assert!(matches!(
+            plot_expression("x +", &options),
+            Err(ConversionError::Parse(_))
+        ));
+        Ok(())
+    }
+}
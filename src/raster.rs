@@ -0,0 +1,107 @@
+use image::{GrayImage, Luma};
+
+use crate::edge::Edge;
+use crate::vert::Vert;
+
+/// Draw a line with round ends onto a thresholded image, black pixels are the shape.
+/// Points are in pixels, with pixel centers on whole numbers. A single point draws a dot.
+pub fn draw_polyline(img: &mut GrayImage, points: &[Vert], width: f64) {
+    let radius = width / 2.;
+    let segments: Vec<(&Vert, &Vert)> = if points.len() == 1 {
+        vec![(&points[0], &points[0])]
+    } else {
+        points.iter().zip(points.iter().skip(1)).collect()
+    };
+
+    for (a, b) in segments {
+        let min_x = (a.x.min(b.x) - radius).floor().max(0.) as u32;
+        let min_y = (a.y.min(b.y) - radius).floor().max(0.) as u32;
+        let max_x = ((a.x.max(b.x) + radius).ceil().max(0.) as u32).min(img.width());
+        let max_y = ((a.y.max(b.y) + radius).ceil().max(0.) as u32).min(img.height());
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                if Vert::new(x as f64, y as f64).distance_to_segment(a, b) <= radius {
+                    img.put_pixel(x, y, Luma([0]));
+                }
+            }
+        }
+    }
+}
+
+/// Fill closed edges black onto a thresholded image with the even-odd rule, so holes given
+/// with their outlines stay clear. Points are in pixels, with pixel centers on whole numbers.
+pub fn fill_edges(img: &mut GrayImage, edges: &[Edge]) {
+    let (width, height) = img.dimensions();
+    for_each_filled(edges, width, height, |x, y| img.put_pixel(x, y, Luma([0])));
+}
+
+/// Call `f` with each pixel of a width by height image that `fill_edges` would fill.
+/// Only the rows between the lowest and highest points are scanned.
+pub fn for_each_filled(edges: &[Edge], width: u32, height: u32, mut f: impl FnMut(u32, u32)) {
+    let segments: Vec<(&Vert, &Vert)> = edges
+        .iter()
+        .flat_map(|e| e.iter().zip(e.iter().skip(1)))
+        .collect();
+    let (min_y, max_y) = segments
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(lo, hi), (a, _)| (lo.min(a.y), hi.max(a.y)));
+    if segments.is_empty() {
+        return;
+    }
+    let first_row = min_y.ceil().max(0.) as u32;
+    let last_row = (max_y.floor().max(-1.) + 1.).min(height as f64) as u32;
+    for y in first_row..last_row {
+        let fy = y as f64;
+        let mut crossings: Vec<f64> = segments
+            .iter()
+            .filter(|(a, b)| (a.y > fy) != (b.y > fy))
+            .map(|(a, b)| a.x + (fy - a.y) * (b.x - a.x) / (b.y - a.y))
+            .collect();
+        crossings.sort_by(f64::total_cmp);
+        for pair in crossings.chunks(2) {
+            if let [start, end] = pair {
+                let x0 = start.ceil().max(0.) as u32;
+                let x1 = (end.floor() + 1.).clamp(0., width as f64) as u32;
+                for x in x0..x1 {
+                    f(x, y);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draw_polyline() {
+        let mut img = GrayImage::from_pixel(20, 20, Luma([255]));
+        draw_polyline(&mut img, &[Vert::new(5., 10.), Vert::new(15., 10.)], 3.);
+        assert_eq!(img.get_pixel(10, 10).0[0], 0);
+        assert_eq!(img.get_pixel(10, 11).0[0], 0);
+        assert_eq!(img.get_pixel(10, 12).0[0], 255);
+        // round ends
+        assert_eq!(img.get_pixel(4, 10).0[0], 0);
+        assert_eq!(img.get_pixel(3, 9).0[0], 255);
+    }
+
+    #[test]
+    fn test_fill_edges_keeps_holes() {
+        use crate::util::testing::donut;
+        let mut img = GrayImage::from_pixel(20, 20, Luma([255]));
+        fill_edges(&mut img, &donut(10., 2., 5., 5.));
+        assert_eq!(img.get_pixel(5, 5).0[0], 0);
+        assert_eq!(img.get_pixel(6, 10).0[0], 0);
+        assert_eq!(img.get_pixel(10, 10).0[0], 255, "in the hole");
+        assert_eq!(img.get_pixel(16, 10).0[0], 255, "outside");
+    }
+
+    #[test]
+    fn test_draw_dot_off_the_image() {
+        let mut img = GrayImage::from_pixel(4, 4, Luma([255]));
+        draw_polyline(&mut img, &[Vert::new(-1., -1.)], 3.);
+        assert_eq!(img.get_pixel(0, 0).0[0], 0);
+        assert_eq!(img.get_pixel(1, 1).0[0], 255);
+    }
+}
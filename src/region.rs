@@ -0,0 +1,721 @@
+use image::{DynamicImage, GrayImage, Luma};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+
+use crate::edge::Edge;
+use crate::edge_img;
+use crate::engrave::engrave_triangles;
+use crate::error::ConversionError;
+use crate::hollow::{hollow_triangles, HollowOptions};
+use crate::json::feature_collection_from_values;
+use crate::lattice::check_traceable;
+use crate::mold::mold_triangles;
+use crate::raster::for_each_filled;
+use crate::shapes::signed_area;
+use crate::texture::Texture;
+use crate::tree::{sort_edges, Node};
+use crate::triangle::{
+    extrude_edges_with_profile, extrude_nodes_with_profile, image_to_edges, split_border,
+    StlOptions,
+};
+use crate::vert::Vert;
+
+/// What has been chosen for a region. Anything left unset comes from the `RegionDefaults`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegionProperties {
+    /// what an audio-tactile app says about the region
+    pub label: String,
+    pub description: String,
+    pub height: Option<f64>,
+    pub texture: Option<Texture>,
+    pub excluded: Option<bool>,
+    /// Raise only walls along the edges of the region, with the hollow options of the stl
+    /// or the default ones. A texture other than solid fills the inside of the walls.
+    pub hollow: Option<bool>,
+}
+
+/// How regions without their own properties are made
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RegionDefaults {
+    /// regions smaller than this, in the units of the stl, are left off, to drop specks
+    pub min_area: f64,
+    /// The heights of white and black regions, with grey in between, so darker regions
+    /// stand higher. When left out every region gets the height of the stl.
+    pub gray_heights: Option<(f64, f64)>,
+    /// the texture of regions that have not chosen one
+    #[serde(default)]
+    pub texture: Texture,
+}
+
+/// The properties given to regions by id, kept so an image can be converted again
+/// the same way
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegionSettings {
+    pub defaults: RegionDefaults,
+    pub properties: BTreeMap<usize, RegionProperties>,
+}
+
+/// One raised shape on the plate, with its holes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    /// counts up from 1 in reading order, so the same image traced the same way
+    /// always gives the same ids
+    pub id: usize,
+    /// the outline first, then the holes
+    pub polygon: Vec<Vec<[f64; 2]>>,
+    pub centroid: [f64; 2],
+    pub area: f64,
+    /// the average grey level of the image inside the region, before thresholding
+    pub gray: u8,
+    #[serde(flatten)]
+    pub properties: RegionProperties,
+}
+
+/// Where each region of a tactile graphic is, in the same units and coordinates as its stl
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegionMap {
+    pub width: f64,
+    pub height: f64,
+    pub regions: Vec<Region>,
+}
+
+fn ring_points(edge: &Edge, scale_factor: f64) -> Vec<Vert> {
+    edge.iter()
+        .map(|v| Vert::new(v.x * scale_factor, v.y * scale_factor))
+        .collect()
+}
+
+/// The area and centroid of a ring, with the area negative for holes so rings can be summed
+fn ring_moments(points: &[Vert], hole: bool) -> (f64, f64, f64) {
+    let (mut cx, mut cy) = (0., 0.);
+    for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+        let cross = a.determinant(b);
+        cx += (a.x + b.x) * cross;
+        cy += (a.y + b.y) * cross;
+    }
+    let twice_area = signed_area(points);
+    // the moments take the sign of the winding, make it match the side of the ring
+    let sign = if (twice_area < 0.) == hole { 1. } else { -1. };
+    (sign * twice_area / 2., sign * cx / 6., sign * cy / 6.)
+}
+
+/// the top left corner of the outline, to put regions in reading order
+fn reading_position(node: &Node) -> (f64, f64) {
+    node.data
+        .iter()
+        .map(|v| (-v.y, v.x))
+        .fold((f64::MAX, f64::MAX), |a, b| if b < a { b } else { a })
+}
+
+/// Put top level shapes in reading order, the region id of each is its place plus one
+pub fn sort_regions(nodes: &mut [Node]) {
+    nodes.sort_by(|a, b| {
+        let (a, b) = (reading_position(a), reading_position(b));
+        a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1))
+    });
+}
+
+fn node_edges(node: &Node) -> impl Iterator<Item = &Edge> {
+    std::iter::once(&node.data).chain(node.children.iter().map(|c| &c.data))
+}
+
+/// The edges of a traced shape in pixel rows from the top of the image.
+/// Traced edges go up from the bottom of the image and pass between pixel centers.
+fn flipped_edges(node: &Node, height: u32) -> Vec<Edge> {
+    let top = (height - 1) as f64;
+    node_edges(node)
+        .map(|e| {
+            Edge::from(
+                e.iter()
+                    .map(|v| Vert::new(v.x, top - v.y))
+                    .collect::<Vec<Vert>>(),
+            )
+        })
+        .collect()
+}
+
+/// The pixels of a region, black on white like a thresholded image, cut out of the labels of the
+/// whole image around the region with a pixel to spare. Gives the mask with the column and row
+/// of the image its top left corner is at.
+fn region_mask(
+    labels: &[usize],
+    width: u32,
+    height: u32,
+    id: usize,
+    node: &Node,
+) -> (GrayImage, u32, u32) {
+    let top = (height - 1) as f64;
+    let (min_x, max_x, min_y, max_y) = node.data.iter().fold(
+        (f64::MAX, f64::MIN, f64::MAX, f64::MIN),
+        |(x0, x1, y0, y1), v| (x0.min(v.x), x1.max(v.x), y0.min(v.y), y1.max(v.y)),
+    );
+    let x0 = (min_x.floor() - 1.).max(0.) as u32;
+    let x1 = ((max_x.ceil() + 2.).max(0.) as u32).min(width);
+    let y0 = ((top - max_y).floor() - 1.).max(0.) as u32;
+    let y1 = (((top - min_y).ceil() + 2.).max(0.) as u32).min(height);
+    let mask = GrayImage::from_fn(x1 - x0, y1 - y0, |x, y| {
+        if labels[(y + y0) as usize * width as usize + (x + x0) as usize] == id {
+            Luma([0])
+        } else {
+            Luma([255])
+        }
+    });
+    (mask, x0, y0)
+}
+
+/// The id of the region covering each pixel of the image, row by row from the top, and 0 where
+/// there is none. Each region is filled in once, over only the rows it covers.
+fn region_labels(nodes: &[Node], width: u32, height: u32) -> Vec<usize> {
+    let mut labels = vec![0; width as usize * height as usize];
+    for (i, node) in nodes.iter().enumerate() {
+        for_each_filled(&flipped_edges(node, height), width, height, |x, y| {
+            labels[y as usize * width as usize + x as usize] = i + 1;
+        });
+    }
+    labels
+}
+
+/// the mean gray of the pixels of each region, in one pass over the image
+fn mean_grays(labels: &[usize], n_regions: usize, img: &GrayImage) -> Vec<u8> {
+    let mut sums = vec![(0u64, 0u64); n_regions + 1];
+    for (&label, p) in labels.iter().zip(img.pixels()) {
+        sums[label].0 += p.0[0] as u64;
+        sums[label].1 += 1;
+    }
+    sums[1..]
+        .iter()
+        .map(|&(sum, count)| if count == 0 { 0 } else { (sum / count) as u8 })
+        .collect()
+}
+
+impl Region {
+    fn from_node(id: usize, node: &Node, scale_factor: f64) -> Self {
+        let rings: Vec<(Vec<Vert>, bool)> = node_edges(node)
+            .enumerate()
+            .map(|(i, e)| (ring_points(e, scale_factor), i > 0))
+            .collect();
+        let (area, mx, my) = rings
+            .iter()
+            .map(|(points, hole)| ring_moments(points, *hole))
+            .fold((0., 0., 0.), |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2));
+        let centroid = if area != 0. {
+            [mx / area, my / area]
+        } else {
+            let p = &rings[0].0[0];
+            [p.x, p.y]
+        };
+        Region {
+            id,
+            polygon: rings
+                .into_iter()
+                .map(|(points, _)| points.iter().map(|v| [v.x, v.y]).collect())
+                .collect(),
+            centroid,
+            area,
+            gray: 0,
+            properties: RegionProperties::default(),
+        }
+    }
+
+    pub fn to_geojson_feature(&self) -> Value {
+        json!({
+            "type": "Feature",
+            "id": self.id,
+            "geometry": {
+                "type": "Polygon",
+                "coordinates": self.polygon,
+            },
+            "properties": {
+                "id": self.id,
+                "label": self.properties.label,
+                "description": self.properties.description,
+                "centroid": self.centroid,
+                "area": self.area,
+            },
+        })
+    }
+}
+
+impl RegionMap {
+    /// A region for each top level shape from `sort_edges`.
+    /// Coordinates are multiplied by the scale factor, like the stl is.
+    pub fn from_nodes(mut nodes: Vec<Node>, width: f64, height: f64, scale_factor: f64) -> Self {
+        sort_regions(&mut nodes);
+        RegionMap {
+            width: width * scale_factor,
+            height: height * scale_factor,
+            regions: nodes
+                .iter()
+                .enumerate()
+                .map(|(i, node)| Region::from_node(i + 1, node, scale_factor))
+                .collect(),
+        }
+    }
+
+    /// fill in the properties that have been chosen for each id
+    pub fn apply_properties(&mut self, settings: &RegionSettings) {
+        for region in self.regions.iter_mut() {
+            if let Some(p) = settings.properties.get(&region.id) {
+                region.properties = p.clone();
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, ConversionError> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn to_geojson(&self) -> Value {
+        feature_collection_from_values(
+            self.regions
+                .iter()
+                .map(|r| r.to_geojson_feature())
+                .collect(),
+        )
+    }
+
+    pub fn write_geojson(&self, filename: &str) -> Result<(), ConversionError> {
+        Ok(fs::write(filename, format!("{}\n", self.to_geojson()))?)
+    }
+}
+
+impl RegionSettings {
+    pub fn to_json(&self) -> Result<String, ConversionError> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, ConversionError> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    /// The height and texture a region is made with, or None when it is left off
+    pub fn resolve(&self, region: &Region, height: f64) -> Option<(f64, Texture)> {
+        let properties = self.properties.get(&region.id).cloned().unwrap_or_default();
+        let excluded = properties
+            .excluded
+            .unwrap_or(region.area < self.defaults.min_area);
+        if excluded {
+            return None;
+        }
+        let height = properties
+            .height
+            .unwrap_or(match self.defaults.gray_heights {
+                Some((white, black)) => black + (white - black) * region.gray as f64 / 255.,
+                None => height,
+            });
+        Some((height, properties.texture.unwrap_or(self.defaults.texture)))
+    }
+
+    /// How a region is hollowed, if it is. Regions that have not chosen follow the stl options.
+    pub fn hollow(&self, region: &Region, options: &StlOptions) -> Option<HollowOptions> {
+        let chosen = self.properties.get(&region.id).and_then(|p| p.hollow);
+        match chosen {
+            Some(true) => Some(options.hollow.unwrap_or_default()),
+            Some(false) => None,
+            None => options.hollow,
+        }
+    }
+}
+
+/// the traced shapes in region order with their map, and the border edges kept apart
+struct Traced {
+    nodes: Vec<Node>,
+    map: RegionMap,
+    border: Vec<Edge>,
+    /// see `region_labels`
+    labels: Vec<usize>,
+}
+
+fn trace_regions(
+    img: DynamicImage,
+    threshold: u8,
+    scale_factor: f64,
+    options: &StlOptions,
+) -> Result<Traced, ConversionError> {
+    check_traceable(img.width(), img.height())?;
+    let (width, height) = (img.width(), img.height());
+    let gray = img.to_luma8();
+    let mut edges = image_to_edges(img, threshold, options)?;
+    // the border is added after the traced shapes, it is not a region
+    let border = split_border(&mut edges, width, height, options);
+
+    let mut nodes = sort_edges(edges)?;
+    sort_regions(&mut nodes);
+    let mut map = RegionMap::from_nodes(
+        nodes.clone(),
+        (width - 1) as f64,
+        (height - 1) as f64,
+        scale_factor,
+    );
+    let labels = region_labels(&nodes, width, height);
+    let grays = mean_grays(&labels, nodes.len(), &gray);
+    for (region, gray) in map.regions.iter_mut().zip(grays) {
+        region.gray = gray;
+    }
+    Ok(Traced {
+        nodes,
+        map,
+        border,
+        labels,
+    })
+}
+
+/// Trace an image the way `image_to_stl_with_options` does and map its regions.
+/// The border from the options is not a region.
+pub fn image_to_region_map(
+    img: DynamicImage,
+    threshold: u8,
+    scale_factor: f64,
+    options: &StlOptions,
+) -> Result<RegionMap, ConversionError> {
+    Ok(trace_regions(img, threshold, scale_factor, options)?.map)
+}
+
+/// Like `image_to_stl_with_options`, with each region made the way the settings say.
+/// Molds and engraved plates leave off excluded regions, but make the rest at the one height
+/// and whole, even when they are hollowed.
+pub fn image_to_stl_with_regions(
+    img: DynamicImage,
+    threshold: u8,
+    height: f64,
+    scale_factor: f64,
+    options: &StlOptions,
+    settings: &RegionSettings,
+) -> Result<Vec<f64>, ConversionError> {
+    options.check()?;
+    let (width, image_height) = (img.width(), img.height());
+    let Traced {
+        nodes,
+        map,
+        border,
+        labels,
+    } = trace_regions(img, threshold, scale_factor, options)?;
+    let kept: Vec<(usize, Node, f64, Texture, Option<HollowOptions>)> = nodes
+        .into_iter()
+        .zip(map.regions.iter())
+        .filter_map(|(node, region)| {
+            settings.resolve(region, height).map(|(h, texture)| {
+                let hollow = settings.hollow(region, options);
+                (region.id, node, h, texture, hollow)
+            })
+        })
+        .collect();
+    info!("making {} of {} regions", kept.len(), map.regions.len());
+
+    let (w, h) = ((width - 1) as f64, (image_height - 1) as f64);
+    let tris = if options.mold.is_some() || options.engrave.is_some() {
+        let mut edges: Vec<Edge> = kept
+            .iter()
+            .flat_map(|(_, node, _, _, _)| node_edges(node).cloned().collect::<Vec<Edge>>())
+            .collect();
+        edges.extend(border);
+        match (&options.mold, &options.engrave) {
+            (Some(mold), _) => mold_triangles(edges, w, h, height, mold)?,
+            (None, Some(engrave)) => {
+                engrave_triangles(edges, w, h, height, options.profile, engrave)?
+            }
+            (None, None) => unreachable!(),
+        }
+    } else {
+        let mut tris = extrude_edges_with_profile(border, height, options.profile)?;
+        for (id, node, region_height, texture, hollow) in kept {
+            if let Some(mut hollow) = hollow {
+                if texture != Texture::Solid {
+                    hollow.fill = Some(texture);
+                }
+                tris.append(&mut hollow_triangles(
+                    &[node],
+                    region_height,
+                    options.profile,
+                    &hollow,
+                )?);
+            } else if texture == Texture::Solid {
+                tris.append(&mut extrude_nodes_with_profile(
+                    vec![node],
+                    region_height,
+                    options.profile,
+                )?);
+            } else {
+                // only the part of the image around the region is textured and traced again
+                let (mut mask, x0, y0) = region_mask(&labels, width, image_height, id, &node);
+                texture.apply_at(&mut mask, x0, y0);
+                let dy = (image_height - y0 - mask.height()) as f64;
+                let mut edges = edge_img(&mask.into())?.closed_edges;
+                for edge in edges.iter_mut() {
+                    edge.translate(x0 as f64, dy);
+                }
+                tris.append(&mut extrude_edges_with_profile(
+                    edges,
+                    region_height,
+                    options.profile,
+                )?);
+            }
+        }
+        tris
+    };
+    Ok(tris.into_iter().map(|v| v * scale_factor).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::rectangle;
+    use std::error::Error;
+
+    fn two_squares() -> Vec<Node> {
+        // a square with a hole, to the right of and below a plain square
+        sort_edges(vec![
+            rectangle(0., 0., 10., 10., false),
+            rectangle(21., -16., 2., 2., true),
+            rectangle(20., -20., 10., 10., false),
+        ])
+        .unwrap()
+    }
+
+    /// a white image with a black square in the top left and a grey one in the bottom right
+    fn two_blocks() -> DynamicImage {
+        GrayImage::from_fn(40, 40, |x, y| {
+            if (5..15).contains(&x) && (5..15).contains(&y) {
+                Luma([0])
+            } else if (25..35).contains(&x) && (25..35).contains(&y) {
+                Luma([100])
+            } else {
+                Luma([255])
+            }
+        })
+        .into()
+    }
+
+    fn max_z(tris: &[f64]) -> f64 {
+        tris.chunks(3).map(|v| v[2]).fold(f64::MIN, f64::max)
+    }
+
+    #[test]
+    fn test_region_ids_follow_reading_order() {
+        let map = RegionMap::from_nodes(two_squares(), 40., 40., 1.);
+        assert_eq!(map.regions.len(), 2);
+        assert_eq!(map.regions[0].id, 1);
+        assert_eq!(map.regions[0].centroid, [5., 5.]);
+        assert_eq!(map.regions[1].id, 2);
+        // the same edges in another order get the same ids
+        let mut nodes = two_squares();
+        nodes.reverse();
+        assert_eq!(RegionMap::from_nodes(nodes, 40., 40., 1.), map);
+    }
+
+    #[test]
+    fn test_region_area_and_scale() {
+        let map = RegionMap::from_nodes(two_squares(), 40., 40., 0.5);
+        assert_eq!(map.width, 20.);
+        let holed = &map.regions[1];
+        assert_eq!(holed.polygon.len(), 2);
+        assert!((holed.area - 24.).abs() < 1e-9);
+        // the hole is left of center, so the centroid moves right
+        assert!(holed.centroid[0] > 12.5);
+        assert!((holed.centroid[1] + 7.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_region_properties_in_exports() -> Result<(), Box<dyn Error>> {
+        let mut map = RegionMap::from_nodes(two_squares(), 40., 40., 1.);
+        let mut settings = RegionSettings::default();
+        settings.properties.insert(
+            2,
+            RegionProperties {
+                label: "lake".to_string(),
+                description: "with an island".to_string(),
+                ..Default::default()
+            },
+        );
+        map.apply_properties(&settings);
+        let parsed: RegionMap = serde_json::from_str(&map.to_json()?)?;
+        assert_eq!(parsed, map);
+        let geojson = map.to_geojson();
+        assert_eq!(geojson["features"][1]["properties"]["label"], "lake");
+        assert_eq!(geojson["features"][0]["properties"]["label"], "");
+        assert_eq!(RegionSettings::from_json(&settings.to_json()?)?, settings);
+        Ok(())
+    }
+
+    #[test]
+    fn test_region_gray() -> Result<(), Box<dyn Error>> {
+        let map = image_to_region_map(two_blocks(), 128, 1., &StlOptions::default())?;
+        assert_eq!(map.regions.len(), 2);
+        assert_eq!(map.regions[0].gray, 0);
+        assert_eq!(map.regions[1].gray, 100);
+        Ok(())
+    }
+
+    #[test]
+    fn test_region_labels() {
+        // two squares of 10 by 10 pixels in a 40 by 40 image, the first with a hole of 2 by 2
+        let nodes = sort_edges(vec![
+            rectangle(4.5, 4.5, 10., 10., false),
+            rectangle(6.5, 6.5, 2., 2., true),
+            rectangle(24.5, 24.5, 10., 10., false),
+        ])
+        .unwrap();
+        let labels = region_labels(&nodes, 40, 40);
+        let count = |id| labels.iter().filter(|&&l| l == id).count();
+        assert_eq!((count(0), count(1), count(2)), (1600 - 196, 96, 100));
+        // the rows go down from the top of the image
+        assert_eq!(labels[30 * 40 + 10], 1);
+        assert_eq!(labels[10 * 40 + 30], 2);
+    }
+
+    #[test]
+    fn test_resolve_defaults() -> Result<(), Box<dyn Error>> {
+        let map = image_to_region_map(two_blocks(), 128, 1., &StlOptions::default())?;
+        let mut settings = RegionSettings {
+            defaults: RegionDefaults {
+                min_area: 0.,
+                gray_heights: Some((0., 5.1)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            settings.resolve(&map.regions[0], 1.),
+            Some((5.1, Texture::Solid))
+        );
+        let (grey_height, _) = settings.resolve(&map.regions[1], 1.).unwrap();
+        assert!((grey_height - 3.1).abs() < 1e-9);
+        // small regions are left off unless they are chosen
+        settings.defaults.min_area = 1000.;
+        assert_eq!(settings.resolve(&map.regions[0], 1.), None);
+        settings.properties.insert(
+            1,
+            RegionProperties {
+                excluded: Some(false),
+                height: Some(2.),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            settings.resolve(&map.regions[0], 1.),
+            Some((2., Texture::Solid))
+        );
+        // regions without a texture of their own take the default one
+        settings.defaults.texture = Texture::from_name("grid").unwrap();
+        let (_, texture) = settings.resolve(&map.regions[0], 1.).unwrap();
+        assert_eq!(texture.name(), "grid");
+        settings.properties.get_mut(&1).unwrap().texture = Some(Texture::Solid);
+        let (_, texture) = settings.resolve(&map.regions[0], 1.).unwrap();
+        assert_eq!(texture, Texture::Solid);
+        Ok(())
+    }
+
+    #[test]
+    fn test_settings_without_textures() -> Result<(), Box<dyn Error>> {
+        let json = r#"{
+            "defaults": {"min_area": 2.0, "gray_heights": null},
+            "properties": {"3": {"label": "sea", "description": "", "height": 1.5}}
+        }"#;
+        let settings = RegionSettings::from_json(json)?;
+        assert_eq!(settings.defaults.texture, Texture::Solid);
+        assert_eq!(settings.properties[&3].texture, None);
+        assert_eq!(settings.properties[&3].height, Some(1.5));
+        assert!(RegionSettings::from_json("{\"defaults\": 1}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_hollow_regions() -> Result<(), Box<dyn Error>> {
+        let map = image_to_region_map(two_blocks(), 128, 1., &StlOptions::default())?;
+        let mut settings = RegionSettings::default();
+        settings.properties.insert(
+            2,
+            RegionProperties {
+                hollow: Some(true),
+                ..Default::default()
+            },
+        );
+        let options = StlOptions::default();
+        assert_eq!(settings.hollow(&map.regions[0], &options), None);
+        assert_eq!(
+            settings.hollow(&map.regions[1], &options),
+            Some(HollowOptions::default())
+        );
+        // a region can stay solid when the rest are hollowed
+        let hollowed = StlOptions {
+            hollow: Some(HollowOptions {
+                wall_width: 2.,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        settings.properties.get_mut(&2).unwrap().hollow = Some(false);
+        assert_eq!(settings.hollow(&map.regions[1], &hollowed), None);
+        assert_eq!(settings.hollow(&map.regions[0], &hollowed), hollowed.hollow);
+
+        let solid = image_to_stl_with_regions(two_blocks(), 128, 2., 1., &options, &settings)?;
+        let tris = image_to_stl_with_regions(two_blocks(), 128, 2., 1., &hollowed, &settings)?;
+        assert_eq!(max_z(&tris), 2.);
+        // the top of the first block is only its walls, the second is whole
+        let top_area = |tris: &[f64], left: bool| -> f64 {
+            tris.chunks(9)
+                .filter(|t| t[2] == 2. && t[5] == 2. && t[8] == 2.)
+                .filter(|t| (t[0] < 20.) == left)
+                .map(|t| ((t[3] - t[0]) * (t[7] - t[1]) - (t[6] - t[0]) * (t[4] - t[1])).abs() / 2.)
+                .sum()
+        };
+        assert!((top_area(&solid, true) - 100.).abs() < 1.);
+        assert!((top_area(&tris, true) - (100. - 36.)).abs() < 1.);
+        assert!((top_area(&tris, false) - 100.).abs() < 1.);
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_to_stl_with_regions() -> Result<(), Box<dyn Error>> {
+        let options = StlOptions::default();
+        let mut settings = RegionSettings::default();
+        let plain = image_to_stl_with_regions(two_blocks(), 128, 2., 1., &options, &settings)?;
+        assert_eq!(max_z(&plain), 2.);
+        assert_eq!(plain.len() % 9, 0);
+
+        settings.properties.insert(
+            2,
+            RegionProperties {
+                height: Some(4.),
+                texture: Texture::from_name("grid"),
+                ..Default::default()
+            },
+        );
+        let tall = image_to_stl_with_regions(two_blocks(), 128, 2., 1., &options, &settings)?;
+        assert_eq!(max_z(&tall), 4.);
+        // the textured region is cut out of the image, but comes out as it would from the
+        // whole image
+        let mut mask = GrayImage::from_fn(40, 40, |x, y| {
+            if (25..35).contains(&x) && (25..35).contains(&y) {
+                Luma([0])
+            } else {
+                Luma([255])
+            }
+        });
+        Texture::from_name("grid").unwrap().apply(&mut mask);
+        let whole =
+            extrude_edges_with_profile(edge_img(&mask.into())?.closed_edges, 4., options.profile)?;
+        let textured: Vec<f64> = tall
+            .chunks(9)
+            .filter(|t| t[0] > 20.)
+            .flatten()
+            .copied()
+            .collect();
+        assert_eq!(textured, whole);
+
+        settings.properties.remove(&2);
+        settings.properties.insert(
+            1,
+            RegionProperties {
+                excluded: Some(true),
+                ..Default::default()
+            },
+        );
+        let one = image_to_stl_with_regions(two_blocks(), 128, 2., 1., &options, &settings)?;
+        assert_eq!(one.len(), plain.len() / 2);
+        Ok(())
+    }
+}
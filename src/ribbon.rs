@@ -1,4 +1,295 @@
-use crate::edge::Edge;
+use rstar::primitives::{GeomWithData, Rectangle};
+use rstar::{RTree, AABB};
+use serde::{Deserialize, Serialize};
+
+use crate::edge::{orient2d, Edge};
+use crate::offset::miter_edge;
+use crate::tree::encloses;
+use crate::vert::Vert;
+
+/// number of steps used to approximate the curved profiles
+static ARC_SEGMENTS: usize = 8;
+/// number of halvings used to find how much of the inset a polygon can take
+static INSET_SEARCH_STEPS: usize = 12;
+/// Part of the largest inset that fits that is used. Right at the limit the top of the
+/// polygon is a sliver, so it is kept a little wider.
+static INSET_MARGIN: f64 = 0.9;
+/// how far, in times the inset, the corners of the walls may reach
+static INSET_MITER_LIMIT: f64 = 3.;
+
+/// The shape of the walls around the shapes.
+/// Sizes are in the same units as the height of the shape.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EdgeProfile {
+    /// straight walls with a sharp edge at the top
+    #[default]
+    Vertical,
+    /// the top edge is rounded off with a quarter circle of the given radius
+    Fillet { radius: f64 },
+    /// the top edge is cut off at 45 degrees, the given distance from the corner
+    Chamfer { size: f64 },
+    /// the whole wall is a quarter circle, so lines as wide as twice the height become half rounds
+    Dome,
+    /// the whole wall leans in by the given angle from vertical, in degrees, so a
+    /// mold lets go of the part
+    Draft { angle: f64 },
+}
+
+impl EdgeProfile {
+    /// the names used to choose a profile in the ui
+    pub const NAMES: [&'static str; 5] = ["vertical", "fillet", "chamfer", "dome", "draft"];
+
+    /// `size` is the radius or chamfer size, or the angle in degrees for a draft
+    pub fn from_name(name: &str, size: f64) -> Option<Self> {
+        match name {
+            "vertical" => Some(EdgeProfile::Vertical),
+            "fillet" => Some(EdgeProfile::Fillet { radius: size }),
+            "chamfer" => Some(EdgeProfile::Chamfer { size }),
+            "dome" => Some(EdgeProfile::Dome),
+            "draft" => Some(EdgeProfile::Draft { angle: size }),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EdgeProfile::Vertical => "vertical",
+            EdgeProfile::Fillet { .. } => "fillet",
+            EdgeProfile::Chamfer { .. } => "chamfer",
+            EdgeProfile::Dome => "dome",
+            EdgeProfile::Draft { .. } => "draft",
+        }
+    }
+
+    /// The (inset, z) pairs of the wall from the bottom to the top of a shape with the given height.
+    /// The inset is how far the wall has moved into the shape at that height.
+    pub fn levels(&self, height: f64) -> Vec<(f64, f64)> {
+        let arc = |radius: f64| -> Vec<(f64, f64)> {
+            let radius = radius.clamp(0., height);
+            let mut out = vec![(0., 0.)];
+            for i in 0..ARC_SEGMENTS {
+                let theta = std::f64::consts::FRAC_PI_2 * (i as f64) / (ARC_SEGMENTS as f64);
+                out.push((
+                    radius - radius * theta.cos(),
+                    height - radius + radius * theta.sin(),
+                ));
+            }
+            // exactly at the top so the top face lines up
+            out.push((radius, height));
+            out
+        };
+
+        let mut levels = match *self {
+            EdgeProfile::Vertical => vec![(0., 0.), (0., height)],
+            EdgeProfile::Fillet { radius } => arc(radius),
+            EdgeProfile::Chamfer { size } => {
+                let size = size.clamp(0., height);
+                vec![(0., 0.), (0., height - size), (size, height)]
+            }
+            EdgeProfile::Dome => arc(height),
+            EdgeProfile::Draft { angle } => {
+                let angle = angle.clamp(0., 89.).to_radians();
+                vec![(0., 0.), (height * angle.tan(), height)]
+            }
+        };
+        // drop repeated levels, e.g. the straight part of a fillet as tall as the shape
+        levels.dedup();
+        levels
+    }
+
+    /// how far the top face is moved into the shape
+    pub fn top_inset(&self, height: f64) -> f64 {
+        self.levels(height).last().map_or(0., |l| l.0)
+    }
+}
+
+/// Move every vertex of a closed edge the given distance towards the filled side of the edge.
+/// Both outer edges and holes have the filled side on their left, so this shrinks shapes and
+/// grows holes. Corners are mitred with `miter_edge`. The number of vertices is unchanged, but
+/// the result overlaps itself when the distance is more than half the width of the thinnest part.
+pub fn inset_edge(edge: &Edge, distance: f64) -> Edge {
+    miter_edge(edge, distance, INSET_MITER_LIMIT)
+}
+
+/// the ends of the sides of the rings, with the ring and side they belong to
+fn sides(rings: &[Edge]) -> Vec<(Vert, Vert, usize, usize)> {
+    rings
+        .iter()
+        .enumerate()
+        .flat_map(|(r, ring)| {
+            ring.iter()
+                .zip(ring.iter().skip(1))
+                .enumerate()
+                .map(move |(i, (a, b))| (a.clone(), b.clone(), r, i))
+        })
+        .collect()
+}
+
+/// Check if two sides of the rings cross or touch, apart from sides of a ring meeting at
+/// their shared corner
+fn rings_cross(rings: &[Edge]) -> bool {
+    let sides = sides(rings);
+    let boxes = RTree::bulk_load(
+        sides
+            .iter()
+            .enumerate()
+            .map(|(i, (a, b, _, _))| {
+                GeomWithData::new(Rectangle::from_corners([a.x, a.y], [b.x, b.y]), i)
+            })
+            .collect(),
+    );
+    let on_side = |a: &Vert, b: &Vert, v: &Vert| {
+        orient2d(a, b, v) == 0.
+            && v.x >= a.x.min(b.x)
+            && v.x <= a.x.max(b.x)
+            && v.y >= a.y.min(b.y)
+            && v.y <= a.y.max(b.y)
+    };
+    sides.iter().enumerate().any(|(i, (a, b, ring, side))| {
+        let n = rings[*ring].len() - 1;
+        let envelope =
+            AABB::from_corners([a.x.min(b.x), a.y.min(b.y)], [a.x.max(b.x), a.y.max(b.y)]);
+        boxes
+            .locate_in_envelope_intersecting(&envelope)
+            .map(|found| found.data)
+            .filter(|&j| j > i)
+            .any(|j| {
+                let (c, d, other_ring, other_side) = &sides[j];
+                let neighbors = ring == other_ring
+                    && ((side + 1) % n == *other_side || (other_side + 1) % n == *side);
+                if neighbors {
+                    return false;
+                }
+                let (o1, o2) = (orient2d(a, b, c), orient2d(a, b, d));
+                let (o3, o4) = (orient2d(c, d, a), orient2d(c, d, b));
+                (o1 * o2 < 0. && o3 * o4 < 0.)
+                    || on_side(a, b, c)
+                    || on_side(a, b, d)
+                    || on_side(c, d, a)
+                    || on_side(c, d, b)
+            })
+    })
+}
+
+/// Check that the rings of a polygon, the outline first, can be inset by the distance without
+/// folding over: no side turns around, no two sides cross and the holes stay inside
+fn inset_fits(rings: &[&Edge], distance: f64) -> bool {
+    let inset: Vec<Edge> = rings.iter().map(|e| inset_edge(e, distance)).collect();
+    let turned = rings.iter().zip(inset.iter()).any(|(before, after)| {
+        before
+            .iter()
+            .zip(before.iter().skip(1))
+            .zip(after.iter().zip(after.iter().skip(1)))
+            .any(|((a, b), (c, d))| a != b && b.diff(a).dot_product(&d.diff(c)) <= 0.)
+    });
+    !turned && !rings_cross(&inset) && inset.iter().skip(1).all(|hole| encloses(&inset[0], hole))
+}
+
+/// How much of the inset of the profile the outline and then the holes of one polygon can
+/// take, from 0 to 1. Parts narrower than twice the inset would fold over, so their walls are made
+/// steeper instead, and a polygon too small to take any inset gets vertical walls.
+pub fn inset_scale(rings: &[&Edge], height: f64, profile: EdgeProfile) -> f64 {
+    // once the rings fold over they stay folded as they move further in, so only the
+    // furthest inset needs checking
+    let furthest = profile
+        .levels(height)
+        .iter()
+        .map(|(inset, _)| *inset)
+        .fold(0., f64::max);
+    let fits = |scale: f64| inset_fits(rings, furthest * scale);
+    if furthest == 0. || fits(1.) {
+        return 1.;
+    }
+    let (mut low, mut high) = (0., 1.);
+    for _ in 0..INSET_SEARCH_STEPS {
+        let middle = (low + high) / 2.;
+        if fits(middle) {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    low * INSET_MARGIN
+}
+
+fn stitch_rings(lower: &Edge, upper: &Edge, bottom: f64, top: f64, is_hole: bool) -> Vec<f64> {
+    (0..(lower.len() - 1))
+        .flat_map(|i| {
+            let (ln, lnp1) = (&lower[i], &lower[i + 1]);
+            let (un, unp1) = (&upper[i], &upper[i + 1]);
+            if !is_hole {
+                vec![
+                    ln.to3(bottom),
+                    un.to3(top),
+                    lnp1.to3(bottom),
+                    un.to3(top),
+                    unp1.to3(top),
+                    lnp1.to3(bottom),
+                ]
+            } else {
+                vec![
+                    ln.to3(bottom),
+                    lnp1.to3(bottom),
+                    un.to3(top),
+                    un.to3(top),
+                    lnp1.to3(bottom),
+                    unp1.to3(top),
+                ]
+            }
+        })
+        .flatten()
+        .collect()
+}
+
+/// create the walls along an edge following the given profile.
+/// The wall is built from copies of the edge inset at each level of the profile,
+/// the rings are stitched together so the top of the wall lines up exactly with
+/// `inset_edge(edge, profile.top_inset(height))`
+pub fn edge_to_profiled_ribbon(edge: &Edge, height: f64, profile: EdgeProfile) -> Vec<f64> {
+    scaled_ribbon(edge, height, profile, 1.)
+}
+
+/// Like `edge_to_profiled_ribbon` for the outline and then the holes of a polygon, with the inset
+/// limited to what the polygon can take, see `inset_scale`. Also gives how far the top
+/// face has to be inset to meet the top of the walls.
+pub fn polygon_to_profiled_ribbon(
+    rings: &[&Edge],
+    height: f64,
+    profile: EdgeProfile,
+) -> (Vec<f64>, f64) {
+    let scale = inset_scale(rings, height, profile);
+    let tris = rings
+        .iter()
+        .flat_map(|e| scaled_ribbon(e, height, profile, scale))
+        .collect();
+    (tris, profile.top_inset(height) * scale)
+}
+
+fn scaled_ribbon(edge: &Edge, height: f64, profile: EdgeProfile, scale: f64) -> Vec<f64> {
+    if profile == EdgeProfile::Vertical {
+        return edge_to_ribbon(edge, height);
+    }
+    let is_hole = edge.is_hole();
+    let levels: Vec<(f64, f64)> = profile
+        .levels(height)
+        .into_iter()
+        .map(|(inset, z)| (inset * scale, z))
+        .collect();
+    let rings: Vec<Edge> = levels
+        .iter()
+        .map(|(inset, _)| {
+            if *inset == 0. {
+                edge.clone()
+            } else {
+                inset_edge(edge, *inset)
+            }
+        })
+        .collect();
+
+    (0..(levels.len() - 1))
+        .flat_map(|i| stitch_rings(&rings[i], &rings[i + 1], levels[i].1, levels[i + 1].1, is_hole))
+        .collect()
+}
 
 /// create a vertical wall of triangles along an edge with a given height
 /// used to enclose the sides of the shape
@@ -39,6 +330,71 @@ mod test {
     use super::*;
     use crate::util::testing::closed_edge;
 
+    #[test]
+    fn test_profile_levels() {
+        let height = 4.;
+        assert_eq!(EdgeProfile::Vertical.levels(height), vec![(0., 0.), (0., height)]);
+        assert_eq!(
+            EdgeProfile::Chamfer { size: 1. }.levels(height),
+            vec![(0., 0.), (0., 3.), (1., 4.)]
+        );
+        let dome = EdgeProfile::Dome.levels(height);
+        assert_eq!(dome.len(), ARC_SEGMENTS + 1, "the straight part is dropped");
+        assert_eq!(dome[dome.len() - 1], (height, height));
+        assert_eq!(EdgeProfile::Fillet { radius: 10. }.top_inset(height), height);
+        let draft = EdgeProfile::Draft { angle: 45. }.levels(height);
+        assert_eq!(draft.len(), 2);
+        assert!((draft[1].0 - height).abs() < 1e-9);
+        assert_eq!(EdgeProfile::Draft { angle: 0. }.top_inset(height), 0.);
+    }
+
+    #[test]
+    fn test_inset_edge() {
+        let edge = closed_edge(10., 0., 0., false);
+        let inset = inset_edge(&edge, 1.);
+        assert_eq!(inset.len(), edge.len());
+        assert_eq!(inset[0], Vert::new(1., 1.));
+        assert_eq!(inset[2], Vert::new(9., 9.));
+        assert!(inset.is_closed());
+
+        let hole = closed_edge(10., 0., 0., true);
+        let outset = inset_edge(&hole, 1.);
+        assert_eq!(outset[0], Vert::new(-1., -1.));
+    }
+
+    #[test]
+    fn test_inset_scale() {
+        let square = closed_edge(20., 0., 0., false);
+        assert_eq!(inset_scale(&[&square], 5., EdgeProfile::Dome), 1.);
+        // a line 2 wide can only move in less than 1 from each side
+        let line = Edge::from(vec![
+            Vert::new(0., 0.),
+            Vert::new(20., 0.),
+            Vert::new(20., 2.),
+            Vert::new(0., 2.),
+            Vert::new(0., 0.),
+        ]);
+        let scale = inset_scale(&[&line], 5., EdgeProfile::Dome);
+        assert!(scale > 0.15 && scale < 0.2, "{}", scale);
+        assert!(inset_fits(&[&line], 5. * scale));
+        assert!(!inset_fits(&[&line], 1.01));
+        // a hole too close to the outline limits the inset of the polygon
+        let hole = closed_edge(16., 2., 2., true);
+        assert!(inset_scale(&[&square, &hole], 5., EdgeProfile::Dome) < 0.2);
+    }
+
+    #[test]
+    fn test_profiled_ribbon() {
+        let edge = closed_edge(10., 0., 0., false);
+        let height = 6.;
+
+        let tris = edge_to_profiled_ribbon(&edge, height, EdgeProfile::Chamfer { size: 1. });
+        // two rings of quads, 4 sides each
+        assert_eq!(tris.len() / 9, 16);
+        let top: Vec<&[f64]> = tris.chunks(3).filter(|v| v[2] == height).collect();
+        assert!(top.iter().all(|v| v[0] == 1. || v[0] == 9.));
+    }
+
     #[test]
     fn test_edge_to_ribbon() {
         let edge = closed_edge(10., 0., 0., false);
@@ -0,0 +1,504 @@
+use std::collections::BTreeSet;
+
+use crate::edge::Edge;
+use crate::error::ConversionError;
+use crate::vert::Vert;
+
+/// rings with less area than this, in square units, are dropped after clipping
+pub(crate) static MIN_AREA: f64 = 1e-9;
+
+/// twice the signed area of a ring of points, positive when counterclockwise
+pub fn signed_area(points: &[Vert]) -> f64 {
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.determinant(b))
+        .sum()
+}
+
+/// Whether a point is inside a closed edge, whichever way it is wound.
+/// Counts the sides crossed by a ray going right from the point.
+pub fn ring_contains(edge: &Edge, v: &Vert) -> bool {
+    edge.iter()
+        .zip(edge.iter().skip(1))
+        .filter(|(a, b)| (a.y > v.y) != (b.y > v.y))
+        .filter(|(a, b)| v.x < a.x + (v.y - a.y) * (b.x - a.x) / (b.y - a.y))
+        .count()
+        % 2
+        == 1
+}
+
+/// Close a ring of points into an edge. Outlines are wound counterclockwise and holes
+/// clockwise, which is the same way the traced edges from `edge_img` are wound.
+/// Returns None when there are fewer than three distinct points.
+pub fn polygon(points: Vec<Vert>, hole: bool) -> Option<Edge> {
+    let distinct: BTreeSet<(u64, u64)> = points
+        .iter()
+        .map(|v| (v.x.to_bits(), v.y.to_bits()))
+        .collect();
+    if distinct.len() < 3 {
+        return None;
+    }
+    Some(close_ring(points, hole))
+}
+
+/// Like `polygon`, for points that are known to make a ring. Fewer than three points give an
+/// edge without area.
+pub(crate) fn close_ring(mut points: Vec<Vert>, hole: bool) -> Edge {
+    if points.len() > 1 && points[0] == points[points.len() - 1] {
+        points.pop();
+    }
+    let counterclockwise = signed_area(&points) > 0.;
+    if counterclockwise == hole {
+        points.reverse();
+    }
+    if let Some(first) = points.first().cloned() {
+        points.push(first);
+    }
+    let mut edge = Edge::from(points);
+    edge.mark_hole(hole);
+    edge
+}
+
+/// flip the winding of a closed edge, so its filled side becomes the empty side
+pub fn invert_edge(edge: &Edge) -> Edge {
+    close_ring(edge.iter().cloned().collect(), !edge.is_hole())
+}
+
+/// an axis aligned rectangle with its lower left corner at (x, y)
+pub fn rectangle(x: f64, y: f64, width: f64, height: f64, hole: bool) -> Edge {
+    close_ring(
+        vec![
+            Vert::new(x, y),
+            Vert::new(x + width, y),
+            Vert::new(x + width, y + height),
+            Vert::new(x, y + height),
+        ],
+        hole,
+    )
+}
+
+/// a regular polygon with `segments` sides approximating a circle
+pub fn circle(x: f64, y: f64, radius: f64, segments: usize, hole: bool) -> Edge {
+    regular_polygon(x, y, radius, segments, 0., hole)
+}
+
+/// A regular polygon centered on (x, y) with its corners `radius` from the center.
+/// The first corner is `rotation` degrees counterclockwise from the right.
+pub fn regular_polygon(
+    x: f64,
+    y: f64,
+    radius: f64,
+    sides: usize,
+    rotation: f64,
+    hole: bool,
+) -> Edge {
+    close_ring(
+        (0..sides)
+            .map(|i| {
+                let angle = rotation.to_radians() + std::f64::consts::TAU * i as f64 / sides as f64;
+                Vert::new(x + radius * angle.cos(), y + radius * angle.sin())
+            })
+            .collect(),
+        hole,
+    )
+}
+
+/// points along a circle from the start angle to the end angle, in degrees counterclockwise
+pub fn arc_points(x: f64, y: f64, radius: f64, start: f64, end: f64, segments: usize) -> Vec<Vert> {
+    let segments = segments.max(1);
+    (0..=segments)
+        .map(|i| {
+            let angle = (start + (end - start) * i as f64 / segments as f64).to_radians();
+            Vert::new(x + radius * angle.cos(), y + radius * angle.sin())
+        })
+        .collect()
+}
+
+/// Outline of an arrow from one point to another, with a shaft of the given width and a
+/// triangular head at the end. Returns None when the arrow has no length.
+pub fn arrow(
+    from: &Vert,
+    to: &Vert,
+    width: f64,
+    head_length: f64,
+    head_width: f64,
+) -> Option<Edge> {
+    let d = to.diff(from);
+    let length = d.magnitude();
+    if length == 0. {
+        return None;
+    }
+    let (ux, uy) = (d.x / length, d.y / length);
+    // the head is never longer than the whole arrow
+    let head_length = head_length.min(length);
+    let at = |along: f64, across: f64| {
+        Vert::new(
+            from.x + ux * along - uy * across,
+            from.y + uy * along + ux * across,
+        )
+    };
+    let neck = length - head_length;
+    let (w, hw) = (width / 2., head_width.max(width) / 2.);
+    Some(close_ring(
+        vec![
+            at(0., -w),
+            at(neck, -w),
+            at(neck, -hw),
+            at(length, 0.),
+            at(neck, hw),
+            at(neck, w),
+            at(0., w),
+        ],
+        false,
+    ))
+}
+
+/// keep the part of a ring on the inside of one side of the clip rectangle
+fn clip_half_plane(
+    points: &[Vert],
+    inside: impl Fn(&Vert) -> bool,
+    intersect: impl Fn(&Vert, &Vert) -> Vert,
+) -> Vec<Vert> {
+    let mut out = vec![];
+    for (i, current) in points.iter().enumerate() {
+        let previous = &points[(i + points.len() - 1) % points.len()];
+        match (inside(previous), inside(current)) {
+            (true, true) => out.push(current.clone()),
+            (true, false) => out.push(intersect(previous, current)),
+            (false, true) => {
+                out.push(intersect(previous, current));
+                out.push(current.clone());
+            }
+            (false, false) => {}
+        }
+    }
+    out
+}
+
+fn at_x(a: &Vert, b: &Vert, x: f64) -> Vert {
+    let t = (x - a.x) / (b.x - a.x);
+    Vert::new(x, a.y + t * (b.y - a.y))
+}
+
+fn at_y(a: &Vert, b: &Vert, y: f64) -> Vert {
+    let t = (y - a.y) / (b.y - a.y);
+    Vert::new(a.x + t * (b.x - a.x), y)
+}
+
+/// The outline of a line through the points with the given width.
+/// The ends are cut square at the first and last points and the corners are mitred.
+/// Returns None when the points do not go anywhere.
+pub fn stroke(points: &[Vert], width: f64) -> Option<Edge> {
+    let mut points = points.to_vec();
+    points.dedup();
+    if points.len() < 2 {
+        return None;
+    }
+    let mut left = offset_line(&points, width / 2., false);
+    let mut right = offset_line(&points, -width / 2., false);
+    right.reverse();
+    left.append(&mut right);
+    Some(close_ring(left, false))
+}
+
+/// Points of a line moved `distance` to its left, or to its right when negative, with mitred
+/// corners. A closed line also has its corner at the first point mitred and stays closed.
+pub fn offset_line(points: &[Vert], distance: f64, closed: bool) -> Vec<Vert> {
+    let mut points = points.to_vec();
+    points.dedup();
+    if closed && points.len() > 1 && points[0] == points[points.len() - 1] {
+        points.pop();
+    }
+    if points.len() < 2 {
+        return points;
+    }
+    let next = |i: usize| &points[(i + 1) % points.len()];
+    let segments = if closed {
+        points.len()
+    } else {
+        points.len() - 1
+    };
+    let normals: Vec<Vert> = (0..segments)
+        .map(|i| {
+            let d = next(i).diff(&points[i]);
+            let length = d.magnitude();
+            Vert::new(-d.y / length, d.x / length)
+        })
+        .collect();
+
+    let mut out: Vec<Vert> = points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let (before, after) = if closed {
+                (&normals[(i + segments - 1) % segments], &normals[i])
+            } else {
+                (&normals[i.saturating_sub(1)], &normals[i.min(segments - 1)])
+            };
+            // same miter as `inset_edge`, stretched so the line is `distance` away
+            let stretch = (1. + before.dot_product(after)).max(0.25);
+            Vert::new(
+                p.x + distance * (before.x + after.x) / stretch,
+                p.y + distance * (before.y + after.y) / stretch,
+            )
+        })
+        .collect();
+    if closed {
+        out.push(out[0].clone());
+    }
+    out
+}
+
+/// the distances along a line that step along it must be more than zero to get anywhere
+fn check_step(name: &str, length: f64) -> Result<(), ConversionError> {
+    if length > 0. && length.is_finite() {
+        return Ok(());
+    }
+    Err(ConversionError::InvalidOptions(format!(
+        "the {} along a line has to be more than 0, not {}",
+        name, length
+    )))
+}
+
+/// points every `spacing` along a line, starting at its first point
+pub fn points_along(points: &[Vert], spacing: f64) -> Result<Vec<Vert>, ConversionError> {
+    check_step("spacing", spacing)?;
+    if points.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut out = vec![points[0].clone()];
+    let mut left = spacing;
+    for (a, b) in points.iter().zip(points.iter().skip(1)) {
+        let length = b.diff(a).magnitude();
+        let mut done = 0.;
+        while length - done >= left {
+            done += left;
+            let t = done / length;
+            out.push(Vert::new(a.x + t * (b.x - a.x), a.y + t * (b.y - a.y)));
+            left = spacing;
+        }
+        left -= length - done;
+    }
+    Ok(out)
+}
+
+/// pieces of a line `dash` long, with `gap` between them, measured along the line
+pub fn dashes(points: &[Vert], dash: f64, gap: f64) -> Result<Vec<Vec<Vert>>, ConversionError> {
+    check_step("dash", dash)?;
+    check_step("gap", gap)?;
+    if points.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut out = vec![];
+    let mut current = vec![points[0].clone()];
+    let mut drawing = true;
+    // distance left until the current dash or gap ends
+    let mut left = dash;
+    for (a, b) in points.iter().zip(points.iter().skip(1)) {
+        let length = b.diff(a).magnitude();
+        let mut done = 0.;
+        while length - done > left {
+            done += left;
+            let t = done / length;
+            let p = Vert::new(a.x + t * (b.x - a.x), a.y + t * (b.y - a.y));
+            if drawing {
+                current.push(p);
+                out.push(std::mem::take(&mut current));
+                left = gap;
+            } else {
+                current = vec![p];
+                left = dash;
+            }
+            drawing = !drawing;
+        }
+        left -= length - done;
+        if drawing {
+            current.push(b.clone());
+        }
+    }
+    if drawing && current.len() > 1 {
+        out.push(current);
+    }
+    Ok(out)
+}
+
+/// Clip a closed edge to the rectangle from (x0, y0) to (x1, y1) (Sutherland-Hodgman).
+/// Parts of a concave ring that leave and come back are joined by lines along the side
+/// of the rectangle, which enclose no area. Returns None when nothing is left.
+pub fn clip_to_rect(edge: &Edge, x0: f64, y0: f64, x1: f64, y1: f64, hole: bool) -> Option<Edge> {
+    let mut points: Vec<Vert> = edge.iter().cloned().collect();
+    points.pop();
+    let points = clip_half_plane(&points, |v| v.x >= x0, |a, b| at_x(a, b, x0));
+    let points = clip_half_plane(&points, |v| v.x <= x1, |a, b| at_x(a, b, x1));
+    let points = clip_half_plane(&points, |v| v.y >= y0, |a, b| at_y(a, b, y0));
+    let mut points = clip_half_plane(&points, |v| v.y <= y1, |a, b| at_y(a, b, y1));
+
+    points.dedup_by(|a, b| a == b);
+    while points.len() > 1 && points[0] == points[points.len() - 1] {
+        points.pop();
+    }
+    if points.len() < 3 || signed_area(&points).abs() <= MIN_AREA {
+        return None;
+    }
+    Some(close_ring(points, hole))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edge::is_hole;
+    use std::error::Error;
+
+    #[test]
+    fn test_polygon_winding() {
+        let clockwise = vec![Vert::new(0., 0.), Vert::new(0., 1.), Vert::new(1., 0.)];
+        let outline = polygon(clockwise.clone(), false).unwrap();
+        assert!(outline.is_closed());
+        assert_eq!(outline.len(), 4);
+        assert!(!is_hole(&outline));
+        assert!(is_hole(&polygon(clockwise, true).unwrap()));
+    }
+
+    #[test]
+    fn test_rectangle() {
+        let r = rectangle(1., 2., 3., 4., false);
+        assert_eq!(
+            signed_area(&r.verts.iter().cloned().collect::<Vec<Vert>>()),
+            24.
+        );
+        assert!(is_hole(&rectangle(1., 2., 3., 4., true)));
+    }
+
+    #[test]
+    fn test_clip_to_rect() {
+        let square = rectangle(0., 0., 10., 10., false);
+        let clipped = clip_to_rect(&square, 5., -1., 20., 20., false).unwrap();
+        let points: Vec<Vert> = clipped.iter().cloned().collect();
+        assert_eq!(signed_area(&points[..points.len() - 1]), 100.);
+        assert!(clip_to_rect(&square, 11., 0., 20., 20., false).is_none());
+    }
+
+    #[test]
+    fn test_stroke() {
+        let line = stroke(
+            &[Vert::new(0., 0.), Vert::new(10., 0.), Vert::new(10., 10.)],
+            2.,
+        )
+        .unwrap();
+        assert!(!is_hole(&line));
+        let points: Vec<Vert> = line.iter().cloned().collect();
+        // an L made of an 11 by 2 and a 2 by 9 rectangle
+        assert!((signed_area(&points[..points.len() - 1]) - 2. * 40.).abs() < 1e-9);
+        assert!(points.contains(&Vert::new(11., -1.)), "mitred corner");
+        assert!(stroke(&[Vert::new(1., 1.), Vert::new(1., 1.)], 2.).is_none());
+    }
+
+    #[test]
+    fn test_invert_edge() {
+        let square = rectangle(0., 0., 1., 1., false);
+        let inverted = invert_edge(&square);
+        assert!(inverted.is_hole());
+        assert_eq!(inverted.len(), square.len());
+    }
+
+    #[test]
+    fn test_ring_contains() {
+        let r = rectangle(0., 0., 2., 2., true);
+        assert!(ring_contains(&r, &Vert::new(1., 1.)));
+        assert!(!ring_contains(&r, &Vert::new(3., 1.)));
+        assert!(!ring_contains(&r, &Vert::new(1., -1.)));
+    }
+
+    #[test]
+    fn test_circle() {
+        let c = circle(1., 1., 2., 32, false);
+        assert_eq!(c.len(), 33);
+        let center = Vert::new(1., 1.);
+        for v in c.iter() {
+            let d = v.diff(&center);
+            assert!((d.dot_product(&d) - 4.).abs() < 1e-9);
+        }
+        assert!(!is_hole(&c));
+    }
+
+    #[test]
+    fn test_regular_polygon() {
+        let hexagon = regular_polygon(0., 0., 1., 6, 90., false);
+        assert_eq!(hexagon.len(), 7);
+        assert!((hexagon[0].x).abs() < 1e-9 && (hexagon[0].y - 1.).abs() < 1e-9);
+        assert!(is_hole(&regular_polygon(0., 0., 1., 3, 0., true)));
+    }
+
+    #[test]
+    fn test_arc_points() {
+        let quarter = arc_points(0., 0., 2., 0., 90., 4);
+        assert_eq!(quarter.len(), 5);
+        assert_eq!(quarter[0], Vert::new(2., 0.));
+        assert!(quarter[4].x.abs() < 1e-9 && (quarter[4].y - 2.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_arrow() {
+        let a = arrow(&Vert::new(0., 0.), &Vert::new(10., 0.), 1., 3., 4.).unwrap();
+        assert_eq!(a.len(), 8);
+        assert!(!is_hole(&a));
+        let points: Vec<Vert> = a.iter().cloned().collect();
+        // a shaft 7 long and 1 wide, and a head 3 long and 4 wide
+        assert!((signed_area(&points) / 2. - (7. + 6.)).abs() < 1e-9);
+        assert!(arrow(&Vert::new(1., 1.), &Vert::new(1., 1.), 1., 3., 4.).is_none());
+    }
+
+    #[test]
+    fn test_too_few_points_for_a_polygon() {
+        assert!(polygon(vec![], false).is_none());
+        let p = Vert::new(1., 1.);
+        assert!(polygon(vec![p.clone(), Vert::new(2., 1.), p], false).is_none());
+    }
+
+    #[test]
+    fn test_dashes() -> Result<(), Box<dyn Error>> {
+        let line = [Vert::new(0., 0.), Vert::new(10., 0.)];
+        let pieces = dashes(&line, 2., 1.)?;
+        assert_eq!(pieces.len(), 4);
+        assert_eq!(pieces[1], vec![Vert::new(3., 0.), Vert::new(5., 0.)]);
+        assert!(dashes(&[], 2., 1.)?.is_empty());
+        for (dash, gap) in [
+            (0., 1.),
+            (2., 0.),
+            (-1., 1.),
+            (2., f64::NAN),
+            (f64::INFINITY, 1.),
+        ] {
+            assert!(dashes(&line, dash, gap).is_err(), "{} {}", dash, gap);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_points_along() -> Result<(), Box<dyn Error>> {
+        let line = [Vert::new(0., 0.), Vert::new(10., 0.)];
+        assert_eq!(points_along(&line, 5.)?.len(), 3);
+        assert!(points_along(&[], 5.)?.is_empty());
+        for spacing in [0., -1., f64::NAN] {
+            assert!(points_along(&line, spacing).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_offset_line() {
+        let line = [Vert::new(0., 0.), Vert::new(10., 0.), Vert::new(10., 10.)];
+        assert_eq!(
+            offset_line(&line, 1., false),
+            vec![Vert::new(0., 1.), Vert::new(9., 1.), Vert::new(9., 10.)]
+        );
+        // a closed square grows outward when moved to the right of its counterclockwise sides
+        let square = rectangle(0., 0., 4., 4., false);
+        let points: Vec<Vert> = square.iter().cloned().collect();
+        let grown = offset_line(&points, -1., true);
+        assert_eq!(grown.len(), 5);
+        assert_eq!(grown[0], grown[4]);
+        assert!((signed_area(&grown[..4]) - 2. * 36.).abs() < 1e-9);
+    }
+}
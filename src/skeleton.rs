@@ -0,0 +1,384 @@
+use std::collections::HashSet;
+
+use image::{DynamicImage, GrayImage, Luma};
+
+use crate::{edge::Edge, edge_img, error::ConversionError, raster::draw_polyline, vert::Vert};
+
+/// how many times finer than the input image the lines are redrawn before tracing them
+static LINE_OVERSAMPLE: u32 = 4;
+/// how far, in pixels, the simplified lines may stray from the skeleton
+static SIMPLIFY_TOLERANCE: f64 = 0.75;
+
+type Pixel = (i64, i64);
+
+/// A binary image where `true` is ink
+struct Grid {
+    width: usize,
+    height: usize,
+    ink: Vec<bool>,
+}
+
+impl Grid {
+    fn from_mask(mask: &GrayImage) -> Self {
+        Self {
+            width: mask.width() as usize,
+            height: mask.height() as usize,
+            ink: mask.pixels().map(|p| p.0[0] == 0).collect(),
+        }
+    }
+
+    fn to_mask(&self) -> GrayImage {
+        GrayImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            if self.ink[y as usize * self.width + x as usize] {
+                Luma([0])
+            } else {
+                Luma([255])
+            }
+        })
+    }
+
+    /// outside of the image counts as background
+    fn get(&self, x: i64, y: i64) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return false;
+        }
+        self.ink[y as usize * self.width + x as usize]
+    }
+
+    /// The 8 neighbors going clockwise, starting above
+    fn ring(&self, x: i64, y: i64) -> [bool; 8] {
+        [
+            self.get(x, y - 1),
+            self.get(x + 1, y - 1),
+            self.get(x + 1, y),
+            self.get(x + 1, y + 1),
+            self.get(x, y + 1),
+            self.get(x - 1, y + 1),
+            self.get(x - 1, y),
+            self.get(x - 1, y - 1),
+        ]
+    }
+
+    /// Neighbors with mixed connectivity: diagonal neighbors only count when they are
+    /// not already connected through one of the side neighbors. This stops the stair steps
+    /// of a skeleton from looking like junctions.
+    fn neighbors(&self, x: i64, y: i64) -> Vec<Pixel> {
+        let mut out = vec![];
+        for (dx, dy) in [(0, -1), (1, 0), (0, 1), (-1, 0)] {
+            if self.get(x + dx, y + dy) {
+                out.push((x + dx, y + dy));
+            }
+        }
+        for (dx, dy) in [(1, -1), (1, 1), (-1, 1), (-1, -1)] {
+            if self.get(x + dx, y + dy) && !self.get(x + dx, y) && !self.get(x, y + dy) {
+                out.push((x + dx, y + dy));
+            }
+        }
+        out
+    }
+}
+
+/// Thin the shapes of a thresholded image down to lines one pixel wide.
+/// This is the Zhang-Suen thinning algorithm, black pixels are ink.
+pub fn thin(mask: &GrayImage) -> GrayImage {
+    let mut grid = Grid::from_mask(mask);
+    loop {
+        let mut changed = false;
+        for step in 0..2 {
+            let mut remove = vec![];
+            for y in 0..grid.height as i64 {
+                for x in 0..grid.width as i64 {
+                    if !grid.get(x, y) {
+                        continue;
+                    }
+                    let p = grid.ring(x, y);
+                    let count = p.iter().filter(|&&n| n).count();
+                    if !(2..=6).contains(&count) {
+                        continue;
+                    }
+                    let transitions = (0..8).filter(|&i| !p[i] && p[(i + 1) % 8]).count();
+                    if transitions != 1 {
+                        continue;
+                    }
+                    // p[0] is above, p[2] right, p[4] below, p[6] left
+                    let keep = if step == 0 {
+                        p[2] && p[4] && (p[0] || p[6])
+                    } else {
+                        p[0] && p[6] && (p[2] || p[4])
+                    };
+                    if !keep {
+                        remove.push(y as usize * grid.width + x as usize);
+                    }
+                }
+            }
+            changed |= !remove.is_empty();
+            for i in remove {
+                grid.ink[i] = false;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    grid.to_mask()
+}
+
+fn edge_key(a: Pixel, b: Pixel) -> (Pixel, Pixel) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+fn path_length(path: &[Pixel]) -> f64 {
+    path.iter()
+        .zip(path.iter().skip(1))
+        .map(|(a, b)| (((a.0 - b.0).pow(2) + (a.1 - b.1).pow(2)) as f64).sqrt())
+        .sum()
+}
+
+/// Follow a skeleton from a start pixel through the next pixel, until it reaches
+/// an end, a junction or a pixel that was already used.
+fn walk(
+    grid: &Grid,
+    start: Pixel,
+    next: Pixel,
+    visited: &mut HashSet<(Pixel, Pixel)>,
+) -> Vec<Pixel> {
+    let mut path = vec![start, next];
+    visited.insert(edge_key(start, next));
+    let (mut prev, mut current) = (start, next);
+    loop {
+        let neighbors = grid.neighbors(current.0, current.1);
+        if neighbors.len() != 2 {
+            break;
+        }
+        let following = if neighbors[0] == prev {
+            neighbors[1]
+        } else {
+            neighbors[0]
+        };
+        if !visited.insert(edge_key(current, following)) {
+            break;
+        }
+        path.push(following);
+        prev = current;
+        current = following;
+    }
+    path
+}
+
+/// Turn a one pixel wide skeleton into polylines in pixel coordinates.
+/// Lines are split at junctions. Spurs, lines with a free end that are shorter than
+/// `prune_length`, are dropped, as they are usually noise from thinning.
+pub fn skeleton_polylines(skeleton: &GrayImage, prune_length: f64) -> Vec<Vec<Vert>> {
+    let grid = Grid::from_mask(skeleton);
+    let mut visited = HashSet::new();
+    let mut paths: Vec<Vec<Pixel>> = vec![];
+
+    let pixels: Vec<Pixel> = (0..grid.height as i64)
+        .flat_map(|y| (0..grid.width as i64).map(move |x| (x, y)))
+        .filter(|&(x, y)| grid.get(x, y))
+        .collect();
+
+    // lines between ends and junctions
+    for &p in pixels.iter() {
+        let neighbors = grid.neighbors(p.0, p.1);
+        match neighbors.len() {
+            0 => paths.push(vec![p]),
+            2 => (),
+            _ => {
+                for n in neighbors {
+                    if !visited.contains(&edge_key(p, n)) {
+                        paths.push(walk(&grid, p, n, &mut visited));
+                    }
+                }
+            }
+        }
+    }
+    // whatever is left are loops
+    for &p in pixels.iter() {
+        for n in grid.neighbors(p.0, p.1) {
+            if !visited.contains(&edge_key(p, n)) {
+                paths.push(walk(&grid, p, n, &mut visited));
+            }
+        }
+    }
+
+    let is_end = |p: &Pixel| grid.neighbors(p.0, p.1).len() == 1;
+    let is_junction = |p: &Pixel| grid.neighbors(p.0, p.1).len() > 2;
+    paths
+        .into_iter()
+        .filter(|path| {
+            let (first, last) = (&path[0], &path[path.len() - 1]);
+            let spur = (is_end(first) && is_junction(last)) || (is_junction(first) && is_end(last));
+            !(spur && path_length(path) < prune_length)
+        })
+        .map(|path| {
+            path.into_iter()
+                .map(|(x, y)| Vert::new(x as f64, y as f64))
+                .collect()
+        })
+        .collect()
+}
+
+/// Drop points from a polyline that are within the tolerance of the simplified line.
+/// This is the Ramer-Douglas-Peucker algorithm.
+pub fn simplify(points: &[Vert], tolerance: f64) -> Vec<Vert> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let (first, last) = (&points[0], &points[points.len() - 1]);
+    let (furthest, distance) = points[1..points.len() - 1]
+        .iter()
+        .map(|p| p.distance_to_segment(first, last))
+        .enumerate()
+        .fold((0, 0.), |acc, (i, d)| if d > acc.1 { (i + 1, d) } else { acc });
+
+    if distance <= tolerance {
+        return vec![first.clone(), last.clone()];
+    }
+    let mut out = simplify(&points[..=furthest], tolerance);
+    out.pop();
+    out.append(&mut simplify(&points[furthest..], tolerance));
+    out
+}
+
+/// Draw the polylines with a uniform width, on an image `oversample` times finer than the
+/// given size.
+pub fn redraw_lines(
+    polylines: &[Vec<Vert>],
+    width: f64,
+    image_width: u32,
+    image_height: u32,
+    oversample: u32,
+) -> GrayImage {
+    let k = oversample as f64;
+    // the center of pixel i lands in the center of the matching block of fine pixels
+    let offset = (k - 1.) / 2.;
+    let mut img = GrayImage::from_pixel(image_width * oversample, image_height * oversample, Luma([255]));
+    for line in polylines {
+        let fine: Vec<Vert> = line
+            .iter()
+            .map(|v| Vert::new(v.x * k + offset, v.y * k + offset))
+            .collect();
+        draw_polyline(&mut img, &fine, width * k);
+    }
+    img
+}
+
+/// Trace the centerlines of a thresholded line drawing and redraw them with a uniform width.
+/// Returns the closed edges of the redrawn lines, in the same coordinates as `edge_img`
+/// would give for the input image. The width is in pixels of the input image.
+pub fn line_art_edges(
+    img: &DynamicImage,
+    line_width: f64,
+) -> Result<Vec<Edge>, ConversionError> {
+    let skeleton = thin(&img.to_luma8());
+    let polylines: Vec<Vec<Vert>> = skeleton_polylines(&skeleton, line_width)
+        .iter()
+        .map(|line| simplify(line, SIMPLIFY_TOLERANCE))
+        .collect();
+
+    let fine = redraw_lines(
+        &polylines,
+        line_width,
+        img.width(),
+        img.height(),
+        LINE_OVERSAMPLE,
+    );
+    let edges = edge_img(&fine.into())?;
+
+    // undo the oversampling, see `redraw_lines`
+    let k = LINE_OVERSAMPLE as f64;
+    let offset = (k - 1.) / (2. * k);
+    Ok(edges
+        .closed_edges
+        .into_iter()
+        .map(|mut edge| {
+            edge.scale(1. / k);
+            edge.translate(-offset, -offset);
+            edge
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use crate::edge::edge_contains;
+
+    /// a white image with a thick black horizontal bar
+    fn bar() -> GrayImage {
+        GrayImage::from_fn(40, 20, |x, y| {
+            if (5..35).contains(&x) && (7..13).contains(&y) {
+                Luma([0])
+            } else {
+                Luma([255])
+            }
+        })
+    }
+
+    #[test]
+    fn test_thin_bar() {
+        let skeleton = thin(&bar());
+        for x in 10..30 {
+            let column = (0..20).filter(|&y| skeleton.get_pixel(x, y).0[0] == 0).count();
+            assert_eq!(column, 1, "column {x} should be one pixel thick");
+        }
+    }
+
+    #[test]
+    fn test_skeleton_polylines_bar() {
+        let lines = skeleton_polylines(&thin(&bar()), 3.);
+        assert_eq!(lines.len(), 1);
+        let line = simplify(&lines[0], SIMPLIFY_TOLERANCE);
+        let length = line[0].diff(&line[line.len() - 1]).magnitude();
+        assert!(length > 20., "the line runs along the bar");
+    }
+
+    #[test]
+    fn test_skeleton_polylines_cross() {
+        let mut img = GrayImage::from_pixel(21, 21, Luma([255]));
+        for i in 2..19 {
+            img.put_pixel(i, 10, Luma([0]));
+            img.put_pixel(10, i, Luma([0]));
+        }
+        let lines = skeleton_polylines(&img, 1.);
+        assert_eq!(lines.len(), 4, "split at the junction");
+    }
+
+    #[test]
+    fn test_skeleton_polylines_loop() {
+        let mut img = GrayImage::from_pixel(20, 20, Luma([255]));
+        for i in 5..15 {
+            for (x, y) in [(i, 5), (i, 14), (5, i), (14, i)] {
+                img.put_pixel(x, y, Luma([0]));
+            }
+        }
+        let lines = skeleton_polylines(&img, 1.);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0][0], lines[0][lines[0].len() - 1], "loops are closed");
+    }
+
+    #[test]
+    fn test_simplify() {
+        let points: Vec<Vert> = (0..10).map(|x| Vert::new(x as f64, 0.)).collect();
+        assert_eq!(simplify(&points, 0.5), vec![Vert::new(0., 0.), Vert::new(9., 0.)]);
+    }
+
+    #[test]
+    fn test_line_art_edges_uniform_width() -> Result<(), Box<dyn Error>> {
+        let width = 2.;
+        let edges = line_art_edges(&bar().into(), width)?;
+        assert_eq!(edges.len(), 1);
+        // the bar was 6 pixels thick, the new line is about 2 thick around the middle
+        // y = 10 in image coordinates is 19 - 10 = 9 in edge coordinates
+        assert!(edge_contains(&edges[0], &Vert::new(20., 9.5)));
+        assert!(!edge_contains(&edges[0], &Vert::new(20., 12.)));
+        assert!(!edge_contains(&edges[0], &Vert::new(20., 7.)));
+        Ok(())
+    }
+}
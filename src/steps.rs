@@ -0,0 +1,214 @@
+use std::collections::BTreeSet;
+
+use log::info;
+
+use crate::boolean::{difference, intersection};
+use crate::edge::Edge;
+use crate::error::ConversionError;
+use crate::ribbon::{edge_to_ribbon, polygon_to_profiled_ribbon, EdgeProfile};
+use crate::tree::Node;
+use crate::triangle::{
+    extrude_nodes_with_profile, inset_node, nodes_to_triangles, translate_triangles,
+};
+use crate::vert::Vert;
+
+/// the edges of the polygons and their holes
+fn rings(nodes: &[Node]) -> impl Iterator<Item = &Edge> {
+    nodes
+        .iter()
+        .flat_map(|n| std::iter::once(&n.data).chain(n.children.iter().map(|c| &c.data)))
+}
+
+/// a side the same whichever way it is walked
+fn side_key(a: &Vert, b: &Vert) -> [u64; 4] {
+    let (a, b) = (
+        [a.x.to_bits(), a.y.to_bits()],
+        [b.x.to_bits(), b.y.to_bits()],
+    );
+    if a < b {
+        [a[0], a[1], b[0], b[1]]
+    } else {
+        [b[0], b[1], a[0], a[1]]
+    }
+}
+
+fn side_keys<'a>(edges: impl Iterator<Item = &'a Edge>) -> BTreeSet<[u64; 4]> {
+    edges
+        .flat_map(|e| e.verts.iter().zip(e.verts.iter().skip(1)))
+        .map(|(a, b)| side_key(a, b))
+        .collect()
+}
+
+/// the walls from 0 to `top` along the sides that are not shared with the other part
+fn outer_walls(nodes: &[Node], shared: &BTreeSet<[u64; 4]>, top: f64) -> Vec<f64> {
+    rings(nodes)
+        .flat_map(|e| {
+            let is_hole = e.is_hole();
+            e.verts
+                .iter()
+                .zip(e.verts.iter().skip(1))
+                .filter(|(a, b)| !shared.contains(&side_key(a, b)))
+                .flat_map(move |(a, b)| {
+                    let mut side = Edge::from(vec![a.clone(), b.clone()]);
+                    side.mark_hole(is_hole);
+                    edge_to_ribbon(&side, top)
+                })
+        })
+        .collect()
+}
+
+/// flat 2D triangles lifted to z, turned to face down when `down`
+fn faces(tris: Vec<f64>, z: f64, down: bool) -> Vec<f64> {
+    tris.chunks(6)
+        .flat_map(|t| {
+            let corners = if down { [0, 2, 1] } else { [0, 1, 2] };
+            corners
+                .into_iter()
+                .flat_map(move |i| [t[2 * i], t[2 * i + 1], z])
+        })
+        .collect()
+}
+
+/// A solid `low` high over the footprint and `high` over the raised polygons inside it, as
+/// one closed mesh. Where the raised part stands on the rest, its walls start at `low` and
+/// nothing lies on top of anything else. The walls of the raised part above `low` are shaped
+/// like the profile, the rest are vertical.
+pub fn extrude_steps(
+    footprint: &[Node],
+    raised: &[Node],
+    low: f64,
+    high: f64,
+    profile: EdgeProfile,
+) -> Result<Vec<f64>, ConversionError> {
+    extrude_levels(footprint, raised, low, |_| (high, profile))
+}
+
+/// Like `extrude_steps`, but each polygon of the raised part, once the overlapping ones are
+/// joined, gets its own height and profile from `level`.
+pub fn extrude_levels(
+    footprint: &[Node],
+    raised: &[Node],
+    low: f64,
+    level: impl Fn(&Node) -> (f64, EdgeProfile),
+) -> Result<Vec<f64>, ConversionError> {
+    if low <= 0. {
+        return intersection(footprint, raised)?
+            .into_iter()
+            .map(|node| {
+                let (high, profile) = level(&node);
+                extrude_nodes_with_profile(vec![node], high, profile)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|tris| tris.concat());
+    }
+    // both parts come out of the same polygons, so the points where they meet are the same
+    let lower = difference(footprint, raised)?;
+    let upper = intersection(footprint, raised)?;
+    if upper.is_empty() {
+        return extrude_nodes_with_profile(lower, low, EdgeProfile::Vertical);
+    }
+    info!(
+        "stepped {} polygons up from {} around them",
+        upper.len(),
+        lower.len()
+    );
+
+    let shared: BTreeSet<[u64; 4]> = side_keys(rings(&lower))
+        .intersection(&side_keys(rings(&upper)))
+        .cloned()
+        .collect();
+    let mut tris = outer_walls(&lower, &shared, low);
+    tris.append(&mut outer_walls(&upper, &shared, low));
+
+    for node in upper.iter() {
+        let (high, profile) = level(node);
+        let rings: Vec<&Edge> = rings(std::slice::from_ref(node)).collect();
+        let (mut raised_walls, top_inset) = polygon_to_profiled_ribbon(&rings, high - low, profile);
+        translate_triangles(&mut raised_walls, 0., 0., low);
+        tris.append(&mut raised_walls);
+        let top = nodes_to_triangles(vec![inset_node(node, top_inset)])?;
+        tris.append(&mut faces(top, high, false));
+    }
+    let lower_faces = nodes_to_triangles(lower)?;
+    tris.append(&mut faces(lower_faces.clone(), low, false));
+    tris.append(&mut faces(lower_faces, 0., true));
+    tris.append(&mut faces(nodes_to_triangles(upper)?, 0., true));
+    Ok(tris)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::rectangle;
+    use crate::tree::sort_edges;
+    use crate::util::testing::{crosses_itself, is_watertight};
+    use std::error::Error;
+
+    fn max_z(tris: &[f64]) -> f64 {
+        tris.chunks(3).map(|v| v[2]).fold(f64::MIN, f64::max)
+    }
+
+    #[test]
+    fn test_steps_are_one_closed_solid() -> Result<(), Box<dyn Error>> {
+        let plate = sort_edges(vec![rectangle(0., 0., 30., 20., false)])?;
+        // one block in the middle and one running off the side, which meets the plate along
+        // a side the plate goes on along straight
+        let raised = sort_edges(vec![
+            rectangle(5., 5., 5., 5., false),
+            rectangle(20., 5., 15., 10., false),
+        ])?;
+        for profile in [EdgeProfile::Vertical, EdgeProfile::Dome] {
+            let tris = extrude_steps(&plate, &raised, 2., 5., profile)?;
+            assert!(is_watertight(&tris));
+            assert!(!crosses_itself(&tris));
+            assert_eq!(max_z(&tris), 5.);
+            // no face of the plate is left under the blocks
+            let under_block = tris
+                .chunks(9)
+                .filter(|t| t[2] == 2. && t[5] == 2. && t[8] == 2.)
+                .any(|t| {
+                    t.chunks(3)
+                        .all(|v| v[0] > 5. && v[0] < 10. && v[1] > 5. && v[1] < 10.)
+                });
+            assert!(!under_block);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_steps_in_a_row() -> Result<(), Box<dyn Error>> {
+        // the blocks line up, so earcut finds corners of the plate on straight lines
+        let plate = sort_edges(vec![rectangle(0., 0., 100., 100., false)])?;
+        let blocks = (0..5)
+            .map(|i| rectangle(10. + 6. * i as f64, 69.5, 4., 1., false))
+            .collect();
+        let tris = extrude_steps(&plate, &sort_edges(blocks)?, 2., 3., EdgeProfile::Vertical)?;
+        assert!(is_watertight(&tris));
+        Ok(())
+    }
+
+    #[test]
+    fn test_steps_meet_along_straight_sides() -> Result<(), Box<dyn Error>> {
+        // a plate with a bump on its top side, where the bump is raised, so the top side of
+        // the rest goes on straight under the bump without a corner where the bump starts
+        let plate = [
+            sort_edges(vec![rectangle(0., 0., 30., 10., false)])?,
+            sort_edges(vec![rectangle(10., 5., 10., 15., false)])?,
+        ]
+        .concat();
+        let raised = sort_edges(vec![rectangle(10., 10., 10., 10., false)])?;
+        let tris = extrude_steps(&plate, &raised, 2., 5., EdgeProfile::Vertical)?;
+        assert!(is_watertight(&tris));
+        Ok(())
+    }
+
+    #[test]
+    fn test_nothing_raised() -> Result<(), Box<dyn Error>> {
+        let plate = sort_edges(vec![rectangle(0., 0., 30., 20., false)])?;
+        let raised = sort_edges(vec![rectangle(50., 5., 5., 5., false)])?;
+        let tris = extrude_steps(&plate, &raised, 2., 5., EdgeProfile::Vertical)?;
+        assert!(is_watertight(&tris));
+        assert_eq!(max_z(&tris), 2.);
+        Ok(())
+    }
+}
@@ -1,16 +1,17 @@
 use std::{
-    error::Error,
     fs::File,
-    io::{BufWriter, Write},
+    io::{BufWriter, Seek, SeekFrom, Write},
 };
 
+use crate::error::ConversionError;
+
 static HEADER: [u8; 80] = [0; 80];
 static NORM: [u8; 12] = [0; 12];
 static ATTRIBUTES: [u8; 2] = [0; 2];
 
 static _Z_ZERO: [u8; 4] = (0.0f32).to_le_bytes();
 
-pub fn write_triangle<T>(w: &mut T, tri: &[f32]) -> Result<(), Box<dyn Error>>
+pub fn write_triangle<T>(w: &mut T, tri: &[f32]) -> Result<(), ConversionError>
 where
     T: std::io::Write,
 {
@@ -35,7 +36,7 @@ where
     Ok(())
 }
 
-pub fn write_triangle_f64<T>(w: &mut T, tri: &[f64]) -> Result<(), Box<dyn Error>>
+pub fn write_triangle_f64<T>(w: &mut T, tri: &[f64]) -> Result<(), ConversionError>
 where
     T: std::io::Write,
 {
@@ -60,7 +61,7 @@ where
     Ok(())
 }
 
-pub fn write_2d_triangle<T>(w: &mut T, tri: &[f64], height: f64) -> Result<(), Box<dyn Error>>
+pub fn write_2d_triangle<T>(w: &mut T, tri: &[f64], height: f64) -> Result<(), ConversionError>
 where
     T: std::io::Write,
 {
@@ -81,7 +82,7 @@ where
     )
 }
 
-pub fn write_stl_2d(filename: &str, tris: Vec<f64>) -> Result<(), Box<dyn Error>> {
+pub fn write_stl_2d(filename: &str, tris: Vec<f64>) -> Result<(), ConversionError> {
     let f = File::create(filename)?;
     let mut writer = BufWriter::new(f);
     assert!(tris.len() % 6 == 0);
@@ -92,16 +93,28 @@ pub fn write_stl_2d(filename: &str, tris: Vec<f64>) -> Result<(), Box<dyn Error>
     for tri in tris.chunks(6) {
         write_2d_triangle(&mut writer, tri, 0.0)?;
     }
+    writer.flush()?;
 
     Ok(())
 }
 
+/// fails when the values do not make up whole triangles of three points
+fn check_whole_triangles(tris: &[f64]) -> Result<(), ConversionError> {
+    match tris.len() % 9 {
+        0 => Ok(()),
+        left => Err(ConversionError::Triangulation(format!(
+            "{} values left over after the last whole triangle",
+            left
+        ))),
+    }
+}
+
 pub fn triangles_to_stl_writer<T: Write>(
     writer: &mut BufWriter<T>,
     tris: Vec<f64>,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<(), ConversionError> {
     let n3d = 9;
-    assert!(tris.len() % n3d == 0);
+    check_whole_triangles(&tris)?;
     let count: u32 = (tris.len() / n3d) as u32;
 
     writer.write_all(&HEADER)?;
@@ -112,10 +125,39 @@ pub fn triangles_to_stl_writer<T: Write>(
     Ok(())
 }
 
-pub fn write_stl_3d(filename: &str, tris: Vec<f64>) -> Result<(), Box<dyn Error>> {
+/// Write triangles to a binary stl as they are made, one batch at a time. The count in the
+/// header is filled in at the end, so the writer has to be able to go back to it.
+/// Returns the number of triangles written.
+pub fn write_stl_stream<W, I>(writer: &mut W, batches: I) -> Result<u32, ConversionError>
+where
+    W: Write + Seek,
+    I: IntoIterator<Item = Result<Vec<f64>, ConversionError>>,
+{
+    let start = writer.stream_position()?;
+    writer.write_all(&HEADER)?;
+    writer.write_all(&0u32.to_le_bytes())?;
+    let mut count: u32 = 0;
+    for tris in batches {
+        let tris = tris?;
+        check_whole_triangles(&tris)?;
+        for tri in tris.chunks(9) {
+            write_triangle_f64(writer, tri)?;
+        }
+        count += (tris.len() / 9) as u32;
+    }
+    let end = writer.stream_position()?;
+    writer.seek(SeekFrom::Start(start + HEADER.len() as u64))?;
+    writer.write_all(&count.to_le_bytes())?;
+    writer.seek(SeekFrom::Start(end))?;
+    writer.flush()?;
+    Ok(count)
+}
+
+pub fn write_stl_3d(filename: &str, tris: Vec<f64>) -> Result<(), ConversionError> {
     let f = File::create(filename)?;
     let mut writer = BufWriter::new(f);
     triangles_to_stl_writer(&mut writer, tris)?;
+    writer.flush()?;
     Ok(())
 }
 
@@ -123,7 +165,8 @@ pub fn write_stl_3d(filename: &str, tris: Vec<f64>) -> Result<(), Box<dyn Error>
 mod tests {
     use std::error::Error;
 
-    use super::write_stl_2d;
+    use super::{write_stl_2d, write_stl_stream};
+    use std::io::Cursor;
 
     #[test]
     fn test_chunks() {
@@ -137,6 +180,22 @@ mod tests {
     #[test]
     fn test_write_stl_2d() -> Result<(), Box<dyn Error>> {
         let tris: Vec<f64> = vec![0., 0., 10., 0., 10., 10.];
-        write_stl_2d("test.stl", tris)
+        write_stl_2d("test.stl", tris)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_stl_stream() -> Result<(), Box<dyn Error>> {
+        let tri = vec![0., 0., 0., 1., 0., 0., 0., 1., 0.];
+        let batches = vec![Ok(tri.clone()), Ok(vec![]), Ok([tri.clone(), tri].concat())];
+        let mut out = Cursor::new(vec![]);
+        assert_eq!(write_stl_stream(&mut out, batches)?, 3);
+        let bytes = out.into_inner();
+        assert_eq!(bytes[80..84], 3u32.to_le_bytes());
+        assert_eq!(bytes.len(), 84 + 3 * 50);
+        // a batch cut off in the middle of a triangle is an error, not a panic
+        let batches = vec![Ok(vec![0.; 6])];
+        assert!(write_stl_stream(&mut Cursor::new(vec![]), batches).is_err());
+        Ok(())
     }
 }
@@ -0,0 +1,234 @@
+use std::collections::VecDeque;
+
+use image::DynamicImage;
+use log::info;
+
+use crate::edge::{try_is_hole, Edge};
+use crate::error::ConversionError;
+use crate::frame::{border_edges, clear_border, BorderOptions};
+use crate::lattice::{check_traceable, Tracer};
+use crate::ribbon::EdgeProfile;
+use crate::threshold_img;
+use crate::tree::{encloses, Node};
+use crate::triangle::{extrude_edges_with_profile, extrude_nodes_with_profile};
+
+/// Sorts closed edges into polygons while they are traced.
+/// Tracing goes down the image a row at a time, so the holes of an outline are all
+/// closed before the outline is. Once an outline closes, the holes waiting inside it are
+/// its holes, and it can be made right away.
+#[derive(Debug, Default)]
+pub struct PolygonSorter {
+    holes: Vec<Edge>,
+}
+
+impl PolygonSorter {
+    /// the outline with its holes, when the edge is an outline
+    pub fn push(&mut self, edge: Edge) -> Result<Option<Node>, ConversionError> {
+        if try_is_hole(&edge)? {
+            self.holes.push(edge);
+            return Ok(None);
+        }
+        let children = self
+            .holes
+            .extract_if(|hole| edge.possibly_contains(&hole[0]) && encloses(&edge, hole))
+            .map(|data| Node {
+                data,
+                children: vec![],
+            })
+            .collect();
+        Ok(Some(Node {
+            data: edge,
+            children,
+        }))
+    }
+
+    /// fails when a hole was never closed around by an outline
+    pub fn finish(self) -> Result<(), ConversionError> {
+        match self.holes.first() {
+            None => Ok(()),
+            Some(hole) => Err(ConversionError::DegenerateContour(format!(
+                "the hole starting at {:?} is not inside any outline",
+                hole.front()
+            ))),
+        }
+    }
+}
+
+/// The triangles of an image one polygon at a time, see `image_to_triangle_stream`
+pub struct TriangleStream {
+    tracer: Tracer,
+    sorter: PolygonSorter,
+    polygons: VecDeque<Node>,
+    /// made last, since it goes around everything else
+    border: Vec<Edge>,
+    height: f64,
+    scale_factor: f64,
+    profile: EdgeProfile,
+    finished: bool,
+}
+
+/// Like `image_to_stl_with_options`, with the triangles of each polygon handed out as soon as
+/// tracing has closed it, so only the edges still being traced are kept and large images do
+/// not need all their triangles in memory at once. Each item is the scaled triangles of a
+/// polygon. Molds, engraving and line art need every edge at once, so they can not be streamed.
+pub fn image_to_triangle_stream(
+    mut img: DynamicImage,
+    threshold: u8,
+    height: f64,
+    scale_factor: f64,
+    profile: EdgeProfile,
+    border: Option<&BorderOptions>,
+) -> Result<TriangleStream, ConversionError> {
+    check_traceable(img.width(), img.height())?;
+    threshold_img(&mut img, threshold);
+    info!("thresholded image for stl");
+    let border = match border {
+        Some(border) => {
+            clear_border(&mut img, border);
+            border_edges(img.width(), img.height(), border)
+        }
+        None => vec![],
+    };
+    Ok(TriangleStream {
+        tracer: Tracer::new(img.into_luma8())?,
+        sorter: PolygonSorter::default(),
+        polygons: VecDeque::new(),
+        border,
+        height,
+        scale_factor,
+        profile,
+        finished: false,
+    })
+}
+
+impl TriangleStream {
+    /// how much of the image has been traced, from 0 to 1
+    pub fn progress(&self) -> f64 {
+        self.tracer.traced_rows() as f64 / self.tracer.rows() as f64
+    }
+
+    fn trace_row(&mut self) -> Result<(), ConversionError> {
+        self.tracer.trace_row();
+        for edge in self.tracer.take_closed()? {
+            if let Some(node) = self.sorter.push(edge)? {
+                self.polygons.push_back(node);
+            }
+        }
+        Ok(())
+    }
+
+    fn next_polygon(&mut self) -> Result<Option<Vec<f64>>, ConversionError> {
+        let tris = loop {
+            if let Some(node) = self.polygons.pop_front() {
+                break extrude_nodes_with_profile(vec![node], self.height, self.profile)?;
+            }
+            if !self.finished && self.tracer.traced_rows() < self.tracer.rows() {
+                self.trace_row()?;
+                continue;
+            }
+            if self.finished {
+                return Ok(None);
+            }
+            self.finished = true;
+            std::mem::take(&mut self.sorter).finish()?;
+            info!("traced every row for stl");
+            if self.border.is_empty() {
+                return Ok(None);
+            }
+            let border = std::mem::take(&mut self.border);
+            break extrude_edges_with_profile(border, self.height, self.profile)?;
+        };
+        Ok(Some(
+            tris.into_iter().map(|v| v * self.scale_factor).collect(),
+        ))
+    }
+}
+
+impl Iterator for TriangleStream {
+    type Item = Result<Vec<f64>, ConversionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.next_polygon();
+        if next.is_err() {
+            // nothing more can be traced after a bad edge
+            self.polygons.clear();
+            self.finished = true;
+        }
+        next.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::constants::DEFAULT_THRESHOLD_VALUE;
+    use crate::frame::BorderOptions;
+    use crate::triangle::{extrude_edges, image_to_edges, StlOptions};
+    use crate::util::testing::closed_edge;
+    use image::io::Reader as ImageReader;
+    use std::error::Error;
+
+    /// the same triangles in any order
+    fn sorted(mut tris: Vec<f64>) -> Vec<Vec<u64>> {
+        let mut out: Vec<Vec<u64>> = tris
+            .chunks_mut(9)
+            .map(|t| t.iter().map(|v| v.to_bits()).collect())
+            .collect();
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn test_sorter_waits_for_outlines() -> Result<(), Box<dyn Error>> {
+        let mut sorter = PolygonSorter::default();
+        assert!(sorter.push(closed_edge(2., 4., 4., true))?.is_none());
+        assert!(sorter.push(closed_edge(2., 40., 40., true))?.is_none());
+        let node = sorter.push(closed_edge(10., 0., 0., false))?.unwrap();
+        assert_eq!(node.children.len(), 1);
+        // the second hole has nothing around it
+        assert!(sorter.finish().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_makes_the_same_triangles() -> Result<(), Box<dyn Error>> {
+        for name in ["./images/stick-figure.png", "./images/moontower.png"] {
+            same_triangles(name)?;
+        }
+        Ok(())
+    }
+
+    fn same_triangles(filename: &str) -> Result<(), Box<dyn Error>> {
+        let img = ImageReader::open(filename)?
+            .with_guessed_format()?
+            .decode()?;
+        let options = StlOptions {
+            border: Some(BorderOptions::default()),
+            ..Default::default()
+        };
+        let edges = image_to_edges(img.clone(), DEFAULT_THRESHOLD_VALUE, &options)?;
+        let batch: Vec<f64> = extrude_edges(edges, 2.)?
+            .into_iter()
+            .map(|v| v * 0.5)
+            .collect();
+        let mut stream = image_to_triangle_stream(
+            img,
+            DEFAULT_THRESHOLD_VALUE,
+            2.,
+            0.5,
+            EdgeProfile::Vertical,
+            options.border.as_ref(),
+        )?;
+        let mut streamed = vec![];
+        let mut polygons = 0;
+        while let Some(tris) = stream.next() {
+            streamed.append(&mut tris?);
+            polygons += 1;
+            assert!(stream.progress() <= 1.);
+        }
+        assert!(polygons > 1);
+        assert_eq!(stream.progress(), 1.);
+        assert_eq!(sorted(streamed), sorted(batch));
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+use image::GrayImage;
+use serde::{Deserialize, Serialize};
+
+use crate::edge::Edge;
+use crate::shapes::{circle, clip_to_rect, close_ring, rectangle};
+use crate::vert::Vert;
+
+/// number of sides used for the round bumps
+static DOT_SEGMENTS: usize = 12;
+
+/// A raised pattern used to fill a region so it can be told apart by touch.
+/// Dimensions are in pixels of the image the pattern is applied to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Texture {
+    /// the whole region is raised
+    #[default]
+    Solid,
+    /// round bumps on a square grid
+    Dots { spacing: u32, radius: u32 },
+    /// diagonal lines running from the bottom left to the top right
+    Stripes { spacing: u32, width: u32 },
+    /// horizontal and vertical lines
+    Grid { spacing: u32, width: u32 },
+}
+
+impl Texture {
+    /// the names used to choose a texture in the ui
+    pub const NAMES: [&'static str; 4] = ["solid", "dots", "stripes", "grid"];
+
+    /// a texture with sizes that can be felt on a print at the usual scale
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "solid" => Some(Texture::Solid),
+            "dots" => Some(Texture::Dots {
+                spacing: 8,
+                radius: 2,
+            }),
+            "stripes" => Some(Texture::Stripes {
+                spacing: 8,
+                width: 3,
+            }),
+            "grid" => Some(Texture::Grid {
+                spacing: 8,
+                width: 2,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Texture::Solid => "solid",
+            Texture::Dots { .. } => "dots",
+            Texture::Stripes { .. } => "stripes",
+            Texture::Grid { .. } => "grid",
+        }
+    }
+
+    /// check if the pattern is raised at the pixel (x, y)
+    pub fn is_raised(&self, x: u32, y: u32) -> bool {
+        match *self {
+            Texture::Solid => true,
+            Texture::Dots { spacing, radius } => {
+                let spacing = spacing.max(1) as i64;
+                let half = spacing / 2;
+                let dx = (x as i64 % spacing) - half;
+                let dy = (y as i64 % spacing) - half;
+                dx * dx + dy * dy <= (radius as i64).pow(2)
+            }
+            Texture::Stripes { spacing, width } => (x + y) % spacing.max(1) < width,
+            Texture::Grid { spacing, width } => {
+                x % spacing.max(1) < width || y % spacing.max(1) < width
+            }
+        }
+    }
+
+    /// Clear the parts of a thresholded mask that are not covered by the pattern.
+    /// The mask uses the same convention as the thresholded images: black pixels are the shape.
+    pub fn apply(&self, mask: &mut GrayImage) {
+        self.apply_at(mask, 0, 0);
+    }
+
+    /// Like `apply` for a mask cut out of a bigger image at (x0, y0), so the pattern lines up
+    /// with the one the whole image would get
+    pub fn apply_at(&self, mask: &mut GrayImage, x0: u32, y0: u32) {
+        if *self == Texture::Solid {
+            return;
+        }
+        for (x, y, p) in mask.enumerate_pixels_mut() {
+            if p.0[0] == 0 && !self.is_raised(x + x0, y + y0) {
+                p.0[0] = 255;
+            }
+        }
+    }
+
+    /// The pattern as closed edges filling the rectangle from (x0, y0) to (x1, y1),
+    /// with the same sizes as the raster pattern but in the units of the rectangle.
+    /// Bumps that would be cut by the sides of the rectangle are left out.
+    pub fn pattern_edges(&self, x0: f64, y0: f64, x1: f64, y1: f64) -> Vec<Edge> {
+        let clip = |e: Edge| clip_to_rect(&e, x0, y0, x1, y1, false);
+        match *self {
+            Texture::Solid => vec![rectangle(x0, y0, x1 - x0, y1 - y0, false)],
+            Texture::Dots { spacing, radius } => {
+                let (spacing, radius) = (spacing.max(1) as f64, radius as f64);
+                let mut out = vec![];
+                let mut y = (y0 / spacing).floor() * spacing + spacing / 2.;
+                while y < y1 {
+                    let mut x = (x0 / spacing).floor() * spacing + spacing / 2.;
+                    while x < x1 {
+                        let inside = x - radius >= x0 && x + radius <= x1;
+                        if inside && y - radius >= y0 && y + radius <= y1 {
+                            out.push(circle(x, y, radius, DOT_SEGMENTS, false));
+                        }
+                        x += spacing;
+                    }
+                    y += spacing;
+                }
+                out
+            }
+            Texture::Stripes { spacing, width } => {
+                let (spacing, width) = (spacing.max(1) as f64, width as f64);
+                // bands where x + y is within `width` above a multiple of the spacing
+                let mut out = vec![];
+                let mut k = ((x0 + y0) / spacing).floor() * spacing;
+                while k < x1 + y1 {
+                    let band = close_ring(
+                        vec![
+                            Vert::new(k - y0, y0),
+                            Vert::new(k + width - y0, y0),
+                            Vert::new(k + width - y1, y1),
+                            Vert::new(k - y1, y1),
+                        ],
+                        false,
+                    );
+                    out.extend(clip(band));
+                    k += spacing;
+                }
+                out
+            }
+            Texture::Grid { spacing, width } => {
+                let (spacing, width) = (spacing.max(1) as f64, width as f64);
+                let mut out = vec![];
+                let mut x = (x0 / spacing).floor() * spacing;
+                while x < x1 {
+                    out.extend(clip(rectangle(x, y0, width, y1 - y0, false)));
+                    x += spacing;
+                }
+                let mut y = (y0 / spacing).floor() * spacing;
+                while y < y1 {
+                    out.extend(clip(rectangle(x0, y, x1 - x0, width, false)));
+                    y += spacing;
+                }
+                out
+            }
+        }
+    }
+
+    /// like `pattern_edges`, with `scale` units of the rectangle for each unit of the pattern
+    pub fn scaled_pattern_edges(
+        &self,
+        x0: f64,
+        y0: f64,
+        x1: f64,
+        y1: f64,
+        scale: f64,
+    ) -> Vec<Edge> {
+        self.pattern_edges(x0 / scale, y0 / scale, x1 / scale, y1 / scale)
+            .into_iter()
+            .map(|mut e| {
+                e.scale(scale);
+                e
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    #[test]
+    fn test_solid_keeps_mask() {
+        let mut mask = GrayImage::from_pixel(10, 10, Luma([0]));
+        Texture::Solid.apply(&mut mask);
+        assert!(mask.pixels().all(|p| p.0[0] == 0));
+    }
+
+    #[test]
+    fn test_pattern_edges() {
+        assert_eq!(Texture::Solid.pattern_edges(0., 0., 4., 4.).len(), 1);
+        let dots = Texture::Dots {
+            spacing: 4,
+            radius: 1,
+        };
+        assert_eq!(dots.pattern_edges(0., 0., 8., 8.).len(), 4);
+        let stripes = Texture::Stripes {
+            spacing: 4,
+            width: 1,
+        };
+        let bands = stripes.pattern_edges(0., 0., 8., 8.);
+        assert!(!bands.is_empty());
+        for v in bands.iter().flat_map(|e| e.iter()) {
+            assert!(v.x >= 0. && v.x <= 8. && v.y >= 0. && v.y <= 8.);
+        }
+        // the same dots twice as big
+        let scaled = dots.scaled_pattern_edges(0., 0., 16., 16., 2.);
+        assert_eq!(scaled.len(), 4);
+        assert!(scaled[0].iter().any(|v| *v == Vert::new(6., 4.)));
+    }
+
+    #[test]
+    fn test_dots_clear_between_bumps() {
+        let mut mask = GrayImage::from_pixel(20, 20, Luma([0]));
+        Texture::Dots {
+            spacing: 10,
+            radius: 2,
+        }
+        .apply(&mut mask);
+        // centers of the bumps stay raised
+        assert_eq!(mask.get_pixel(5, 5).0[0], 0);
+        assert_eq!(mask.get_pixel(15, 5).0[0], 0);
+        // the space between them is cleared
+        assert_eq!(mask.get_pixel(0, 0).0[0], 255);
+        assert_eq!(mask.get_pixel(10, 5).0[0], 255);
+    }
+
+    #[test]
+    fn test_texture_does_not_add_to_background() {
+        let mut mask = GrayImage::from_pixel(10, 10, Luma([255]));
+        Texture::Grid {
+            spacing: 4,
+            width: 1,
+        }
+        .apply(&mut mask);
+        assert!(mask.pixels().all(|p| p.0[0] == 255));
+    }
+}
@@ -0,0 +1,591 @@
+use image::DynamicImage;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::boolean::{boolean_edges, intersection, union, BooleanOp};
+use crate::braille::{braille_cells, braille_triangles, BrailleDimensions};
+use crate::edge::Edge;
+use crate::error::ConversionError;
+use crate::lattice::check_traceable;
+use crate::ribbon::EdgeProfile;
+use crate::shapes::{close_ring, rectangle};
+use crate::steps::extrude_steps;
+use crate::stl::write_stl_3d;
+use crate::tree::{sort_edges, Node};
+use crate::triangle::{image_to_edges, translate_triangles, StlOptions};
+use crate::vert::Vert;
+
+/// space between the label and the sides of the tile, and around the label
+static LABEL_MARGIN: f64 = 2.;
+
+/// How neighboring tiles line up with each other
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Joint {
+    /// tiles meet side to side with nothing to line them up
+    Butt,
+    /// both tiles carry a strip this wide along the sides they share
+    Overlap(f64),
+    /// A tab on the right and bottom sides of a tile fits a notch in the next tile.
+    /// The notch is bigger than the tab by `clearance` on every side.
+    Keys {
+        width: f64,
+        depth: f64,
+        clearance: f64,
+    },
+}
+
+/// How to split a graphic into tiles. Sizes are in stl units (usually millimeters).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TileOptions {
+    /// Largest size of the graphic on one tile.
+    /// Key tabs stick out past this by their depth.
+    pub tile_width: f64,
+    pub tile_height: f64,
+    /// thickness of the plate the graphic sits on
+    pub base_thickness: f64,
+    pub joint: Joint,
+    /// put the row letter and column number in braille in the lower left of each tile
+    pub labels: bool,
+    pub braille: BrailleDimensions,
+}
+
+impl Default for TileOptions {
+    fn default() -> Self {
+        Self {
+            tile_width: 150.,
+            tile_height: 150.,
+            base_thickness: 2.,
+            joint: Joint::Keys {
+                width: 10.,
+                depth: 4.,
+                clearance: 0.2,
+            },
+            labels: true,
+            braille: BrailleDimensions::default(),
+        }
+    }
+}
+
+/// A single plate of a tiled graphic
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Tile {
+    /// rows count down from the top of the graphic
+    pub row: usize,
+    pub column: usize,
+    pub label: String,
+    /// position of the lower left corner of the tile in the whole graphic
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    /// the plate and graphic, moved so the lower left corner of the tile is at the origin
+    #[serde(skip)]
+    pub triangles: Vec<f64>,
+}
+
+/// A graphic split into tiles
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TiledGraphic {
+    pub width: f64,
+    pub height: f64,
+    pub columns: usize,
+    pub rows: usize,
+    pub options: TileOptions,
+    pub tiles: Vec<Tile>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rect {
+    x0: f64,
+    y0: f64,
+    x1: f64,
+    y1: f64,
+}
+
+impl Rect {
+    fn new(x0: f64, y0: f64, x1: f64, y1: f64) -> Self {
+        Self { x0, y0, x1, y1 }
+    }
+
+    fn edge(&self) -> Edge {
+        rectangle(
+            self.x0,
+            self.y0,
+            self.x1 - self.x0,
+            self.y1 - self.y0,
+            false,
+        )
+    }
+}
+
+/// spreadsheet style names for rows: a to z, then aa, ab and so on
+fn row_name(mut row: usize) -> String {
+    let mut out = vec![];
+    loop {
+        out.push((b'a' + (row % 26) as u8) as char);
+        if row < 26 {
+            break;
+        }
+        row = row / 26 - 1;
+    }
+    out.iter().rev().collect()
+}
+
+/// The part of the graphic on a tile: everything inside the tile and the tabs it sticks into
+/// its neighbors, except the keep out areas. The shapes are cut all at once, so outlines cut
+/// into several pieces come apart and holes stay inside what is left of their outline.
+fn tile_relief(
+    nodes: &[Node],
+    tile: &Rect,
+    tabs: &[Rect],
+    keep_out: &[Rect],
+) -> Result<Vec<Node>, ConversionError> {
+    let mut area = boolean_edges(
+        &[tile.edge()],
+        &keep_out.iter().map(Rect::edge).collect::<Vec<Edge>>(),
+        BooleanOp::Difference,
+    )?;
+    if !tabs.is_empty() {
+        area = union(&area, &sort_edges(tabs.iter().map(Rect::edge).collect())?)?;
+    }
+    intersection(nodes, &area)
+}
+
+/// the tabs a tile sticks into its neighbors to the right and below, and the notches
+/// it has for the tabs of its neighbors to the left and above
+fn keys(
+    tile: &Rect,
+    right: bool,
+    below: bool,
+    left: bool,
+    above: bool,
+    joint: &Joint,
+) -> (Vec<Rect>, Vec<Rect>) {
+    let (width, depth, clearance) = if let Joint::Keys {
+        width,
+        depth,
+        clearance,
+    } = joint
+    {
+        (*width, *depth, *clearance)
+    } else {
+        return (vec![], vec![]);
+    };
+    let mx = (tile.x0 + tile.x1) / 2.;
+    let my = (tile.y0 + tile.y1) / 2.;
+    let (w, n) = (width / 2., width / 2. + clearance);
+
+    let mut tabs = vec![];
+    if right {
+        tabs.push(Rect::new(tile.x1, my - w, tile.x1 + depth, my + w));
+    }
+    if below {
+        tabs.push(Rect::new(mx - w, tile.y0 - depth, mx + w, tile.y0));
+    }
+    let mut notches = vec![];
+    if left {
+        notches.push(Rect::new(
+            tile.x0,
+            my - n,
+            tile.x0 + depth + clearance,
+            my + n,
+        ));
+    }
+    if above {
+        notches.push(Rect::new(
+            mx - n,
+            tile.y1 - depth - clearance,
+            mx + n,
+            tile.y1,
+        ));
+    }
+    (tabs, notches)
+}
+
+/// The outline of the plate under a tile, going counterclockwise with tabs sticking out
+/// of the bottom and right sides and notches cut into the top and left sides.
+/// Tabs and notches are centered on their side, as returned by `keys`.
+fn plate_outline(tile: &Rect, tabs: &[Rect], notches: &[Rect]) -> Edge {
+    let mut points = vec![Vert::new(tile.x0, tile.y0)];
+    for t in tabs.iter().filter(|t| t.y1 == tile.y0) {
+        points.extend([
+            Vert::new(t.x0, t.y1),
+            Vert::new(t.x0, t.y0),
+            Vert::new(t.x1, t.y0),
+            Vert::new(t.x1, t.y1),
+        ]);
+    }
+    points.push(Vert::new(tile.x1, tile.y0));
+    for t in tabs.iter().filter(|t| t.x0 == tile.x1) {
+        points.extend([
+            Vert::new(t.x0, t.y0),
+            Vert::new(t.x1, t.y0),
+            Vert::new(t.x1, t.y1),
+            Vert::new(t.x0, t.y1),
+        ]);
+    }
+    points.push(Vert::new(tile.x1, tile.y1));
+    for n in notches.iter().filter(|n| n.y1 == tile.y1) {
+        points.extend([
+            Vert::new(n.x1, n.y1),
+            Vert::new(n.x1, n.y0),
+            Vert::new(n.x0, n.y0),
+            Vert::new(n.x0, n.y1),
+        ]);
+    }
+    points.push(Vert::new(tile.x0, tile.y1));
+    for n in notches.iter().filter(|n| n.x0 == tile.x0) {
+        points.extend([
+            Vert::new(n.x0, n.y1),
+            Vert::new(n.x1, n.y1),
+            Vert::new(n.x1, n.y0),
+            Vert::new(n.x0, n.y0),
+        ]);
+    }
+    close_ring(points, false)
+}
+
+/// number of tiles needed to cover a length, when neighbors share `overlap`
+fn tile_count(length: f64, tile: f64, overlap: f64) -> usize {
+    (((length - overlap) / (tile - overlap)).ceil() as usize).max(1)
+}
+
+/// Split sorted polygons covering 0 to width and 0 to height into tiles.
+/// Every tile gets a plate `base_thickness` thick with the graphic extruded `height` on top.
+/// Walls are kept vertical so the cut sides of neighboring tiles sit flush.
+pub fn tile_nodes(
+    nodes: &[Node],
+    width: f64,
+    height: f64,
+    relief_height: f64,
+    options: &TileOptions,
+) -> Result<TiledGraphic, ConversionError> {
+    let overlap = match options.joint {
+        Joint::Overlap(overlap) => overlap,
+        _ => 0.,
+    };
+    if options.tile_width <= overlap || options.tile_height <= overlap {
+        return Err(ConversionError::InvalidOptions(
+            "tiles must be bigger than the overlap between them".to_string(),
+        ));
+    }
+    let columns = tile_count(width, options.tile_width, overlap);
+    let rows = tile_count(height, options.tile_height, overlap);
+    let step_x = options.tile_width - overlap;
+    let step_y = options.tile_height - overlap;
+    let base = options.base_thickness;
+    info!(
+        "splitting {}x{} graphic into {} columns and {} rows",
+        width, height, columns, rows
+    );
+
+    let mut tiles = vec![];
+    for row in 0..rows {
+        for column in 0..columns {
+            let x0 = column as f64 * step_x;
+            let y1 = height - row as f64 * step_y;
+            let rect = Rect::new(
+                x0,
+                (y1 - options.tile_height).max(0.),
+                (x0 + options.tile_width).min(width),
+                y1,
+            );
+            let label = format!("{}{}", row_name(row), column + 1);
+
+            let (tabs, mut keep_out) = keys(
+                &rect,
+                column + 1 < columns,
+                row + 1 < rows,
+                column > 0,
+                row > 0,
+                &options.joint,
+            );
+            let plate = sort_edges(vec![plate_outline(&rect, &tabs, &keep_out)])?;
+            let mut triangles = vec![];
+
+            if options.labels {
+                let dims = &options.braille;
+                let (lx, ly) = (rect.x0 + LABEL_MARGIN, rect.y0 + LABEL_MARGIN);
+                let label_width = dims.width(braille_cells(&label).len());
+                keep_out.push(Rect::new(
+                    rect.x0,
+                    rect.y0,
+                    lx + label_width + LABEL_MARGIN,
+                    ly + dims.height() + LABEL_MARGIN,
+                ));
+                triangles.extend(braille_triangles(
+                    &label,
+                    lx,
+                    ly + dims.height(),
+                    base,
+                    dims,
+                )?);
+            }
+
+            // tabs carry the graphic of the neighbor they stick into, so none of it is lost
+            let pieces = tile_relief(nodes, &rect, &tabs, &keep_out)?;
+            triangles.append(&mut extrude_steps(
+                &plate,
+                &pieces,
+                base,
+                base + relief_height,
+                EdgeProfile::Vertical,
+            )?);
+            translate_triangles(&mut triangles, -rect.x0, -rect.y0, 0.);
+
+            tiles.push(Tile {
+                row,
+                column,
+                label,
+                x: rect.x0,
+                y: rect.y0,
+                width: rect.x1 - rect.x0,
+                height: rect.y1 - rect.y0,
+                triangles,
+            });
+        }
+    }
+
+    Ok(TiledGraphic {
+        width,
+        height,
+        columns,
+        rows,
+        options: *options,
+        tiles,
+    })
+}
+
+/// Like `image_to_stl_with_options`, but split into tiles.
+/// The height and scale factor work the same way, while the tile options are in stl units.
+pub fn image_to_tiles(
+    img: DynamicImage,
+    threshold: u8,
+    height: f64,
+    scale_factor: f64,
+    options: &StlOptions,
+    tile_options: &TileOptions,
+) -> Result<TiledGraphic, ConversionError> {
+    check_traceable(img.width(), img.height())?;
+    let (width, image_height) = (img.width(), img.height());
+    let mut edges = image_to_edges(img, threshold, options)?;
+    for edge in edges.iter_mut() {
+        edge.scale(scale_factor);
+    }
+    let nodes = sort_edges(edges)?;
+    tile_nodes(
+        &nodes,
+        (width - 1) as f64 * scale_factor,
+        (image_height - 1) as f64 * scale_factor,
+        height * scale_factor,
+        tile_options,
+    )
+}
+
+impl TiledGraphic {
+    /// the stl file for a tile, next to the manifest
+    fn tile_file_name(prefix: &str, tile: &Tile) -> String {
+        format!("{}-{}.stl", prefix, tile.label)
+    }
+
+    /// describe the layout of the tiles and which file each one is in
+    pub fn manifest(&self, prefix: &str) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).expect("tiles are always serializable");
+        for (tile, entry) in self
+            .tiles
+            .iter()
+            .zip(value["tiles"].as_array_mut().unwrap())
+        {
+            let file = Self::tile_file_name(prefix, tile);
+            let file = Path::new(&file).file_name().unwrap().to_string_lossy();
+            entry["file"] = file.into();
+        }
+        value
+    }
+
+    /// write `<prefix>-<label>.stl` for every tile and the manifest to `<prefix>-manifest.json`
+    pub fn write(self, prefix: &str) -> Result<(), ConversionError> {
+        let manifest = serde_json::to_string_pretty(&self.manifest(prefix))?;
+        std::fs::write(format!("{}-manifest.json", prefix), manifest + "\n")?;
+        for tile in self.tiles {
+            write_stl_3d(&Self::tile_file_name(prefix, &tile), tile.triangles)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::constants::DEFAULT_THRESHOLD_VALUE;
+    use crate::shapes::signed_area;
+    use crate::triangle::extrude_nodes_with_profile;
+    use crate::util::testing::{image_from_file, is_watertight};
+    use std::error::Error;
+
+    fn max_xy(tris: &[f64]) -> (f64, f64) {
+        tris.chunks(3)
+            .fold((f64::MIN, f64::MIN), |(x, y), v| (x.max(v[0]), y.max(v[1])))
+    }
+
+    #[test]
+    fn test_row_name() {
+        assert_eq!(row_name(0), "a");
+        assert_eq!(row_name(25), "z");
+        assert_eq!(row_name(26), "aa");
+        assert_eq!(row_name(27), "ab");
+    }
+
+    #[test]
+    fn test_tile_relief_cuts_shapes_apart() -> Result<(), Box<dyn Error>> {
+        // a cup crossing the line between two tiles with its sides, and a square with
+        // a hole below the line
+        let cup = close_ring(
+            vec![
+                Vert::new(10., 50.),
+                Vert::new(90., 50.),
+                Vert::new(90., 150.),
+                Vert::new(70., 150.),
+                Vert::new(70., 70.),
+                Vert::new(30., 70.),
+                Vert::new(30., 150.),
+                Vert::new(10., 150.),
+            ],
+            false,
+        );
+        let nodes = sort_edges(vec![
+            cup,
+            rectangle(120., 70., 60., 60., false),
+            rectangle(140., 75., 20., 15., true),
+        ])?;
+        let area = |nodes: &[Node]| -> f64 {
+            let ring = |e: &Edge| signed_area(&e.iter().cloned().collect::<Vec<Vert>>()).abs();
+            nodes
+                .iter()
+                .map(|n| ring(&n.data) - n.children.iter().map(|c| ring(&c.data)).sum::<f64>())
+                .sum::<f64>()
+                / 2.
+        };
+
+        let top = tile_relief(&nodes, &Rect::new(0., 100., 200., 200.), &[], &[])?;
+        let bottom = tile_relief(&nodes, &Rect::new(0., 0., 200., 100.), &[], &[])?;
+        // the sides of the cup come apart instead of being bridged along the cut
+        assert_eq!(top.len(), 3);
+        assert!(top.iter().all(|n| n.children.is_empty()));
+        assert_eq!(bottom.len(), 2);
+        assert_eq!(bottom.iter().map(|n| n.children.len()).sum::<usize>(), 1);
+        // nothing overlaps and nothing is lost
+        assert!((area(&top) + area(&bottom) - area(&nodes)).abs() < 1e-6);
+        assert!((area(&union(&top, &[])?) - area(&top)).abs() < 1e-6);
+        for pieces in [top, bottom] {
+            assert!(pieces
+                .iter()
+                .all(|n| n.data.is_closed() && !n.data.is_hole()));
+            let tris = extrude_nodes_with_profile(pieces, 1., EdgeProfile::Vertical)?;
+            assert!(is_watertight(&tris));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_tile_relief_leaves_keep_out_areas() -> Result<(), Box<dyn Error>> {
+        let nodes = sort_edges(vec![rectangle(0., 0., 10., 10., false)])?;
+        let pieces = tile_relief(
+            &nodes,
+            &Rect::new(0., 0., 10., 10.),
+            &[Rect::new(10., 4., 11., 6.)],
+            &[Rect::new(-1., 4., 2., 6.)],
+        )?;
+        assert_eq!(pieces.len(), 1);
+        // the tab sticks out past the graphic, so it adds nothing
+        let points: Vec<Vert> = pieces[0].data.iter().cloned().collect();
+        assert_eq!(signed_area(&points).abs() / 2., 100. - 4.);
+        Ok(())
+    }
+
+    #[test]
+    fn test_plate_outline_with_keys() {
+        let r = Rect::new(0., 0., 10., 10.);
+        let joint = Joint::Keys {
+            width: 2.,
+            depth: 1.,
+            clearance: 0.,
+        };
+        let (tabs, notches) = keys(&r, true, true, true, true, &joint);
+        let plate = plate_outline(&r, &tabs, &notches);
+        assert_eq!(plate.len(), 4 + 4 * 4 + 1);
+        let points: Vec<Vert> = plate.iter().cloned().collect();
+        // two tabs added and two notches taken away, signed area is twice the area
+        assert_eq!(signed_area(&points[..points.len() - 1]), 200.);
+    }
+
+    #[test]
+    fn test_tile_grid() -> Result<(), Box<dyn Error>> {
+        let nodes = sort_edges(vec![rectangle(0., 0., 250., 100., false)])?;
+        let options = TileOptions {
+            joint: Joint::Butt,
+            labels: false,
+            ..Default::default()
+        };
+        let tiled = tile_nodes(&nodes, 250., 100., 1., &options)?;
+        assert_eq!((tiled.columns, tiled.rows), (2, 1));
+        assert_eq!(tiled.tiles[1].label, "a2");
+        assert_eq!(tiled.tiles[1].x, 150.);
+        assert_eq!(tiled.tiles[1].width, 100.);
+        assert_eq!(max_xy(&tiled.tiles[1].triangles), (100., 100.));
+        // the relief and the plate under it are one solid
+        assert!(tiled.tiles.iter().all(|t| is_watertight(&t.triangles)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_overlap() -> Result<(), Box<dyn Error>> {
+        let options = TileOptions {
+            tile_width: 100.,
+            tile_height: 100.,
+            joint: Joint::Overlap(10.),
+            ..Default::default()
+        };
+        let tiled = tile_nodes(&[], 190., 50., 1., &options)?;
+        assert_eq!(tiled.columns, 2);
+        assert_eq!(tiled.tiles[1].x, 90.);
+        let options = TileOptions {
+            joint: Joint::Overlap(100.),
+            ..options
+        };
+        assert!(matches!(
+            tile_nodes(&[], 190., 50., 1., &options),
+            Err(ConversionError::InvalidOptions(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_to_tiles_manifest() -> Result<(), Box<dyn Error>> {
+        let img = image_from_file("./images/stick-figure.png")?;
+        let options = TileOptions {
+            tile_width: 40.,
+            tile_height: 40.,
+            ..Default::default()
+        };
+        let tiled = image_to_tiles(
+            img,
+            DEFAULT_THRESHOLD_VALUE,
+            2.,
+            1.,
+            &StlOptions::default(),
+            &options,
+        )?;
+        assert!(tiled.tiles.len() > 1);
+        let manifest = tiled.manifest("out/map");
+        assert_eq!(manifest["tiles"][0]["file"], "map-a1.stl");
+        assert_eq!(manifest["tiles"][0]["label"], "a1");
+        assert_eq!(manifest["columns"], tiled.columns);
+        for tile in tiled.tiles.iter() {
+            assert!(!tile.triangles.is_empty());
+            assert_eq!(tile.triangles.len() % 9, 0);
+        }
+        Ok(())
+    }
+}
@@ -1,38 +1,24 @@
 use log::info;
+use rstar::primitives::{GeomWithData, Rectangle};
+use rstar::RTree;
 
-use crate::edge::{edge_contains, hole_contains, Edge};
+use crate::edge::{try_is_hole, winding_number, Edge};
+use crate::error::ConversionError;
+use crate::shapes::signed_area;
+use crate::vert::Vert;
 
 #[derive(Debug)]
 pub struct Tree {
     children: Vec<Node>,
 }
 
-/// insert new_node into the tree
-fn insert(nodes: &mut Vec<Node>, mut new_node: Node) {
-    // get children of the new data
-    let data_children: Vec<Node> = nodes
-        .extract_if(|child| child.is_child_of(&new_node))
-        .collect();
-
-    // insert those children as children of the new data
-    for data_child in data_children {
-        new_node.insert(data_child);
-    }
-
-    nodes.push(new_node);
-    //// previously here we would handle the case where nodes here were parents of the current node.
-    //// but if we insert nodes in the order that they were created, this is never the case
-
-    //// find the parent of the new data
-    //let some_parent_index = nodes.iter().position(|child| child._is_parent_of(&new_node));
-
-    //// insert the new data into its parent
-    //if let Some(index) = some_parent_index {
-    //    nodes[index].insert(new_node);
-    //} else {
-    //    // or insert it as a sibling
-    //    nodes.push(new_node);
-    //}
+/// Whether the inner edge is inside the outer one. Traced edges can touch without crossing,
+/// so the first point of the inner edge that is not on the outer edge decides.
+pub(crate) fn encloses(outer: &Edge, inner: &Edge) -> bool {
+    inner
+        .iter()
+        .find_map(|v| winding_number(outer, v))
+        .map_or(false, |w| w != 0)
 }
 
 /// flatten_nodes to be passed to earcuttr
@@ -60,13 +46,9 @@ fn flatten_nodes(mut t: Tree) -> Vec<Node> {
 }
 
 impl Tree {
-    fn new() -> Tree {
-        Tree { children: vec![] }
-    }
-
-    fn insert(&mut self, data: Edge) {
-        let new_node = Node::new(data);
-        insert(&mut self.children, new_node);
+    /// the outermost shapes, with their holes, islands and so on nested inside
+    pub fn into_roots(self) -> Vec<Node> {
+        self.children
     }
 }
 
@@ -85,25 +67,6 @@ impl Node {
             children: vec![],
         }
     }
-    fn is_child_of(&self, other: &Node) -> bool {
-        if other.data.is_hole() {
-            hole_contains(&other.data, &self.data[0])
-        } else {
-            edge_contains(&other.data, &self.data[0])
-        }
-    }
-    fn _is_parent_of(&self, other: &Node) -> bool {
-        if self.data.is_hole() {
-            hole_contains(&self.data, &other.data[0])
-        } else {
-            edge_contains(&self.data, &other.data[0])
-        }
-    }
-
-    fn insert(&mut self, new_node: Node) {
-        insert(&mut self.children, new_node);
-    }
-
     pub fn to_geojson_polygon_arr(self) -> Vec<Vec<[f64; 2]>> {
         let mut out = vec![self.data.into()];
         for c in self.children {
@@ -113,29 +76,79 @@ impl Node {
     }
 }
 
-pub fn tree_from_edges(edges: Vec<Edge>) -> Tree {
-    let mut t = Tree::new();
-    let l = edges.len();
-    for (i, e) in edges.into_iter().enumerate() {
-        info!("inserting item {i}/{l}");
-        t.insert(e);
+/// Nest closed edges inside each other, each one in the smallest edge around it.
+/// The bounding boxes go in an r-tree, so each edge is only checked against the edges whose
+/// boxes cover it instead of every other edge.
+/// Fails when an edge does not close around an area, since it can not be put in the tree.
+pub fn tree_from_edges(edges: Vec<Edge>) -> Result<Tree, ConversionError> {
+    let mut areas = Vec::with_capacity(edges.len());
+    for e in edges.iter() {
+        try_is_hole(e)?;
+        let points: Vec<Vert> = e.iter().cloned().collect();
+        areas.push(signed_area(&points).abs());
     }
-    t
+    let boxes = RTree::bulk_load(
+        edges
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| {
+                let (lower, upper) = e.bounding_box()?;
+                Some(GeomWithData::new(Rectangle::from_corners(lower, upper), i))
+            })
+            .collect(),
+    );
+
+    let mut children: Vec<Vec<usize>> = vec![vec![]; edges.len()];
+    let mut roots = vec![];
+    for (i, e) in edges.iter().enumerate() {
+        // every edge around this one has a box around its first point and a bigger area
+        let mut around: Vec<usize> = boxes
+            .locate_all_at_point(&[e[0].x, e[0].y])
+            .map(|b| b.data)
+            .filter(|&j| areas[j] > areas[i])
+            .collect();
+        around.sort_by(|&a, &b| areas[a].total_cmp(&areas[b]));
+        match around.into_iter().find(|&j| encloses(&edges[j], e)) {
+            Some(parent) => children[parent].push(i),
+            None => roots.push(i),
+        }
+    }
+    info!("nested {} edges in {} outermost edges", edges.len(), roots.len());
+
+    let mut edges: Vec<Option<Edge>> = edges.into_iter().map(Some).collect();
+    let children = roots
+        .into_iter()
+        .map(|i| build_node(i, &mut edges, &children))
+        .collect();
+    Ok(Tree { children })
+}
+
+fn build_node(i: usize, edges: &mut [Option<Edge>], children: &[Vec<usize>]) -> Node {
+    let mut node = Node::new(edges[i].take().unwrap());
+    node.children = children[i]
+        .iter()
+        .map(|&c| build_node(c, edges, children))
+        .collect();
+    node
 }
 
-pub fn sort_edges(edges: Vec<Edge>) -> Vec<Node> {
-    let t = tree_from_edges(edges);
-    flatten_nodes(t)
+/// sort closed edges into polygons with their holes, see `tree_from_edges`
+pub fn sort_edges(edges: Vec<Edge>) -> Result<Vec<Node>, ConversionError> {
+    Ok(flatten_nodes(tree_from_edges(edges)?))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::util::testing::{closed_edge, donut};
+    use crate::vert::Vert;
     use std::error::Error;
     use crate::edge_file;
     use crate::components::constants::DEFAULT_THRESHOLD_VALUE;
 
+    extern crate test;
+    use test::Bencher;
+
     static SIDE: f32 = 100.;
     static X0: f32 = 0.;
     static Y0: f32 = 0.;
@@ -143,31 +156,83 @@ mod tests {
     #[test]
     fn test_tree() -> Result<(), Box<dyn Error>> {
         let edges = edge_file(DEFAULT_THRESHOLD_VALUE, "./images/stick-figure.png")?;
-        let t = tree_from_edges(edges.closed_edges);
+        let t = tree_from_edges(edges.closed_edges)?;
         assert_eq!(t.children.len(), 1);
         assert_eq!(t.children[0].children.len(), 4);
         Ok(())
     }
 
+    /// the way the tree used to be built, checking each new edge against all the others
+    fn insert_by_scanning(nodes: &mut Vec<Node>, mut new_node: Node) {
+        let inside: Vec<Node> = nodes
+            .extract_if(|child| encloses(&new_node.data, &child.data))
+            .collect();
+        for child in inside {
+            insert_by_scanning(&mut new_node.children, child);
+        }
+        nodes.push(new_node);
+    }
+
+    fn moontower_edges() -> Result<Vec<Edge>, Box<dyn Error>> {
+        Ok(edge_file(DEFAULT_THRESHOLD_VALUE, "./images/moontower.png")?.closed_edges)
+    }
+
     #[test]
-    fn test_insert() {
-        let outer = Node::new(closed_edge(SIDE, X0, Y0, false));
-        let inner = Node::new(closed_edge(SIDE / 2., X0 + SIDE / 4., Y0 + SIDE / 4., true));
-        assert_eq!(inner.is_child_of(&outer), true, "inner is child of outer");
-        assert_eq!(outer._is_parent_of(&inner), true, "outer is parent of inner");
-
-        let mut nodes = vec![];
-        insert(&mut nodes, inner);
-        assert_eq!(nodes.len(), 1);
-        insert(&mut nodes, outer);
-        assert_eq!(nodes.len(), 1);
-        assert_eq!(nodes[0].children.len(), 1);
+    fn test_insert() -> Result<(), Box<dyn Error>> {
+        let outer = closed_edge(SIDE, X0, Y0, false);
+        let inner = closed_edge(SIDE / 2., X0 + SIDE / 4., Y0 + SIDE / 4., true);
+        assert!(encloses(&outer, &inner), "inner is child of outer");
+        assert!(!encloses(&inner, &outer), "outer is not child of inner");
+
+        // the order the edges come in does not matter
+        for edges in [
+            vec![inner.clone(), outer.clone()],
+            vec![outer.clone(), inner.clone()],
+        ] {
+            let t = tree_from_edges(edges)?;
+            assert_eq!(t.children.len(), 1);
+            assert_eq!(t.children[0].data, outer);
+            assert_eq!(t.children[0].children[0].data, inner);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_tree_as_scanning() -> Result<(), Box<dyn Error>> {
+        let edges = moontower_edges()?;
+        let mut scanned = vec![];
+        for e in edges.iter() {
+            insert_by_scanning(&mut scanned, Node::new(e.clone()));
+        }
+        let t = tree_from_edges(edges)?;
+        assert_eq!(t.children, scanned);
+        Ok(())
+    }
+
+    #[bench]
+    fn bench_tree_moontower(b: &mut Bencher) -> Result<(), Box<dyn Error>> {
+        let edges = moontower_edges()?;
+        b.iter(|| tree_from_edges(edges.clone()).unwrap());
+        Ok(())
+    }
+
+    #[bench]
+    fn bench_tree_moontower_by_scanning(b: &mut Bencher) -> Result<(), Box<dyn Error>> {
+        let edges = moontower_edges()?;
+        b.iter(|| {
+            let mut nodes = vec![];
+            for e in edges.iter() {
+                insert_by_scanning(&mut nodes, Node::new(e.clone()));
+            }
+            nodes
+        });
+        Ok(())
     }
 
     #[test]
     fn test_sort_edges() -> Result<(), Box<dyn Error>> {
         let d = donut(100., 1., 0., 0.);
-        let nodes = sort_edges(d.clone());
+        let nodes = sort_edges(d.clone())?;
         assert_eq!(nodes[0].data, d[1]);
         assert_eq!(nodes[0].children[0].data, d[0]);
 
@@ -177,19 +242,47 @@ mod tests {
     #[test]
     fn test_sort_edges_stick_figure() -> Result<(), Box<dyn Error>> {
         let edges = edge_file(DEFAULT_THRESHOLD_VALUE, "./images/stick-figure.png")?;
-        let _sorted = sort_edges(edges.closed_edges);
+        let _sorted = sort_edges(edges.closed_edges)?;
         //assert_eq!(sorted.len(), 5, "Should equal the total number of shapes");
         Ok(())
     }
 
     #[test]
-    fn test_sort_edges_donut_within_donut() {
+    fn test_sort_edges_donut_within_donut() -> Result<(), Box<dyn Error>> {
         let mut d = donut(50., 1., 10., 10.);
         d.append(&mut donut(100., 1., 0., 0.));
-        let nodes = sort_edges(d.clone());
+        let nodes = sort_edges(d.clone())?;
         println!("{nodes:#?}");
         assert_eq!(nodes.len(), 2, "should be two donuts");
         assert_eq!(nodes[0].data, d[3]);
         assert_eq!(nodes[0].children[0].data, d[2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_touching_hole() -> Result<(), Box<dyn Error>> {
+        // a hole that starts on a corner of its outline
+        let outline = closed_edge(10., 0., 0., false);
+        let mut hole = Edge::from(vec![
+            Vert::new(0., 0.),
+            Vert::new(5., 2.),
+            Vert::new(2., 5.),
+            Vert::new(0., 0.),
+        ]);
+        hole.verts.make_contiguous().reverse();
+        // inner edges come before the edges around them, like they are traced
+        let nodes = sort_edges(vec![hole, outline])?;
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].children.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_edges_are_an_error() {
+        let open = Edge::from([Vert::new(0., 0.), Vert::new(1., 0.), Vert::new(1., 1.)]);
+        assert!(matches!(
+            sort_edges(vec![open]),
+            Err(ConversionError::DegenerateContour(_))
+        ));
     }
 }
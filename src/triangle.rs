@@ -1,17 +1,33 @@
 use log::info;
-use std::error::Error;
 
 use crate::{
     earcut::{earcut, flatten},
+    edge::Edge,
+    error::ConversionError,
+    engrave::{engrave_triangles, EngraveOptions},
     edge_img,
-    ribbon::edge_to_ribbon,
-    stl::write_stl_3d,
+    frame::{border_edges, clear_border, BorderOptions},
+    lattice::check_traceable,
+    hollow::{hollow_triangles, HollowOptions},
+    mold::{mold_triangles, MoldOptions},
+    palette::{textured_layers, Layer, Palette},
+    ribbon::{inset_edge, polygon_to_profiled_ribbon, EdgeProfile},
+    shapes::signed_area,
+    skeleton::line_art_edges,
+    stl::write_stl_stream,
+    stream::image_to_triangle_stream,
     threshold_img,
-    tree::sort_edges,
+    tree::{sort_edges, Node},
+    vert::Vert,
     components::constants::DEFAULT_THRESHOLD_VALUE,
 };
 
 use image::{io::Reader as ImageReader, DynamicImage};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use rstar::{RTree, AABB};
+use std::fs::File;
+use std::io::BufWriter;
 
 fn to_3d(tris: &Vec<f64>, z: f64) -> Vec<f64> {
     let d2 = 2;
@@ -29,6 +45,10 @@ fn scale(v: Vec<f64>, factor: f64) -> impl Iterator<Item = f64> {
     v.into_iter().map(move |x| x * factor)
 }
 
+/// Triangles handed out a batch at a time, like `write_stl_stream` takes them.
+/// An error ends the batches.
+pub type TriangleBatches = Box<dyn Iterator<Item = Result<Vec<f64>, ConversionError>>>;
+
 /// copy triangles, but flipped over and moved to z
 fn flip_triangles(tris: &[f64], new_z: f64) -> Vec<f64> {
     let dims = 3;
@@ -57,47 +77,8 @@ fn flip_triangles(tris: &[f64], new_z: f64) -> Vec<f64> {
         .collect()
 }
 
-/*
- * TODO
- * how to sort edges in a streaming way:
- * once an exterior edge is closed, the holes will
- * already have been completed. So we can construct its "polygon array"
- * each time an edge is closed insert into sort tree
- * if the edge is exterior, insert it, then remove it with its children
- * construct this polygon arr with this
- * then proceed to flatten and earcut it
- */
-
-/// Given an image create an array of triangles representing an stl
-/// The edges of the shape are determined by thresholding with the given value.
-/// The provided height of the shape, , in stl units (usually millimeters)
-/// The length & width of resulting stl are the pixel-heght & pixel-width
-/// of the input image, multiplied by the provided scale.
-pub fn image_to_stl(
-    mut img: DynamicImage,
-    threshold: u8,
-    height: f64,
-    scale_factor: f64,
-) -> Result<impl Iterator<Item = f64>, Box<dyn Error>> {
-    //let mut img = img.to_luma8();
-
-    //threshold_grey_image(&mut img, threshold);
-    threshold_img(&mut img, threshold);
-    info!("thresholded image for stl");
-
-    let edges = edge_img(&img)?;
-    info!("created edges for stl");
-
-    let ribbon_tris: Vec<f64> = edges
-        .closed_edges
-        .iter()
-        .flat_map(|edge| edge_to_ribbon(edge, height))
-        .collect();
-
-    let nodes = sort_edges(edges.closed_edges);
-
-    info!("sorted edges for stl");
-
+/// earcut the polygons, returning flat 2D triangles
+pub fn nodes_to_triangles(nodes: Vec<Node>) -> Result<Vec<f64>, ConversionError> {
     // simplify this step
     let polygon_arr: Vec<Vec<Vec<Vec<f64>>>> = nodes
         .into_iter()
@@ -116,8 +97,11 @@ pub fn image_to_stl(
 
     info!("got earcut args for stl");
 
-    let triangle_indexes: Vec<Vec<usize>> = earcut_args_arr
-        .iter()
+    #[cfg(feature = "parallel")]
+    let polygons = earcut_args_arr.par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let polygons = earcut_args_arr.iter();
+    let triangle_indexes: Vec<Vec<usize>> = polygons
         .map(|(verts, holes, _dims)| {
             earcut(verts, holes, 2)
         })
@@ -125,29 +109,150 @@ pub fn image_to_stl(
 
     info!("earcut into triangles for stl");
 
+    // earcut gives up without a word, so check every polygon with an area left over after
+    // its holes got some triangles
+    for (indexes, polygon) in triangle_indexes.iter().zip(polygon_arr.iter()) {
+        let rings: Vec<Vec<Vert>> = polygon
+            .iter()
+            .map(|ring| ring.iter().map(|c| Vert::new(c[0], c[1])).collect())
+            .collect();
+        let holes: f64 = rings[1..].iter().map(|r| signed_area(r).abs()).sum();
+        if indexes.is_empty() && signed_area(&rings[0]).abs() - holes > 1e-9 {
+            return Err(ConversionError::Triangulation(format!(
+                "the polygon starting at {:?} with {} holes gave no triangles",
+                rings[0][0],
+                rings.len() - 1
+            )));
+        }
+    }
+
     let triangles: Vec<f64> = triangle_indexes
         .iter()
         .zip(earcut_args_arr)
-        .flat_map(|(indexes, ear_args)| {
-            let verts = ear_args.0;
-            indexes
-                .iter()
-                .map(|i| {
-                    let start = i * 2;
-                    verts[start..(start + 2)].to_vec()
-                })
-                .collect::<Vec<Vec<f64>>>()
-        })
-        .flatten()
+        .flat_map(|(indexes, ear_args)| restore_dropped(&ear_args.0, indexes))
         .collect();
 
     info!("created flat tris for stl");
+    Ok(triangles)
+}
 
-    let tri_3d = to_3d(&triangles, height);
+/// Earcut leaves out corners on a straight line between their neighbours, so a wall along
+/// that side would meet the face in the middle of a triangle side. Split the triangles
+/// with such a corner on a side around their middle, so the face has every corner again.
+fn restore_dropped(verts: &[f64], indexes: &[usize]) -> Vec<f64> {
+    let vert = |i: usize| Vert::new(verts[2 * i], verts[2 * i + 1]);
+    let points: RTree<[f64; 2]> = RTree::bulk_load(verts.chunks(2).map(|c| [c[0], c[1]]).collect());
+
+    // where along a to b the point is, if it is on the side between them
+    let along = |a: &Vert, b: &Vert, p: &Vert| {
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        let length = dx * dx + dy * dy;
+        let cross = dx * (p.y - a.y) - dy * (p.x - a.x);
+        let t = (dx * (p.x - a.x) + dy * (p.y - a.y)) / length;
+        (cross.abs() <= 1e-9 * length && t > 0. && t < 1.).then_some(t)
+    };
+
+    indexes
+        .chunks(3)
+        .flat_map(|t| {
+            let corners: Vec<Vert> = t.iter().map(|&i| vert(i)).collect();
+            let mut ring = vec![];
+            for k in 0..3 {
+                let (a, b) = (&corners[k], &corners[(k + 1) % 3]);
+                let envelope =
+                    AABB::from_corners([a.x.min(b.x), a.y.min(b.y)], [a.x.max(b.x), a.y.max(b.y)]);
+                let mut on_side: Vec<(f64, Vert)> = points
+                    .locate_in_envelope(&envelope)
+                    .map(|c| Vert::new(c[0], c[1]))
+                    .filter_map(|p| along(a, b, &p).map(|t| (t, p)))
+                    .collect();
+                on_side.sort_by(|x, y| x.0.total_cmp(&y.0));
+                on_side.dedup_by(|x, y| x.0 == y.0);
+                ring.push(a.clone());
+                ring.extend(on_side.into_iter().map(|(_, p)| p));
+            }
+            if ring.len() == 3 {
+                return ring.iter().flat_map(|v| [v.x, v.y]).collect::<Vec<f64>>();
+            }
+            let middle = Vert::new(
+                corners.iter().map(|v| v.x).sum::<f64>() / 3.,
+                corners.iter().map(|v| v.y).sum::<f64>() / 3.,
+            );
+            ring.iter()
+                .zip(ring.iter().cycle().skip(1))
+                .flat_map(|(a, b)| [middle.x, middle.y, a.x, a.y, b.x, b.y])
+                .collect()
+        })
+        .collect()
+}
 
-    info!("made tris 3D");
-    let bottom_triangles: Vec<f64> = flip_triangles(&tri_3d, 0.);
-    info!("made bottom tris ");
+/// move the outline and holes of a polygon towards its filled side
+pub(crate) fn inset_node(node: &Node, distance: f64) -> Node {
+    Node {
+        data: inset_edge(&node.data, distance),
+        children: node
+            .children
+            .iter()
+            .map(|child| Node {
+                data: inset_edge(&child.data, distance),
+                children: vec![],
+            })
+            .collect(),
+    }
+}
+
+/// Extrude closed edges into a solid with the given height.
+/// The result is a flat array of 3D triangles sitting on z = 0.
+pub fn extrude_edges(closed_edges: Vec<Edge>, height: f64) -> Result<Vec<f64>, ConversionError> {
+    extrude_edges_with_profile(closed_edges, height, EdgeProfile::Vertical)
+}
+
+/// Extrude closed edges into a solid with the given height, with walls shaped like the profile.
+/// The top face is inset to meet the top of the walls, so the mesh stays closed.
+pub fn extrude_edges_with_profile(
+    closed_edges: Vec<Edge>,
+    height: f64,
+    profile: EdgeProfile,
+) -> Result<Vec<f64>, ConversionError> {
+    let nodes = sort_edges(closed_edges)?;
+
+    info!("sorted edges for stl");
+    extrude_nodes_with_profile(nodes, height, profile)
+}
+
+/// Like `extrude_edges_with_profile`, for edges that are already sorted into polygons
+pub fn extrude_nodes_with_profile(
+    nodes: Vec<Node>,
+    height: f64,
+    profile: EdgeProfile,
+) -> Result<Vec<f64>, ConversionError> {
+    // each polygon is inset as far as it can take, so the top of each can be inset differently
+    let (ribbons, top_insets): (Vec<Vec<f64>>, Vec<f64>) = nodes
+        .iter()
+        .map(|node| {
+            let rings: Vec<&Edge> = std::iter::once(&node.data)
+                .chain(node.children.iter().map(|c| &c.data))
+                .collect();
+            polygon_to_profiled_ribbon(&rings, height, profile)
+        })
+        .unzip();
+    let ribbon_tris = ribbons.concat();
+
+    let (tri_3d, bottom_triangles) = if profile.top_inset(height) == 0. {
+        let tri_3d = to_3d(&nodes_to_triangles(nodes)?, height);
+        let bottom_triangles = flip_triangles(&tri_3d, 0.);
+        (tri_3d, bottom_triangles)
+    } else {
+        let top_nodes = nodes
+            .iter()
+            .zip(top_insets)
+            .map(|(n, top_inset)| inset_node(n, top_inset))
+            .collect();
+        let tri_3d = to_3d(&nodes_to_triangles(top_nodes)?, height);
+        let bottom_triangles = flip_triangles(&to_3d(&nodes_to_triangles(nodes)?, 0.), 0.);
+        (tri_3d, bottom_triangles)
+    };
+    info!("made top and bottom tris");
 
     let all_tris = ribbon_tris
         .into_iter()
@@ -155,8 +260,185 @@ pub fn image_to_stl(
         .chain(bottom_triangles.into_iter())
         .collect();
     info!("combined all triangles");
+    Ok(all_tris)
+}
 
-    Ok(scale(all_tris, scale_factor))
+/// move every corner of a flat array of 3D triangles
+pub fn translate_triangles(tris: &mut [f64], x: f64, y: f64, z: f64) {
+    for v in tris.chunks_mut(3) {
+        v[0] += x;
+        v[1] += y;
+        v[2] += z;
+    }
+}
+
+/// Ways to change how an image is turned into an stl.
+/// The sizes are in the same units as the height.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct StlOptions {
+    /// the shape of the walls of the shapes
+    pub profile: EdgeProfile,
+    /// For line drawings. Instead of following the outline of the ink, the centerlines of
+    /// the lines are found and redrawn with this width, so thin and thick lines come out
+    /// the same. See `line_art_edges`
+    pub line_width: Option<f64>,
+    /// a frame and orientation marker added around the image
+    pub border: Option<BorderOptions>,
+    /// make a thermoform master instead of the plain relief, see `mold_triangles`
+    pub mold: Option<MoldOptions>,
+    /// cut the shapes into a plate as deep as the height, instead of raising them.
+    /// Can not be used together with a mold
+    pub engrave: Option<EngraveOptions>,
+    /// raise only walls along the edges of the shapes, see `hollow_triangles`.
+    /// Molds and engraved plates are made from the whole shapes.
+    pub hollow: Option<HollowOptions>,
+}
+
+impl StlOptions {
+    /// check that the options can be used together
+    pub fn check(&self) -> Result<(), ConversionError> {
+        if self.mold.is_some() && self.engrave.is_some() {
+            return Err(ConversionError::InvalidOptions(
+                "a mold and an engraved plate can not be made at once".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Given an image create an array of triangles representing an stl
+/// The edges of the shape are determined by thresholding with the given value.
+/// The provided height of the shape, , in stl units (usually millimeters)
+/// The length & width of resulting stl are the pixel-heght & pixel-width
+/// of the input image, multiplied by the provided scale.
+pub fn image_to_stl(
+    img: DynamicImage,
+    threshold: u8,
+    height: f64,
+    scale_factor: f64,
+) -> Result<TriangleBatches, ConversionError> {
+    image_to_stl_with_options(img, threshold, height, scale_factor, &StlOptions::default())
+}
+
+/// Like `image_to_stl`, with the extra options. Plain shapes are streamed as they are
+/// traced, the other options make all their triangles in one batch.
+pub fn image_to_stl_with_options(
+    img: DynamicImage,
+    threshold: u8,
+    height: f64,
+    scale_factor: f64,
+    options: &StlOptions,
+) -> Result<TriangleBatches, ConversionError> {
+    check_traceable(img.width(), img.height())?;
+    options.check()?;
+    let plain = options.mold.is_none() && options.engrave.is_none();
+    if plain && options.line_width.is_none() && options.hollow.is_none() {
+        #[cfg(feature = "parallel")]
+        let tris = crate::parallel::image_to_triangles(
+            img,
+            threshold,
+            height,
+            scale_factor,
+            options.profile,
+            options.border.as_ref(),
+        )?;
+        #[cfg(feature = "parallel")]
+        return Ok(Box::new(std::iter::once(Ok(tris))));
+        #[cfg(not(feature = "parallel"))]
+        return Ok(Box::new(image_to_triangle_stream(
+            img,
+            threshold,
+            height,
+            scale_factor,
+            options.profile,
+            options.border.as_ref(),
+        )?));
+    }
+    let (width, image_height) = ((img.width() - 1) as f64, (img.height() - 1) as f64);
+    let (pixel_width, pixel_height) = (img.width(), img.height());
+    let mut edges = image_to_edges(img, threshold, options)?;
+    let tris = if let (true, Some(hollow)) = (plain, &options.hollow) {
+        let border = split_border(&mut edges, pixel_width, pixel_height, options);
+        let mut tris = hollow_triangles(&sort_edges(edges)?, height, options.profile, hollow)?;
+        tris.append(&mut extrude_edges_with_profile(
+            border,
+            height,
+            options.profile,
+        )?);
+        tris
+    } else if let Some(mold) = &options.mold {
+        mold_triangles(edges, width, image_height, height, mold)?
+    } else if let Some(engrave) = &options.engrave {
+        engrave_triangles(edges, width, image_height, height, options.profile, engrave)?
+    } else {
+        extrude_edges_with_profile(edges, height, options.profile)?
+    };
+    Ok(Box::new(std::iter::once(Ok(scale(tris, scale_factor).collect()))))
+}
+
+/// threshold and trace an image, adding the border from the options
+pub fn image_to_edges(
+    mut img: DynamicImage,
+    threshold: u8,
+    options: &StlOptions,
+) -> Result<Vec<Edge>, ConversionError> {
+    check_traceable(img.width(), img.height())?;
+    //let mut img = img.to_luma8();
+
+    //threshold_grey_image(&mut img, threshold);
+    threshold_img(&mut img, threshold);
+    info!("thresholded image for stl");
+
+    if let Some(border) = &options.border {
+        clear_border(&mut img, border);
+    }
+
+    let mut edges = if let Some(line_width) = options.line_width {
+        line_art_edges(&img, line_width)?
+    } else {
+        edge_img(&img)?.closed_edges
+    };
+    info!("created edges for stl");
+
+    // the border goes after the image, so it is inserted into the tree after the shapes it surrounds
+    if let Some(border) = &options.border {
+        edges.append(&mut border_edges(img.width(), img.height(), border));
+    }
+    Ok(edges)
+}
+
+/// take the border that `image_to_edges` adds after the traced shapes off the end of the edges
+pub(crate) fn split_border(
+    edges: &mut Vec<Edge>,
+    width: u32,
+    height: u32,
+    options: &StlOptions,
+) -> Vec<Edge> {
+    let n_border = options
+        .border
+        .map_or(0, |b| border_edges(width, height, &b).len());
+    edges.split_off(edges.len() - n_border)
+}
+
+/// Given an image and a palette, create one array of triangles per colour class.
+/// Each class is traced separately and extruded with its layer's height and texture.
+/// Classes without a layer, like the background, are skipped.
+/// The meshes are kept apart so they can be exported as separate objects,
+/// chain them together to get a single stl.
+pub fn palette_image_to_stl(
+    img: &DynamicImage,
+    palette: &Palette,
+    layers: &[Option<Layer>],
+    scale_factor: f64,
+) -> Result<Vec<Vec<f64>>, ConversionError> {
+    textured_layers(img, palette, layers)
+        .into_iter()
+        .map(|(layer, mask)| {
+            let edges = edge_img(&mask)?;
+            info!("created edges for layer with height {}", layer.height);
+            Ok(scale(extrude_edges(edges.closed_edges, layer.height)?, scale_factor).collect())
+        })
+        .collect()
 }
 
 // add height, scale, and threshold arguments
@@ -165,13 +447,33 @@ pub fn image_file_to_stl(
     height: f64,
     scale_factor: f64,
     output_filename: &str,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<(), ConversionError> {
     let img = ImageReader::open(input_filename)?
         .with_guessed_format()?
         .decode()?;
 
-    let tris = image_to_stl(img, DEFAULT_THRESHOLD_VALUE, height, scale_factor)?;
-    write_stl_3d(output_filename, tris.collect::<Vec<f64>>())?;
+    let mut stream = image_to_triangle_stream(
+        img,
+        DEFAULT_THRESHOLD_VALUE,
+        height,
+        scale_factor,
+        EdgeProfile::Vertical,
+        None,
+    )?;
+    let mut writer = BufWriter::new(File::create(output_filename)?);
+    let mut reported = 0.;
+    let count = write_stl_stream(
+        &mut writer,
+        std::iter::from_fn(|| {
+            let tris = stream.next()?;
+            if stream.progress() >= reported + 0.1 {
+                reported = stream.progress();
+                info!("traced {:.0}% of {}", reported * 100., input_filename);
+            }
+            Some(tris)
+        }),
+    )?;
+    info!("wrote {} triangles to {}", count, output_filename);
     Ok(())
 }
 
@@ -180,6 +482,7 @@ mod tests {
     use test::Bencher;
 
     use super::*;
+    use crate::mold::MoldKind;
     use std::error::Error;
     extern crate test;
 
@@ -200,7 +503,7 @@ mod tests {
                 SOME_SCALE_FACTOR,
             )
             .unwrap()
-            .collect::<Vec<f64>>();
+            .collect::<Result<Vec<Vec<f64>>, _>>();
         });
         Ok(())
     }
@@ -219,11 +522,210 @@ mod tests {
                 SOME_SCALE_FACTOR,
             )
             .unwrap()
-            .collect::<Vec<f64>>();
+            .collect::<Result<Vec<Vec<f64>>, _>>();
         });
         Ok(())
     }
 
+    #[test]
+    fn test_mold_and_engrave_together() {
+        let options = StlOptions {
+            mold: Some(MoldOptions::default()),
+            engrave: Some(EngraveOptions::default()),
+            ..Default::default()
+        };
+        let img = DynamicImage::new_luma8(5, 5);
+        let res = image_to_stl_with_options(img, 100, SOME_HEIGHT, SOME_SCALE_FACTOR, &options);
+        assert!(matches!(res, Err(ConversionError::InvalidOptions(_))));
+    }
+
+    #[test]
+    fn test_image_too_small_with_options() {
+        let options = StlOptions {
+            hollow: Some(HollowOptions::default()),
+            ..Default::default()
+        };
+        let img = DynamicImage::new_luma8(1, 5);
+        let res = image_to_stl_with_options(img, 100, SOME_HEIGHT, SOME_SCALE_FACTOR, &options);
+        assert!(matches!(res, Err(ConversionError::Image(_))));
+    }
+
+    #[test]
+    fn test_palette_image_to_stl() -> Result<(), Box<dyn Error>> {
+        use image::{Rgb, RgbImage};
+
+        let red = Rgb([255, 0, 0]);
+        let white = Rgb([255, 255, 255]);
+        let img: DynamicImage = RgbImage::from_fn(20, 20, |x, y| {
+            if (5..15).contains(&x) && (5..15).contains(&y) {
+                red
+            } else {
+                white
+            }
+        })
+        .into();
+        let palette = Palette::new(vec![red, white])?;
+        let layer = Layer {
+            height: SOME_HEIGHT,
+            texture: Default::default(),
+        };
+
+        let meshes = palette_image_to_stl(&img, &palette, &[Some(layer), None], 1.)?;
+        assert_eq!(meshes.len(), 1);
+        let max_z = meshes[0]
+            .chunks(3)
+            .map(|v| v[2])
+            .fold(f64::MIN, f64::max);
+        assert_eq!(max_z, SOME_HEIGHT);
+        Ok(())
+    }
+
+    #[test]
+    fn test_extrude_with_profile_is_closed() -> Result<(), Box<dyn Error>> {
+        use crate::util::testing::{donut, is_watertight};
+
+        let height = 4.;
+        let tris = extrude_edges_with_profile(
+            donut(20., 8., 0., 0.),
+            height,
+            EdgeProfile::Fillet { radius: 2. },
+        )?;
+        assert!(is_watertight(&tris));
+        Ok(())
+    }
+
+    #[test]
+    fn test_thin_lines_do_not_fold_over() -> Result<(), Box<dyn Error>> {
+        use crate::util::testing::{crosses_itself, is_watertight};
+        use image::{GrayImage, Luma};
+
+        // a diagonal line 3 pixels wide, much narrower than the dome is high
+        let img: DynamicImage = GrayImage::from_fn(60, 60, |x, y| {
+            if x > 5 && x < 55 && (x as i32 - y as i32).abs() <= 1 {
+                Luma([0])
+            } else {
+                Luma([255])
+            }
+        })
+        .into();
+        let edges = image_to_edges(img, DEFAULT_THRESHOLD_VALUE, &StlOptions::default())?;
+        for profile in [EdgeProfile::Dome, EdgeProfile::Draft { angle: 30. }] {
+            let tris = extrude_edges_with_profile(edges.clone(), 10., profile)?;
+            assert!(is_watertight(&tris), "{:?}", profile);
+            assert!(!crosses_itself(&tris), "{:?}", profile);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_art_image_to_stl() -> Result<(), Box<dyn Error>> {
+        let img = ImageReader::open("./images/lines_24pt_swell_braille.png")?
+            .with_guessed_format()?
+            .decode()?;
+        let options = StlOptions {
+            profile: EdgeProfile::Dome,
+            line_width: Some(4.),
+            ..Default::default()
+        };
+        let tris =
+            image_to_stl_with_options(img, DEFAULT_THRESHOLD_VALUE, 2., SOME_SCALE_FACTOR, &options)?
+                .collect::<Result<Vec<_>, _>>()?
+                .concat();
+        assert!(!tris.is_empty());
+        assert_eq!(tris.len() % 9, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_negative_mold_image_to_stl() -> Result<(), Box<dyn Error>> {
+        let img = ImageReader::open("./images/stick-figure.png")?
+            .with_guessed_format()?
+            .decode()?;
+        let mold = MoldOptions {
+            kind: MoldKind::Negative,
+            ..Default::default()
+        };
+        let options = StlOptions {
+            mold: Some(mold),
+            ..Default::default()
+        };
+        let tris = image_to_stl_with_options(img, DEFAULT_THRESHOLD_VALUE, 2., 1., &options)?
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+        let top = tris.chunks(3).map(|v| v[2]).fold(f64::MIN, f64::max);
+        assert_eq!(top, mold.base_thickness + 2.);
+        Ok(())
+    }
+
+    #[test]
+    fn test_hollow_image_to_stl() -> Result<(), Box<dyn Error>> {
+        use image::{GrayImage, Luma};
+
+        let img: DynamicImage = GrayImage::from_fn(60, 60, |x, y| {
+            if (10..50).contains(&x) && (10..50).contains(&y) {
+                Luma([0])
+            } else {
+                Luma([255])
+            }
+        })
+        .into();
+        let hollow = HollowOptions {
+            wall_width: 3.,
+            fill: Some(Default::default()),
+            ..Default::default()
+        };
+        let options = StlOptions {
+            border: Some(BorderOptions {
+                inset: 2.,
+                frame_width: 2.,
+                marker_size: 0.,
+            }),
+            hollow: Some(hollow),
+            ..Default::default()
+        };
+        let tris = image_to_stl_with_options(img, DEFAULT_THRESHOLD_VALUE, 2., 1., &options)?
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+        assert_eq!(tris.len() % 9, 0);
+        // the area of the flat tops at a height, inside the frame
+        let top_area = |z: f64| -> f64 {
+            tris.chunks(9)
+                .filter(|t| t[2] == z && t[5] == z && t[8] == z)
+                .filter(|t| t.chunks(3).all(|v| v[0] > 5. && v[0] < 55.))
+                .map(|t| ((t[3] - t[0]) * (t[7] - t[1]) - (t[6] - t[0]) * (t[4] - t[1])).abs() / 2.)
+                .sum()
+        };
+        // a 40 by 40 square with walls 3 wide around a lower fill, the traced corners are cut
+        assert!((top_area(2.) - (1600. - 34. * 34.)).abs() < 1.);
+        assert!((top_area(2. * hollow.fill_height) - 34. * 34.).abs() < 1.);
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_to_edges_with_border() -> Result<(), Box<dyn Error>> {
+        let img = ImageReader::open("./images/stick-figure.png")?
+            .with_guessed_format()?
+            .decode()?;
+        let border = BorderOptions {
+            inset: 5.,
+            frame_width: 10.,
+            marker_size: 30.,
+        };
+        let options = StlOptions {
+            border: Some(border),
+            ..Default::default()
+        };
+        let plain = image_to_edges(img.clone(), DEFAULT_THRESHOLD_VALUE, &StlOptions::default())?;
+        let edges = image_to_edges(img, DEFAULT_THRESHOLD_VALUE, &options)?;
+        assert_eq!(edges.len(), plain.len() + 2);
+
+        // the figure ends up inside the hole of the frame
+        let nodes = sort_edges(edges)?;
+        assert_eq!(nodes[0].data.len(), 6, "the frame is the outermost shape");
+        assert_eq!(nodes.len(), sort_edges(plain)?.len() + 1);
+        Ok(())
+    }
+
     #[test]
     fn test_earcut_steps_3d() -> Result<(), Box<dyn Error>> {
         let input_filename = "./images/stick-figure.png";
@@ -231,6 +733,7 @@ mod tests {
         let scale_factor = 0.05;
         let output_filename = "test.stl";
 
-        image_file_to_stl(input_filename, height, scale_factor, output_filename)
+        image_file_to_stl(input_filename, height, scale_factor, output_filename)?;
+        Ok(())
     }
 }
@@ -16,7 +16,7 @@ pub fn read_file_to_value(filename: &str) -> Result<Value, Box<dyn Error>> {
 }
 
 pub mod testing {
-    use crate::edge::Edge;
+    use crate::edge::{orient2d, Edge};
     use crate::vert::Vert;
 
     use image::io::Reader as ImageReader;
@@ -48,4 +48,63 @@ pub mod testing {
             closed_edge(side, x, y, false),
         ]
     }
+
+    /// every edge of a closed mesh is shared by exactly two triangles
+    pub fn is_watertight(tris: &[f64]) -> bool {
+        use std::collections::HashMap;
+
+        let mut edge_count: HashMap<[u64; 6], usize> = HashMap::new();
+        for tri in tris.chunks(9) {
+            let corners: Vec<[u64; 3]> = tri
+                .chunks(3)
+                .map(|v| [v[0].to_bits(), v[1].to_bits(), v[2].to_bits()])
+                .collect();
+            for i in 0..3 {
+                let (a, b) = (corners[i], corners[(i + 1) % 3]);
+                let (a, b) = if a < b { (a, b) } else { (b, a) };
+                let key = [a[0], a[1], a[2], b[0], b[1], b[2]];
+                *edge_count.entry(key).or_default() += 1;
+            }
+        }
+        edge_count.values().all(|&count| count == 2)
+    }
+
+    /// Check if the walls of a mesh cross each other, by cutting it halfway between each two
+    /// heights of its corners and looking for lines of the cut that cross
+    pub fn crosses_itself(tris: &[f64]) -> bool {
+        let mut heights: Vec<f64> = tris.chunks(3).map(|v| v[2]).collect();
+        heights.sort_by(f64::total_cmp);
+        heights.dedup();
+        heights.windows(2).any(|w| {
+            let z = (w[0] + w[1]) / 2.;
+            let cut: Vec<(Vert, Vert)> = tris
+                .chunks(9)
+                .filter_map(|t| {
+                    let points: Vec<Vert> = (0..3)
+                        .filter_map(|i| {
+                            let (a, b) = (&t[i * 3..i * 3 + 3], &t[(i + 1) % 3 * 3..][..3]);
+                            // the same point for both triangles on an edge
+                            let (a, b) = if a[2] < b[2] { (a, b) } else { (b, a) };
+                            if a[2] < z && b[2] > z {
+                                let s = (z - a[2]) / (b[2] - a[2]);
+                                Some(Vert::new(
+                                    a[0] + s * (b[0] - a[0]),
+                                    a[1] + s * (b[1] - a[1]),
+                                ))
+                            } else {
+                                None
+                            }
+                        })
+                        .collect();
+                    (points.len() == 2).then(|| (points[0].clone(), points[1].clone()))
+                })
+                .collect();
+            cut.iter().enumerate().any(|(i, (a, b))| {
+                cut[i + 1..].iter().any(|(c, d)| {
+                    orient2d(a, b, c) * orient2d(a, b, d) < 0.
+                        && orient2d(c, d, a) * orient2d(c, d, b) < 0.
+                })
+            })
+        })
+    }
 }
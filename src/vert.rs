@@ -73,6 +73,18 @@ impl Vert {
         (self.x.powi(2) + self.y.powi(2)).sqrt()
     }
 
+    /// shortest distance from this point to the segment between a and b
+    pub fn distance_to_segment(&self, a: &Vert, b: &Vert) -> f64 {
+        let ab = b.diff(a);
+        let length_squared = ab.dot_product(&ab);
+        if length_squared == 0. {
+            return self.diff(a).magnitude();
+        }
+        let t = (self.diff(a).dot_product(&ab) / length_squared).clamp(0., 1.);
+        let closest = Vert::new(a.x + t * ab.x, a.y + t * ab.y);
+        self.diff(&closest).magnitude()
+    }
+
     pub fn to_bytes(&self) -> [u8; 8] {
         let xb = self.x.to_le_bytes();
         let yb = self.y.to_le_bytes();
@@ -139,73 +151,78 @@ fn get_xy_e(a: u8, b: u8, x: f64, y: f64, height: f64) -> Edge {
     Edge::from([get_xy_vert(a, x, y, height), get_xy_vert(b, x, y, height)])
 }
 
-pub fn get_quad_edge(
-    border_case: BorderCase,
-    quad: QuadCase,
-    x: f64,
-    y: f64,
-    height: f64,
-) -> Vec<Edge> {
+/// The pieces of outline in a cell, as pairs of the points numbered above.
+/// Ink is kept on the left going from the first point to the second, once y is flipped to go up.
+pub fn quad_segments(border_case: BorderCase, quad: QuadCase) -> Vec<(u8, u8)> {
     let mut out = match quad {
         (false, false, false, false) => vec![],
-        (false, false, false, true) => vec![get_xy_e(7, 3, x, y, height)],
-        (false, false, true, false) => vec![get_xy_e(5, 7, x, y, height)],
-        (false, false, true, true) => vec![get_xy_e(3, 5, x, y, height)],
-        (false, true, false, false) => vec![get_xy_e(1, 5, x, y, height)],
-        (false, true, false, true) => {
-            vec![get_xy_e(1, 5, x, y, height), get_xy_e(7, 3, x, y, height)]
-        }
-        (false, true, true, false) => vec![get_xy_e(1, 7, x, y, height)],
-        (false, true, true, true) => vec![get_xy_e(1, 3, x, y, height)],
-        (true, false, false, false) => vec![get_xy_e(1, 3, x, y, height)],
-        (true, false, false, true) => vec![get_xy_e(7, 1, x, y, height)],
-        (true, false, true, false) => {
-            vec![get_xy_e(3, 1, x, y, height), get_xy_e(5, 7, x, y, height)]
-        }
-        (true, false, true, true) => vec![get_xy_e(5, 1, x, y, height)],
-        (true, true, false, false) => vec![get_xy_e(3, 5, x, y, height)],
-        (true, true, false, true) => vec![get_xy_e(7, 5, x, y, height)],
-        (true, true, true, false) => vec![get_xy_e(3, 7, x, y, height)],
+        (false, false, false, true) => vec![(7, 3)],
+        (false, false, true, false) => vec![(5, 7)],
+        (false, false, true, true) => vec![(5, 3)],
+        (false, true, false, false) => vec![(1, 5)],
+        (false, true, false, true) => vec![(1, 5), (7, 3)],
+        (false, true, true, false) => vec![(1, 7)],
+        (false, true, true, true) => vec![(1, 3)],
+        (true, false, false, false) => vec![(3, 1)],
+        (true, false, false, true) => vec![(7, 1)],
+        (true, false, true, false) => vec![(3, 1), (5, 7)],
+        (true, false, true, true) => vec![(5, 1)],
+        (true, true, false, false) => vec![(3, 5)],
+        (true, true, false, true) => vec![(7, 5)],
+        (true, true, true, false) => vec![(3, 7)],
         (true, true, true, true) => vec![],
     };
 
     if border_case.0 {
         if quad.0 {
-            out.push(get_xy_e(1, 0, x, y, height))
+            out.push((1, 0))
         };
         if quad.1 {
-            out.push(get_xy_e(2, 1, x, y, height))
+            out.push((2, 1))
         };
     }
     if border_case.1 {
         if quad.1 {
-            out.push(get_xy_e(5, 2, x, y, height))
+            out.push((5, 2))
         };
         if quad.2 {
-            out.push(get_xy_e(8, 5, x, y, height))
+            out.push((8, 5))
         };
     }
     if border_case.2 {
         if quad.2 {
-            out.push(get_xy_e(7, 8, x, y, height))
+            out.push((7, 8))
         };
         if quad.3 {
-            out.push(get_xy_e(6, 7, x, y, height))
+            out.push((6, 7))
         };
     }
 
     if border_case.3 {
         if quad.3 {
-            out.push(get_xy_e(3, 6, x, y, height))
+            out.push((3, 6))
         };
         if quad.0 {
-            out.push(get_xy_e(0, 3, x, y, height))
+            out.push((0, 3))
         };
     }
 
     out
 }
 
+pub fn get_quad_edge(
+    border_case: BorderCase,
+    quad: QuadCase,
+    x: f64,
+    y: f64,
+    height: f64,
+) -> Vec<Edge> {
+    quad_segments(border_case, quad)
+        .into_iter()
+        .map(|(a, b)| get_xy_e(a, b, x, y, height))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -220,6 +237,15 @@ mod tests {
         Ok(())
     }
     
+    #[test]
+    fn distance_to_segment() {
+        let a = Vert::new(0., 0.);
+        let b = Vert::new(10., 0.);
+        assert_eq!(Vert::new(5., 3.).distance_to_segment(&a, &b), 3.);
+        assert_eq!(Vert::new(13., 4.).distance_to_segment(&a, &b), 5.);
+        assert_eq!(Vert::new(3., 4.).distance_to_segment(&a, &a), 5.);
+    }
+
     #[test]
     fn angle_between_counter_cw() {
         let a = Vert::new(1., 0.);